@@ -0,0 +1,24 @@
+// regenerates include/lox_byte.h from src/capi.rs when the capi feature is on
+fn main() {
+    #[cfg(feature = "capi")]
+    {
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            include_guard: Some("LOX_BYTE_H".into()),
+            autogen_warning: Some(
+                "/* Generated by build.rs with cbindgen, do not edit by hand. */".into(),
+            ),
+            documentation: true,
+            ..Default::default()
+        };
+        // only capi.rs is parsed so nothing else leaks into the header
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/capi.rs", crate_dir))
+            .generate()
+            .expect("Failed to generate the C header")
+            .write_to_file(format!("{}/include/lox_byte.h", crate_dir));
+    }
+}
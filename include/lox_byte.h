@@ -0,0 +1,67 @@
+#ifndef LOX_BYTE_H
+#define LOX_BYTE_H
+
+/* Generated by build.rs with cbindgen, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define LOX_OK 0
+
+#define LOX_COMPILE_ERROR 65
+
+#define LOX_RUNTIME_ERROR 70
+
+#define LOX_INVALID_ARGUMENT 64
+
+/**
+ * Opaque interpreter handle, created by `lox_vm_new`.
+ */
+typedef struct LoxVm LoxVm;
+
+/**
+ * Creates an interpreter. Its output is captured rather than printed and
+ * can be read with `lox_vm_output` / `lox_vm_error` after each run.
+ */
+struct LoxVm *lox_vm_new(void);
+
+/**
+ * Compiles and runs a NUL terminated UTF-8 source string. Globals persist
+ * between calls. Returns `LOX_OK`, `LOX_COMPILE_ERROR`, `LOX_RUNTIME_ERROR`
+ * or `LOX_INVALID_ARGUMENT`.
+ *
+ * # Safety
+ * `vm` must come from `lox_vm_new` and not have been freed, `source` must
+ * point to a NUL terminated string.
+ */
+int lox_vm_interpret(struct LoxVm *vm, const char *source);
+
+/**
+ * Everything the last run printed. Owned by the vm and valid until the
+ * next `lox_vm_interpret` or `lox_vm_free`.
+ *
+ * # Safety
+ * `vm` must come from `lox_vm_new` and not have been freed.
+ */
+const char *lox_vm_output(const struct LoxVm *vm);
+
+/**
+ * Compile and runtime error messages from the last run, with the same
+ * lifetime as `lox_vm_output`.
+ *
+ * # Safety
+ * `vm` must come from `lox_vm_new` and not have been freed.
+ */
+const char *lox_vm_error(const struct LoxVm *vm);
+
+/**
+ * Frees an interpreter, NULL is ignored.
+ *
+ * # Safety
+ * `vm` must come from `lox_vm_new` and must not be used afterwards.
+ */
+void lox_vm_free(struct LoxVm *vm);
+
+#endif  /* LOX_BYTE_H */
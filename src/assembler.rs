@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use crate::{
+    chunks::Chunk,
+    error::AssembleErrors,
+    opcode::{OpCode, Operand},
+    value::{create_string, Value},
+    vm::VM,
+};
+
+// Assembles the textual form printed by the disassembler back into a chunk.
+//
+//     ; comments run to the end of the line
+//     .line 3              set the source line of what follows
+//     loop:                a label, usable as a jump target
+//     Op_Constant "hi"     constants are numbers, "strings", true, false, nil
+//     Op_GetLocal 0
+//     Op_JumpIfFalse end
+//     Op_Loop loop
+//
+// Lines copied straight out of `lox_byte disasm` also work: the leading offset
+// and line columns are understood, `Op_Constant 1    hi` takes the value after
+// the constant index and `Op_Jump 3 -> 12` jumps to offset 12.
+pub fn assemble(vm: &mut VM, source: &str) -> Result<Chunk, AssembleErrors> {
+    let mut chunk = Chunk::default();
+    let mut labels = HashMap::<&str, usize>::new();
+    // (operand position, source line, target label or offset, direction)
+    let mut fixups = Vec::<(usize, usize, &str, isize)>::new();
+    let mut line = 1;
+
+    for (number, text) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut rest = strip_comment(text).trim();
+        if rest.is_empty() {
+            continue;
+        }
+
+        if let Some(directive) = rest.strip_prefix(".line") {
+            line = directive
+                .trim()
+                .parse()
+                .map_err(|_| AssembleErrors::InvalidOperand(number, directive.trim().into()))?;
+            continue;
+        }
+
+        if let Some(label) = rest.strip_suffix(':') {
+            if labels.insert(label.trim(), chunk.code.len()).is_some() {
+                return Err(AssembleErrors::DuplicateLabel(number, label.trim().into()));
+            }
+            continue;
+        }
+
+        // disassembler columns: offset, then line or '|'
+        if let Some((offset, tail)) = split_word(rest) {
+            if offset.chars().all(|c| c.is_ascii_digit()) {
+                rest = tail;
+                if let Some((column, tail)) = split_word(rest) {
+                    if column == "|" {
+                        rest = tail;
+                    } else if let Ok(l) = column.parse() {
+                        line = l;
+                        rest = tail;
+                    }
+                }
+            }
+        }
+
+        let (name, operand) = split_word(rest).unwrap_or((rest, ""));
+        let op = opcode(name).ok_or_else(|| AssembleErrors::UnknownOpcode(number, name.into()))?;
+        let kind = op.operand();
+        chunk.write(op.into(), line);
+
+        match kind {
+            Operand::None => {
+                if !operand.is_empty() {
+                    return Err(AssembleErrors::InvalidOperand(number, operand.into()));
+                }
+            }
+            Operand::Byte => {
+                let byte = operand
+                    .parse::<u8>()
+                    .map_err(|_| AssembleErrors::InvalidOperand(number, operand.into()))?;
+                chunk.write(byte, line);
+            }
+            Operand::Constant => {
+                if operand.is_empty() {
+                    return Err(AssembleErrors::MissingOperand(number));
+                }
+                let value = literal(vm, constant_text(operand));
+                let index = chunk
+                    .add(value)
+                    .map_err(|_| AssembleErrors::TooManyConstants(number))?;
+                chunk.write(index, line);
+            }
+            Operand::Jump(sign) => {
+                let target = match operand.split_once("->") {
+                    Some((_, target)) => target.trim(),
+                    None => operand,
+                };
+                if target.is_empty() {
+                    return Err(AssembleErrors::MissingOperand(number));
+                }
+                fixups.push((chunk.code.len(), number, target, sign));
+                chunk.write(0xff, line);
+                chunk.write(0xff, line);
+            }
+        }
+    }
+
+    for (position, number, target, sign) in fixups {
+        let destination = match labels.get(target) {
+            Some(offset) => *offset,
+            None => target
+                .parse::<usize>()
+                .map_err(|_| AssembleErrors::UnknownLabel(number, target.into()))?,
+        };
+        let after = position + 2;
+        let jump = if sign > 0 {
+            destination.checked_sub(after)
+        } else {
+            after.checked_sub(destination)
+        };
+        match jump {
+            Some(jump) if jump <= u16::MAX as usize => {
+                chunk.code[position] = ((jump >> 8) & 0xff) as u8;
+                chunk.code[position + 1] = (jump & 0xff) as u8;
+            }
+            _ => return Err(AssembleErrors::InvalidJump(number, target.into())),
+        }
+    }
+
+    Ok(chunk)
+}
+
+fn opcode(name: &str) -> Option<OpCode> {
+    let name = name.strip_prefix("Op_")?;
+    OpCode::ALL.iter().copied().find(|op| op.name() == name)
+}
+
+fn split_word(text: &str) -> Option<(&str, &str)> {
+    text.split_once(char::is_whitespace)
+        .map(|(word, rest)| (word, rest.trim_start()))
+}
+
+// `1    hi` as printed by the disassembler is the index followed by the value
+fn constant_text(operand: &str) -> &str {
+    match split_word(operand) {
+        Some((index, value)) if index.parse::<u8>().is_ok() && !value.is_empty() => value,
+        _ => operand,
+    }
+}
+
+// quoted strings are unescaped the way the disassembler escapes them,
+// bare words that are not a number or keyword are strings too
+fn literal(vm: &mut VM, text: &str) -> Value {
+    if let Some(quoted) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return create_string(vm, &unescape(quoted)).into();
+    }
+    match text {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "nil" => Value::Nil,
+        _ => match text.parse::<f64>() {
+            Ok(number) => Value::Number(number),
+            Err(_) => create_string(vm, text).into(),
+        },
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            out.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
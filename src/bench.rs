@@ -0,0 +1,73 @@
+use std::{fmt, time::Duration};
+
+use crate::{clock::Clock, error::VmErrors, vm::VM};
+
+// one run of a script, compiling included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub elapsed: Duration,
+    pub instructions: u64,
+}
+
+// compiles and runs `source` on `vm`, counting the instructions executed
+// with fuel that can't run out, which costs less than a profile
+pub fn sample(vm: &mut VM, source: &str) -> Result<Sample, VmErrors> {
+    let fuel = vm.config.fuel.replace(u64::MAX);
+    let clock = Clock::now();
+    let result = vm.interpret(source);
+    let elapsed = clock.elapsed();
+    let left = vm.config.fuel.take().unwrap_or_default();
+    vm.config.fuel = fuel;
+    result.map(|()| Sample {
+        elapsed,
+        instructions: u64::MAX - left,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub runs: usize,
+    pub mean: Duration,
+    pub median: Duration,
+    pub stddev: Duration,
+    // per run, these only differ when runs share a vm
+    pub instructions: u64,
+}
+
+impl Summary {
+    pub fn of(samples: &[Sample]) -> Self {
+        let runs = samples.len();
+        let mut times: Vec<f64> = samples.iter().map(|s| s.elapsed.as_secs_f64()).collect();
+        times.sort_by(f64::total_cmp);
+        let mean = times.iter().sum::<f64>() / runs.max(1) as f64;
+        let median = match runs {
+            0 => 0.0,
+            _ if runs.is_multiple_of(2) => (times[runs / 2 - 1] + times[runs / 2]) / 2.0,
+            _ => times[runs / 2],
+        };
+        let variance = times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / runs.max(1) as f64;
+        let instructions = samples.iter().map(|s| s.instructions).sum::<u64>();
+        Self {
+            runs,
+            mean: Duration::from_secs_f64(mean),
+            median: Duration::from_secs_f64(median),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+            instructions: instructions / runs.max(1) as u64,
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{} runs, mean {:.3}ms, median {:.3}ms, stddev {:.3}ms, {} instructions",
+            self.runs,
+            ms(self.mean),
+            ms(self.median),
+            ms(self.stddev),
+            self.instructions
+        )
+    }
+}
@@ -0,0 +1,14 @@
+// a language server for lox over stdio, for editors that speak lsp:
+// diagnostics as you type, go to definition, hover and document symbols
+use std::{io, process};
+
+fn main() {
+    let stdin = io::stdin();
+    match lox_byte::lsp::serve(stdin.lock(), io::stdout().lock()) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("lox-lsp: {}", e);
+            process::exit(74)
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    error::{RuntimeErrors, VmErrors},
+    heap::Heap,
+    native::NativeResult,
+    value::{create_string, LoxValue, Value},
+    vm::VM,
+};
+
+// a native argument converted out of a Value, reading objects out of
+// `heap`. strings arrive owned since the vm can collect them once the call
+// returns
+pub trait FromLox: Sized {
+    fn from_lox(value: &Value, heap: &Heap) -> Result<Self, RuntimeErrors>;
+}
+
+// a native's return value converted into a Value
+pub trait IntoLox {
+    fn into_lox(self, vm: &mut VM) -> NativeResult;
+}
+
+pub fn type_error(expected: &'static str, value: &Value, heap: &Heap) -> RuntimeErrors {
+    RuntimeErrors::TypeError(expected, value.display(heap).to_string())
+}
+
+impl FromLox for Value {
+    fn from_lox(value: &Value, _heap: &Heap) -> Result<Self, RuntimeErrors> {
+        Ok(*value)
+    }
+}
+
+impl FromLox for LoxValue {
+    fn from_lox(value: &Value, heap: &Heap) -> Result<Self, RuntimeErrors> {
+        Ok(LoxValue::from_value(value, heap))
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: &Value, heap: &Heap) -> Result<Self, RuntimeErrors> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            _ => Err(type_error("number", value, heap)),
+        }
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: &Value, heap: &Heap) -> Result<Self, RuntimeErrors> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(type_error("bool", value, heap)),
+        }
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: &Value, heap: &Heap) -> Result<Self, RuntimeErrors> {
+        match value {
+            Value::String(s) => Ok(heap.get(*s).clone()),
+            _ => Err(type_error("string", value, heap)),
+        }
+    }
+}
+
+// nil becomes None
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: &Value, heap: &Heap) -> Result<Self, RuntimeErrors> {
+        match value {
+            Value::Nil => Ok(None),
+            _ => T::from_lox(value, heap).map(Some),
+        }
+    }
+}
+
+impl IntoLox for Value {
+    fn into_lox(self, _vm: &mut VM) -> NativeResult {
+        Ok(self)
+    }
+}
+
+impl IntoLox for LoxValue {
+    fn into_lox(self, vm: &mut VM) -> NativeResult {
+        Ok(self.into_value(vm))
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self, _vm: &mut VM) -> NativeResult {
+        Ok(Value::Nil)
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self, _vm: &mut VM) -> NativeResult {
+        Ok(self.into())
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self, _vm: &mut VM) -> NativeResult {
+        Ok(self.into())
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self, vm: &mut VM) -> NativeResult {
+        Ok(create_string(vm, &self).into())
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self, vm: &mut VM) -> NativeResult {
+        Ok(create_string(vm, self).into())
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self, vm: &mut VM) -> NativeResult {
+        match self {
+            Some(value) => value.into_lox(vm),
+            None => Ok(Value::Nil),
+        }
+    }
+}
+
+// an Err is raised as a runtime error in the calling script
+impl<T: IntoLox> IntoLox for Result<T, String> {
+    fn into_lox(self, vm: &mut VM) -> NativeResult {
+        match self {
+            Ok(value) => value.into_lox(vm),
+            Err(msg) => Err(VmErrors::RuntimeError(RuntimeErrors::HostError(msg))),
+        }
+    }
+}
+
+// rust closures callable from lox, `Args` is the tuple of parameter types
+pub trait HostFn<Args>: Send + Sync + 'static {
+    fn arity(&self) -> usize;
+    fn call(&self, vm: &mut VM, args: &[Value]) -> NativeResult;
+}
+
+macro_rules! host_fn {
+    ($count: expr; $($arg: ident),*) => {
+        impl<F, R, $($arg),*> HostFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoLox,
+            $($arg: FromLox,)*
+        {
+            fn arity(&self) -> usize {
+                $count
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call(&self, vm: &mut VM, args: &[Value]) -> NativeResult {
+                // the vm has already checked the argument count
+                let mut args = args.iter();
+                $(let $arg = $arg::from_lox(args.next().unwrap(), &vm.heap)
+                    .map_err(VmErrors::RuntimeError)?;)*
+                (self)($($arg),*).into_lox(vm)
+            }
+        }
+    };
+}
+
+host_fn!(0;);
+host_fn!(1; A);
+host_fn!(2; A, B);
+host_fn!(3; A, B, C);
+host_fn!(4; A, B, C, D);
+host_fn!(5; A, B, C, D, E);
+host_fn!(6; A, B, C, D, E, G);
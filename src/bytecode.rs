@@ -0,0 +1,200 @@
+use crate::{
+    chunks::Chunk,
+    error::BytecodeErrors,
+    heap::Heap,
+    opcode::{OpCode, Operand},
+    value::{create_string, Value},
+    vm::VM,
+};
+
+// file layout, all integers little endian:
+//   magic "LOXBC", version u8
+//   code      u32 length, bytes
+//   constants u32 count, each a tag u8 followed by its payload
+//   lines     u32 count, each (offset u32, line u32)
+//   spans     u32 count, each (offset u32, start u32, length u32), empty
+//             unless compiled with debug info
+// a stripped chunk has both tables empty
+pub const MAGIC: &[u8; 5] = b"LOXBC";
+// bumped whenever opcode numbering changes
+pub const VERSION: u8 = 5;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_BOOL: u8 = 2;
+const TAG_NIL: u8 = 3;
+
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn serialize(chunk: &Chunk, heap: &Heap) -> Result<Vec<u8>, BytecodeErrors> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+
+    write_u32(&mut out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    write_u32(&mut out, chunk.constants.len());
+    for constant in chunk.constants.iter() {
+        match constant {
+            Value::Number(n) => {
+                out.push(TAG_NUMBER);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::String(str) => {
+                let str = heap.get(*str);
+                out.push(TAG_STRING);
+                write_u32(&mut out, str.len());
+                out.extend_from_slice(str.as_bytes());
+            }
+            Value::Bool(b) => {
+                out.push(TAG_BOOL);
+                out.push(*b as u8);
+            }
+            Value::Nil => out.push(TAG_NIL),
+            Value::Native(_) | Value::Foreign(_) | Value::Module(_) => {
+                return Err(BytecodeErrors::UnserializableConstant(
+                    constant.display(heap).to_string(),
+                ))
+            }
+        }
+    }
+
+    write_u32(&mut out, chunk.lines.len());
+    for &(offset, line) in chunk.lines.iter() {
+        write_u32(&mut out, offset);
+        write_u32(&mut out, line);
+    }
+
+    write_u32(&mut out, chunk.spans.len());
+    for &(offset, start, len) in chunk.spans.iter() {
+        write_u32(&mut out, offset);
+        write_u32(&mut out, start);
+        write_u32(&mut out, len);
+    }
+    Ok(out)
+}
+
+// string constants are interned into `vm` as they are read
+pub fn deserialize(vm: &mut VM, bytes: &[u8]) -> Result<Chunk, BytecodeErrors> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(BytecodeErrors::BadMagic);
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(BytecodeErrors::UnsupportedVersion(version));
+    }
+
+    let mut chunk = Chunk::default();
+    let len = reader.u32()?;
+    chunk.code = reader.take(len)?.to_vec();
+
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let constant = match reader.u8()? {
+            TAG_NUMBER => {
+                let bytes: [u8; 8] = reader.take(8)?.try_into().unwrap();
+                Value::Number(f64::from_le_bytes(bytes))
+            }
+            TAG_STRING => {
+                let len = reader.u32()?;
+                let str = std::str::from_utf8(reader.take(len)?)
+                    .map_err(|_| BytecodeErrors::InvalidUtf8)?;
+                create_string(vm, str).into()
+            }
+            TAG_BOOL => Value::Bool(reader.u8()? != 0),
+            TAG_NIL => Value::Nil,
+            tag => return Err(BytecodeErrors::InvalidConstantTag(tag)),
+        };
+        chunk.constants.push(constant);
+    }
+
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let offset = reader.u32()?;
+        let line = reader.u32()?;
+        chunk.lines.push((offset, line));
+    }
+
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let offset = reader.u32()?;
+        let start = reader.u32()?;
+        let len = reader.u32()?;
+        chunk.spans.push((offset, start, len));
+    }
+    validate(&chunk.code, chunk.constants.len())?;
+    Ok(chunk)
+}
+
+// every instruction must decode and carry all its operands, each constant
+// index must be in the table and each jump must land on an instruction or
+// the end, so a damaged file is refused on load instead of failing wherever
+// the vm reaches it
+fn validate(code: &[u8], constants: usize) -> Result<(), BytecodeErrors> {
+    let mut starts = vec![false; code.len() + 1];
+    let mut offset = 0;
+    while offset < code.len() {
+        let op = OpCode::try_from(code[offset])
+            .map_err(|_| BytecodeErrors::InvalidOpcode(offset, code[offset]))?;
+        starts[offset] = true;
+        offset += 1 + op.operand().size();
+    }
+    if offset != code.len() {
+        return Err(BytecodeErrors::Truncated);
+    }
+    starts[offset] = true;
+    let mut offset = 0;
+    while offset < code.len() {
+        let op = OpCode::try_from(code[offset]).unwrap();
+        let next = offset + 1 + op.operand().size();
+        match op.operand() {
+            Operand::Constant if code[offset + 1] as usize >= constants => {
+                return Err(BytecodeErrors::InvalidConstant(offset, code[offset + 1]));
+            }
+            Operand::Jump(direction) => {
+                let jump = u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as isize;
+                let target = next as isize + direction * jump;
+                if target < 0 || !starts.get(target as usize).copied().unwrap_or(false) {
+                    return Err(BytecodeErrors::InvalidJump(offset, target));
+                }
+            }
+            _ => {}
+        }
+        offset = next;
+    }
+    Ok(())
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeErrors> {
+        let end = self.offset + len;
+        if end > self.bytes.len() {
+            return Err(BytecodeErrors::Truncated);
+        }
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeErrors> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, BytecodeErrors> {
+        let bytes: [u8; 4] = self.take(4)?.try_into().unwrap();
+        Ok(u32::from_le_bytes(bytes) as usize)
+    }
+}
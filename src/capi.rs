@@ -0,0 +1,119 @@
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    ptr,
+};
+
+use crate::{
+    error::VmErrors,
+    output::{CaptureBuffer, Sink},
+    report_compile_errors, report_runtime_error,
+    vm::VM,
+};
+
+pub const LOX_OK: c_int = 0;
+pub const LOX_COMPILE_ERROR: c_int = 65;
+pub const LOX_RUNTIME_ERROR: c_int = 70;
+pub const LOX_INVALID_ARGUMENT: c_int = 64;
+
+/// Opaque interpreter handle, created by `lox_vm_new`.
+pub struct LoxVm {
+    vm: VM,
+    out: CaptureBuffer,
+    diagnostics: CaptureBuffer,
+    // kept alive until the next interpret so the pointers handed out stay valid
+    output: CString,
+    error: CString,
+}
+
+/// Creates an interpreter. Its output is captured rather than printed and
+/// can be read with `lox_vm_output` / `lox_vm_error` after each run.
+#[no_mangle]
+pub extern "C" fn lox_vm_new() -> *mut LoxVm {
+    let mut vm = VM::new();
+    let (out_sink, out) = Sink::capture();
+    let (diagnostics_sink, diagnostics) = Sink::capture();
+    vm.out = out_sink;
+    vm.diagnostics = diagnostics_sink;
+    Box::into_raw(Box::new(LoxVm {
+        vm,
+        out,
+        diagnostics,
+        output: CString::default(),
+        error: CString::default(),
+    }))
+}
+
+/// Compiles and runs a NUL terminated UTF-8 source string. Globals persist
+/// between calls. Returns `LOX_OK`, `LOX_COMPILE_ERROR`, `LOX_RUNTIME_ERROR`
+/// or `LOX_INVALID_ARGUMENT`.
+///
+/// # Safety
+/// `vm` must come from `lox_vm_new` and not have been freed, `source` must
+/// point to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lox_vm_interpret(vm: *mut LoxVm, source: *const c_char) -> c_int {
+    let (Some(lox), false) = (vm.as_mut(), source.is_null()) else {
+        return LOX_INVALID_ARGUMENT;
+    };
+    lox.out.clear();
+    lox.diagnostics.clear();
+    let status = match CStr::from_ptr(source).to_str() {
+        Err(_) => LOX_INVALID_ARGUMENT,
+        Ok(source) => match lox.vm.interpret(source) {
+            Ok(()) => LOX_OK,
+            Err(VmErrors::CompileError(errors)) => {
+                report_compile_errors(&mut lox.vm, &errors);
+                LOX_COMPILE_ERROR
+            }
+            Err(VmErrors::RuntimeError(e)) => {
+                report_runtime_error(&mut lox.vm, &e);
+                LOX_RUNTIME_ERROR
+            }
+        },
+    };
+    lox.output = to_c_string(lox.out.contents());
+    lox.error = to_c_string(lox.diagnostics.contents());
+    status
+}
+
+/// Everything the last run printed. Owned by the vm and valid until the
+/// next `lox_vm_interpret` or `lox_vm_free`.
+///
+/// # Safety
+/// `vm` must come from `lox_vm_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lox_vm_output(vm: *const LoxVm) -> *const c_char {
+    match vm.as_ref() {
+        Some(lox) => lox.output.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Compile and runtime error messages from the last run, with the same
+/// lifetime as `lox_vm_output`.
+///
+/// # Safety
+/// `vm` must come from `lox_vm_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lox_vm_error(vm: *const LoxVm) -> *const c_char {
+    match vm.as_ref() {
+        Some(lox) => lox.error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Frees an interpreter, NULL is ignored.
+///
+/// # Safety
+/// `vm` must come from `lox_vm_new` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn lox_vm_free(vm: *mut LoxVm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+// interior NULs would cut the string short in C, so they are dropped
+fn to_c_string(text: String) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
@@ -1,14 +1,24 @@
-use std::borrow::Borrow;
+use std::{borrow::Borrow, ops::Range};
 
-use crate::{error::CompileErrors, value::Value};
+use crate::{
+    error::CompileErrors,
+    heap::Heap,
+    opcode::OpCode,
+    value::{create_string, Value},
+    vm::VM,
+};
 
 type OffsetWLine = (usize, usize);
+// (offset, start byte, length) of the source an instruction came from
+type OffsetWSpan = (usize, usize, usize);
 
 #[derive(Debug, Default, Clone)]
 pub struct Chunk {
     pub code: Vec<u8>,
     pub constants: Vec<Value>,
     pub lines: Vec<OffsetWLine>,
+    // only filled when compiled with debug info, run-length like `lines`
+    pub spans: Vec<OffsetWSpan>,
 }
 
 impl Chunk {
@@ -20,6 +30,65 @@ impl Chunk {
         }
     }
 
+    // records the span of the byte just written, if it differs from the last
+    pub fn write_span(&mut self, span: Range<usize>) {
+        match self.spans.last() {
+            Some(&(_, start, len)) if start == span.start && len == span.len() => {}
+            _ => self
+                .spans
+                .push((self.code.len() - 1, span.start, span.len())),
+        }
+    }
+
+    pub fn span_at(&self, offset: usize) -> Option<Range<usize>> {
+        self.spans
+            .iter()
+            .take_while(|(off, _, _)| *off <= offset)
+            .last()
+            .map(|&(_, start, len)| start..start + len)
+    }
+
+    // re-interns the string constants from `from`, the heap of the vm that
+    // built or loaded the chunk, into `vm`. Its strings then live as long as
+    // `vm` does and share their handle with equal strings there
+    pub fn bind(&mut self, from: &Heap, vm: &mut VM) {
+        for constant in self.constants.iter_mut() {
+            if let Value::String(string) = *constant {
+                if let Some(string) = from.try_get(string) {
+                    *constant = create_string(vm, string).into();
+                }
+            }
+        }
+    }
+
+    // drops the line and span tables, for shipping compiled scripts. The vm
+    // still runs the chunk, errors just can't say where they happened
+    pub fn strip_debug_info(&mut self) {
+        self.lines.clear();
+        self.spans.clear();
+    }
+
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        self.lines
+            .iter()
+            .take_while(|(off, _)| *off <= offset)
+            .last()
+            .map(|(_, line)| *line)
+    }
+
+    // offset of the first instruction compiled from `line`
+    pub fn instruction_at_line(&self, line: usize) -> Option<usize> {
+        let mut offset = 0;
+        while offset < self.code.len() {
+            if self.line_at(offset) == Some(line) {
+                return Some(offset);
+            }
+            let op = OpCode::try_from(self.code[offset]).ok()?;
+            offset += 1 + op.operand().size();
+        }
+        None
+    }
+
     pub fn add(&mut self, val: Value) -> Result<u8, CompileErrors> {
         if self.constants.len() > (u8::MAX as usize) {
             Err(CompileErrors::TooManyConstants)
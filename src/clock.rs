@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+// std's Instant and SystemTime panic on wasm32-unknown-unknown, there the
+// browser's performance.now() and Date.now() are used instead
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+}
+
+impl Clock {
+    pub fn now() -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = std::time::Instant::now();
+        #[cfg(target_arch = "wasm32")]
+        let start = js::performance_now();
+        Self { start }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        let elapsed = self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        let elapsed =
+            Duration::from_secs_f64((js::performance_now() - self.start).max(0.0) / 1000.0);
+        elapsed
+    }
+}
+
+// milliseconds since the unix epoch
+pub fn unix_millis() -> f64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as f64;
+    #[cfg(target_arch = "wasm32")]
+    let millis = js::date_now();
+    millis
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+mod js {
+    use wasm_bindgen::prelude::wasm_bindgen;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = performance, js_name = now)]
+        pub fn performance_now() -> f64;
+        #[wasm_bindgen(js_namespace = Date, js_name = now)]
+        pub fn date_now() -> f64;
+    }
+}
+
+// without the bindings there is no clock to read
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+mod js {
+    pub fn performance_now() -> f64 {
+        0.0
+    }
+
+    pub fn date_now() -> f64 {
+        0.0
+    }
+}
@@ -1,9 +1,14 @@
+use std::{fmt, mem, ops::Range};
+
 use crate::{
     chunks::Chunk,
     disassembler::Disassembler,
-    error::CompileErrors,
+    error::{CompileErrors, Diagnostic, Span},
+    module::default_binding,
     opcode::OpCode,
     parser::{get_rule, Local, Parser, Precedence},
+    scanner::Scanner,
+    symbols::{Binding, Symbols},
     token::{TType, Token},
     value::{create_string, Value},
     vm::VM,
@@ -16,6 +21,39 @@ pub struct Compiler<'src, 'vm> {
     pub locals: Vec<Local<'src>>,
     pub scope_depth: usize,
     pub compiling_chunk: Chunk,
+    pub trailing: Trailing,
+    // fed input that does not end in a complete declaration yet, and the
+    // line it starts on
+    pending: String,
+    pending_line: usize,
+    // where in `pending` invalid UTF-8 was replaced, see feed_decoded
+    pending_invalid: Vec<usize>,
+    // byte offset of this compiler's source within the whole input, spans
+    // of a fed segment are relative to the segment until this is added
+    span_base: usize,
+    // expressions and statements being parsed inside one another, bounded
+    // by `config.max_nesting` so recursion can't overflow the stack
+    nesting: usize,
+    // values on the stack where the code being compiled runs: initialized
+    // locals and operands waiting under the one being compiled. A local
+    // declared there takes the next slot
+    pub stack_height: usize,
+    // the slot of the innermost `do` block's value and the nesting of its
+    // statements, whose final expression may leave out the ';' to set it
+    block_value: Option<(usize, u8)>,
+    // where names are declared and used, recorded only when set
+    pub symbols: Option<Symbols>,
+}
+
+// what happens to an expression statement that ends the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trailing {
+    // an ordinary statement, ';' required and the value popped
+    Statement,
+    // left on the stack for the caller to echo when the ';' is left off
+    Echo,
+    // left on the stack for the caller, ';' optional
+    Keep,
 }
 
 // macro_rules! matcher {
@@ -26,6 +64,163 @@ pub struct Compiler<'src, 'vm> {
 //     };
 // }
 
+// incremental compilation, for sources that arrive in pieces: every complete
+// top level declaration is compiled as soon as it has been fed, so only the
+// unfinished tail is held in memory
+//
+//     let mut cc = Compiler::incremental(&mut vm);
+//     cc.feed("var a = ");
+//     cc.feed("1; print a;");
+//     cc.finish()?;
+impl<'vm> Compiler<'static, 'vm> {
+    pub fn incremental(vm: &'vm mut VM) -> Self {
+        Compiler::new("", vm)
+    }
+
+    pub fn feed(&mut self, input: &str) {
+        self.feed_decoded(input, &[]);
+    }
+
+    // like feed, for input from scanner::decode_lossy with the offsets of
+    // its replaced bytes
+    pub fn feed_decoded(&mut self, input: &str, invalid_utf8: &[usize]) {
+        let base = self.pending.len();
+        self.pending_invalid
+            .extend(invalid_utf8.iter().map(|offset| base + offset));
+        self.pending.push_str(input);
+        let end = complete_prefix(&self.pending);
+        if end > 0 {
+            let rest = self.pending.split_off(end);
+            let ready = mem::replace(&mut self.pending, rest);
+            let split = self.pending_invalid.partition_point(|&offset| offset < end);
+            let rest: Vec<usize> = self.pending_invalid[split..]
+                .iter()
+                .map(|offset| offset - end)
+                .collect();
+            self.pending_invalid.truncate(split);
+            let invalid = mem::replace(&mut self.pending_invalid, rest);
+            self.compile_segment(&ready, invalid, false);
+        }
+    }
+
+    // compiles whatever is left and hands the chunk to the vm
+    pub fn finish(mut self) -> Result<(), Vec<Diagnostic>> {
+        let rest = mem::take(&mut self.pending);
+        let invalid = mem::take(&mut self.pending_invalid);
+        self.compile_segment(&rest, invalid, true);
+        // never held whole, runtime errors read the script file instead
+        self.vm.source = None;
+        if self.parser.had_error {
+            return Err(mem::take(&mut self.parser.errors));
+        }
+        self.vm.chunks = mem::take(&mut self.compiling_chunk);
+        Ok(())
+    }
+
+    // segments only ever end at depth 0, where there are no locals to carry
+    // over, so each one gets a fresh compiler writing into the shared chunk
+    fn compile_segment(&mut self, segment: &str, invalid_utf8: Vec<usize>, last: bool) {
+        let mut cc = Compiler::new(segment, self.vm);
+        cc.parser.scanner = Scanner::new(segment)
+            .starting_at_line(self.pending_line)
+            .invalid_utf8_at(invalid_utf8);
+        cc.compiling_chunk = mem::take(&mut self.compiling_chunk);
+        cc.span_base = self.span_base;
+        cc.parser.set_panic(self.parser.get_panic());
+        cc.parser.advance();
+        while !cc.parser.match_token(TType::Eof) {
+            cc.declaraction()
+        }
+        cc.parser.had_error |= self.parser.had_error;
+        if last {
+            cc.end_compiler();
+        }
+        for diagnostic in &mut cc.parser.errors {
+            diagnostic.span.start += self.span_base;
+        }
+        self.parser.errors.append(&mut cc.parser.errors);
+        self.parser.had_error = cc.parser.had_error;
+        self.parser.set_panic(cc.parser.get_panic());
+        self.compiling_chunk = mem::take(&mut cc.compiling_chunk);
+        self.pending_line += segment.matches('\n').count();
+        self.span_base += segment.len();
+    }
+}
+
+// byte offsets where the top level declarations in `source` end. A ';' or
+// '}' outside any brackets ends one, but only once the next token is known
+// not to be an `else` or `catch`. Input that may be `cut_off` can't trust
+// its last token either, the rest of it may arrive with the next piece
+fn declaration_ends(source: &str, cut_off: bool) -> Vec<usize> {
+    let mut depth = 0usize;
+    let mut ends = Vec::new();
+    let mut candidate = None;
+    // whether each open '{' starts a `do` block, whose '}' is inside an
+    // expression and ends nothing
+    let mut braces = Vec::new();
+    let mut previous = TType::Eof;
+    for token in Scanner::new(source) {
+        if let Some(end) = candidate.take() {
+            if cut_off && token.start + token.len == source.len() {
+                break;
+            }
+            if !matches!(token.ttype, TType::Else | TType::Catch) {
+                ends.push(end);
+            }
+        }
+        let mut ends_block = false;
+        match token.ttype {
+            TType::LeftParen => depth += 1,
+            TType::LeftBrace => {
+                depth += 1;
+                braces.push(previous == TType::Do);
+            }
+            TType::RightParen => depth = depth.saturating_sub(1),
+            TType::RightBrace => {
+                depth = depth.saturating_sub(1);
+                ends_block = !braces.pop().unwrap_or(false);
+            }
+            _ => {}
+        }
+        if depth == 0 && (token.ttype == TType::SemiColon || ends_block) {
+            candidate = Some(token.start + token.len);
+        }
+        previous = token.ttype;
+    }
+    ends
+}
+
+// byte length of the leading complete declarations in fed input, cut at
+// the end of a line so every segment starts a line of its own and its
+// diagnostics get the same columns and excerpts as a whole source's. Only
+// blanks or a line comment may follow the last declaration on that line
+fn complete_prefix(source: &str) -> usize {
+    let ends = declaration_ends(source, true);
+    for &end in ends.iter().rev() {
+        let rest = &source[end..];
+        if let Some(newline) = rest.find('\n') {
+            let tail = rest[..newline].trim_start();
+            if tail.is_empty() || tail.starts_with("//") {
+                return end + newline + 1;
+            }
+        }
+    }
+    0
+}
+
+// where each top level declaration of a whole source lies, the last one
+// keeping whatever follows it
+pub fn declaration_ranges(source: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for end in declaration_ends(source, false) {
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges.push(start..source.len());
+    ranges
+}
+
 impl<'src, 'vm> Compiler<'src, 'vm> {
     pub fn new(source: &'src str, vm: &'vm mut VM) -> Self {
         Self {
@@ -34,10 +229,34 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
             locals: Vec::new(),
             scope_depth: 0,
             compiling_chunk: Chunk::default(),
+            trailing: Trailing::Statement,
+            pending: String::new(),
+            pending_line: 1,
+            pending_invalid: Vec::new(),
+            span_base: 0,
+            nesting: 0,
+            stack_height: 0,
+            block_value: None,
+            symbols: None,
         }
     }
 
-    pub fn compile(&mut self) -> Result<(), CompileErrors> {
+    // for a source from scanner::decode_lossy, reporting the bytes it
+    // replaced as invalid UTF-8
+    pub fn invalid_utf8_at(mut self, offsets: Vec<usize>) -> Self {
+        let source = self.parser.scanner.source();
+        self.parser.scanner = Scanner::new(source).invalid_utf8_at(offsets);
+        self
+    }
+
+    pub fn compile(&mut self) -> Result<(), Vec<Diagnostic>> {
+        self.vm.source = Some(self.parser.scanner.source().into());
+        self.vm.chunks = self.compile_chunk()?;
+        Ok(())
+    }
+
+    // compiles without replacing the vm's chunk, used for imported modules
+    pub fn compile_chunk(&mut self) -> Result<Chunk, Vec<Diagnostic>> {
         self.parser.advance();
 
         while !self.parser.match_token(TType::Eof) {
@@ -47,8 +266,10 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
         self.parser
             .consume(TType::Eof, "Expected end of expression");
         self.end_compiler();
-        self.vm.chunks = self.compiling_chunk.clone();
-        Ok(())
+        if self.parser.had_error {
+            return Err(mem::take(&mut self.parser.errors));
+        }
+        Ok(self.compiling_chunk.clone())
     }
 
     pub fn get_current_chunk(&mut self) -> &mut Chunk {
@@ -57,11 +278,9 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
 
     pub fn end_compiler(&mut self) {
         self.emit_return();
-        if cfg!(feature = "debug")
-            || cfg!(debug_assertions) && self.parser.had_error && !cfg!(feature = "trace")
-        {
+        if cfg!(feature = "debug") || self.vm.config.dump_failed_chunks && self.parser.had_error {
             println!("...Dump...");
-            self.compiling_chunk.disassemble("Code");
+            self.compiling_chunk.disassemble(&self.vm.heap, "Code");
         }
     }
 
@@ -71,15 +290,17 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
 
     fn end_scope(&mut self) {
         self.scope_depth -= 1;
-        while !self.locals.is_empty() && self.locals.last().unwrap().depth > self.scope_depth {
+        while matches!(self.locals.last(), Some(local) if local.depth > self.scope_depth) {
             self.emit_byte(OpCode::Pop.into());
             self.locals.pop();
+            self.stack_height -= 1;
         }
     }
 
     pub fn emit_byte(&mut self, byte: u8) {
-        let line = self.parser.previous.as_ref().unwrap().line;
-        self.compiling_chunk.write(byte, line);
+        let line = self.parser.previous_line();
+        let span = self.parser.previous.as_ref().map(Token::span);
+        self.emit_byte_at(byte, line, span);
     }
 
     pub fn emit_bytes(&mut self, byte1: u8, byte2: u8) {
@@ -91,8 +312,7 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
         self.emit_byte(OpCode::Loop.into());
         let offset = self.compiling_chunk.code.len() - loop_start + 2;
         if offset > u16::MAX as usize {
-            self.parser
-                .error_at(CompileErrors::TooFarToLoop.to_string().as_str());
+            self.parser.error_at_previous(CompileErrors::TooFarToLoop);
         }
         self.emit_byte(((offset >> 8) & 0xff) as u8);
         self.emit_byte((offset & 0xff) as u8);
@@ -112,7 +332,7 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
     pub fn emit_constant(&mut self, value: Value) {
         match self.get_current_chunk().add(value) {
             Ok(byte) => self.emit_bytes(OpCode::Constant.into(), byte),
-            Err(err) => self.parser.error_at(format!("{}", err).as_str()),
+            Err(err) => self.parser.error_at_previous(err),
         }
     }
 
@@ -121,22 +341,80 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
         let jump = code.len() - offset - 2;
 
         if jump > u16::MAX as usize {
-            self.parser
-                .error_at(format!("{}", CompileErrors::TooMuchToJump).as_str());
+            self.parser.error_at_previous(CompileErrors::TooMuchToJump);
         } else {
             code[offset] = ((jump >> 8) & 0xff) as u8;
             code[offset + 1] = (jump & 0xff) as u8;
         }
     }
 
-    pub fn emit_byte_with_line(&mut self, byte: u8, line: usize) {
+    // for an operator emitted after its operands, so the instruction is
+    // attributed to the operator's token rather than the last one parsed
+    pub fn emit_byte_at(&mut self, byte: u8, line: usize, span: Option<Range<usize>>) {
+        if let Some(log) = &mut self.parser.log {
+            log.emit(self.compiling_chunk.code.len());
+        }
         self.compiling_chunk.write(byte, line);
+        if let (true, Some(span)) = (self.vm.config.debug_info, span) {
+            let base = self.span_base;
+            self.compiling_chunk
+                .write_span(span.start + base..span.end + base);
+        }
     }
 
     pub fn expression(&mut self) {
         self.parse_precedence(Precedence::Assignment);
     }
 
+    pub fn argument_list(&mut self) -> u8 {
+        let mut arg_count: usize = 0;
+        // the callee and the arguments before each one wait under it
+        let height = self.stack_height;
+        self.stack_height += 1;
+        if !self.parser.check(TType::RightParen) {
+            loop {
+                self.expression();
+                if arg_count == u8::MAX as usize {
+                    self.parser
+                        .error_at_previous(CompileErrors::TooManyArguments);
+                }
+                arg_count += 1;
+                self.stack_height += 1;
+                if !self.parser.match_token(TType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.stack_height = height;
+        self.parser
+            .consume(TType::RightParen, "Expect ')' after arguments.");
+        arg_count.min(u8::MAX as usize) as u8
+    }
+
+    // do { ...; expr } is the value of its final expression, or nil when it
+    // ends in a statement. The value gets a slot under the block's locals,
+    // where it is stored in place of the usual pop, so they can be popped
+    // from above it
+    pub fn do_expression(&mut self) {
+        self.parser
+            .consume(TType::LeftBrace, "Expect '{' after 'do'.");
+        if self.parser.previous_type() != TType::LeftBrace {
+            return;
+        }
+        let slot = self.stack_height;
+        if slot > u8::MAX as usize {
+            self.parser.error_at_previous(CompileErrors::TooManyLocals);
+        }
+        self.emit_byte(OpCode::Nil.into());
+        self.stack_height += 1;
+        let outer = self.block_value.replace((self.nesting + 1, slot as u8));
+        self.begin_scope();
+        self.block();
+        self.end_scope();
+        self.block_value = outer;
+        self.stack_height -= 1;
+    }
+
     pub fn block(&mut self) {
         while !self.parser.check(TType::RightBrace) && !self.parser.check(TType::Eof) {
             self.declaraction();
@@ -158,14 +436,96 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
                     .consume(TType::SemiColon, "Expect ';' after variable declaration.");
                 self.define_variable(var);
             }
-            Err(err) => self.parser.error_at(format!("{err}").as_str()),
+            Err(err) => self.parser.error_at_previous(err),
+        }
+    }
+
+    // import "path/to/file.lox" [as name];
+    fn import_declaration(&mut self) {
+        if self.scope_depth > 0 {
+            self.parser
+                .error_at_previous(CompileErrors::ImportNotTopLevel);
+            return;
+        }
+        self.parser
+            .consume(TType::String, "Expect module path after 'import'.");
+        let lexeme = self.parser.previous_lexeme();
+        let path = lexeme
+            .get(1..lexeme.len().saturating_sub(1))
+            .unwrap_or_default();
+        match self.vm.load_module(path) {
+            Ok(key) => {
+                let key = create_string(self.vm, &key);
+                match self.get_current_chunk().add(key.into()) {
+                    Ok(constant) => self.emit_bytes(OpCode::Import.into(), constant),
+                    Err(err) => self.parser.error_at_previous(err),
+                }
+            }
+            Err(err) => self
+                .parser
+                .error_at_previous(CompileErrors::Import(Box::new(err))),
+        }
+
+        let is_as = |token: &Option<Token>| matches!(token, Some(t) if t.ttype == TType::Identifer && t.lexeme == Some("as"));
+        let name = if is_as(&self.parser.current) {
+            self.parser.advance();
+            self.parser
+                .consume(TType::Identifer, "Expect module name after 'as'.");
+            self.identififer_constant(self.parser.previous.clone())
+        } else {
+            match default_binding(path) {
+                Some(name) => {
+                    let name = create_string(self.vm, name);
+                    self.get_current_chunk().add(name.into())
+                }
+                None => {
+                    self.parser
+                        .error_at_previous(CompileErrors::InvalidModuleName);
+                    return;
+                }
+            }
+        };
+        if let Some(token) = self.parser.previous.clone() {
+            let bound = match token.ttype {
+                TType::Identifer => token.lexeme,
+                _ => default_binding(path),
+            };
+            self.define_symbol(bound.unwrap_or_default(), &token, Binding::Module);
+        }
+        self.parser
+            .consume(TType::SemiColon, "Expect ';' after import.");
+        match name {
+            Ok(name) => self.define_variable(name),
+            Err(err) => self.parser.error_at_previous(err),
         }
     }
 
     fn expression_statement(&mut self) {
         self.expression();
-        self.parser
-            .consume(TType::SemiColon, "Expect ';' after expression.");
+        // the value of a `do` block, see do_expression
+        if let Some((nesting, slot)) = self.block_value {
+            if nesting == self.nesting && self.parser.check(TType::RightBrace) {
+                self.emit_bytes(OpCode::SetLocal.into(), slot);
+                self.emit_byte(OpCode::Pop.into());
+                return;
+            }
+        }
+        match self.trailing {
+            Trailing::Echo if self.parser.check(TType::Eof) => return,
+            Trailing::Keep => {
+                if self.parser.check(TType::Eof) {
+                    return;
+                }
+                self.parser
+                    .consume(TType::SemiColon, "Expect ';' after expression.");
+                if self.parser.check(TType::Eof) {
+                    return;
+                }
+            }
+            _ => self
+                .parser
+                .consume(TType::SemiColon, "Expect ';' after expression."),
+        }
         self.emit_byte(OpCode::Pop.into());
     }
 
@@ -236,6 +596,44 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
         self.patch_jump(else_jump);
     }
 
+    // try { ... } catch (name) { ... }, the catch block starts with the
+    // exception in the slot the handler unwound the stack to
+    fn try_statement(&mut self) {
+        self.parser
+            .consume(TType::LeftBrace, "Expect '{' after 'try'.");
+        let handler = self.emit_jump(OpCode::Try);
+        self.begin_scope();
+        self.block();
+        self.end_scope();
+        self.emit_byte(OpCode::EndTry.into());
+        let end_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(handler);
+
+        self.parser
+            .consume(TType::Catch, "Expect 'catch' after try block.");
+        self.parser
+            .consume(TType::LeftParen, "Expect '(' after 'catch'.");
+        self.begin_scope();
+        self.parser
+            .consume(TType::Identifer, "Expect exception name.");
+        self.declare_variable();
+        self.mark_initialized();
+        self.parser
+            .consume(TType::RightParen, "Expect ')' after exception name.");
+        self.parser
+            .consume(TType::LeftBrace, "Expect '{' before catch block.");
+        self.block();
+        self.end_scope();
+        self.patch_jump(end_jump);
+    }
+
+    fn throw_statement(&mut self) {
+        self.expression();
+        self.parser
+            .consume(TType::SemiColon, "Expect ';' after thrown value.");
+        self.emit_byte(OpCode::Throw.into());
+    }
+
     fn print_statement(&mut self) {
         self.expression();
         self.parser
@@ -262,18 +660,21 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
     }
 
     pub fn synchronize(&mut self) {
-        self.parser.set_panic(true);
+        self.parser.set_panic(false);
 
-        while self.parser.current.as_ref().unwrap().ttype != TType::Eof {
-            if self.parser.previous.as_ref().unwrap().ttype == TType::SemiColon {
+        while self.parser.current_type() != TType::Eof {
+            if self.parser.previous_type() == TType::SemiColon {
                 return;
             }
-            match self.parser.current.as_ref().unwrap().ttype {
+            match self.parser.current_type() {
                 TType::Class
                 | TType::Fun
                 | TType::Var
                 | TType::For
                 | TType::If
+                | TType::Import
+                | TType::Throw
+                | TType::Try
                 | TType::While
                 | TType::Print
                 | TType::Return => return,
@@ -286,7 +687,9 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
     pub fn declaraction(&mut self) {
         // matcher!(self, Var, self.var_declaration());
         if self.parser.match_token(TType::Var) {
-            self.var_declaration();
+            self.rule("var_declaration", Self::var_declaration);
+        } else if self.parser.match_token(TType::Import) {
+            self.rule("import_declaration", Self::import_declaration);
         } else {
             self.statement();
         }
@@ -297,92 +700,177 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
     }
 
     pub fn statement(&mut self) {
+        if self.nest(true) {
+            self.nested_statement();
+            self.nesting -= 1;
+        }
+    }
+
+    fn nested_statement(&mut self) {
         // matcher!(self, Print, self.print_statement());
-        if self.parser.match_token(TType::Print) {
-            self.print_statement();
+        if !self.vm.config.modern && self.parser.match_token(TType::Print) {
+            self.rule("print_statement", Self::print_statement);
         } else if self.parser.match_token(TType::For) {
-            self.for_statement();
+            self.rule("for_statement", Self::for_statement);
         } else if self.parser.match_token(TType::If) {
-            self.if_statement();
+            self.rule("if_statement", Self::if_statement);
         } else if self.parser.match_token(TType::While) {
-            self.while_statement();
+            self.rule("while_statement", Self::while_statement);
+        } else if self.parser.match_token(TType::Try) {
+            self.rule("try_statement", Self::try_statement);
+        } else if self.parser.match_token(TType::Throw) {
+            self.rule("throw_statement", Self::throw_statement);
         } else if self.parser.match_token(TType::LeftBrace) {
-            self.begin_scope();
-            self.block();
-            self.end_scope();
+            self.rule("block", |cc| {
+                cc.begin_scope();
+                cc.block();
+                cc.end_scope();
+            });
         } else {
-            self.expression_statement();
+            self.rule("expression_statement", Self::expression_statement);
         }
     }
 
     pub fn parse_precedence(&mut self, prec: Precedence) {
+        if self.nest(false) {
+            self.rule(format_args!("parse_precedence {:?}", prec), |cc| {
+                cc.nested_precedence(prec)
+            });
+            self.nesting -= 1;
+        }
+    }
+
+    // parses one rule, as a node of the parse log when there is one
+    fn rule(&mut self, name: impl fmt::Display, parse: impl FnOnce(&mut Self)) {
+        if let Some(log) = &mut self.parser.log {
+            log.enter(name);
+        }
+        parse(self);
+        if let Some(log) = &mut self.parser.log {
+            log.leave();
+        }
+    }
+
+    // one level deeper, or an error and false at the limit. What would have
+    // been parsed at that level is skipped whole, an expression up to the
+    // end of its brackets and a statement up to its ';' or '}'. The levels
+    // around it then find what closes them
+    fn nest(&mut self, statement: bool) -> bool {
+        let max = self.vm.config.max_nesting;
+        if self.nesting < max {
+            self.nesting += 1;
+            return true;
+        }
+        self.parser
+            .error_at_current(CompileErrors::TooDeeplyNested(max));
+        let mut depth = 0usize;
+        while self.parser.current_type() != TType::Eof {
+            let ttype = self.parser.current_type();
+            match ttype {
+                TType::LeftParen | TType::LeftBrace => depth += 1,
+                TType::RightParen | TType::RightBrace => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.parser.advance();
+            let ends = !statement || matches!(ttype, TType::SemiColon | TType::RightBrace);
+            if depth == 0 && ends {
+                break;
+            }
+        }
+        // a skipped statement already ends where synchronizing would stop,
+        // and it would go on to skip the '}' of enclosing blocks
+        if statement {
+            self.parser.set_panic(false);
+        }
+        false
+    }
+
+    fn nested_precedence(&mut self, prec: Precedence) {
         self.parser.advance();
 
         let assign = prec <= Precedence::Assignment;
-        match get_rule(self.parser.previous.as_ref().unwrap().ttype).prefix {
-            Some(rule) => rule(self, assign),
-            None => self
-                .parser
-                .error_at(format!("{} Expected expression.", CompileErrors::ParseError).as_str()),
+        let modern = self.vm.config.modern;
+        let ttype = self.parser.previous_type();
+        match get_rule(ttype, modern).prefix {
+            Some(rule) => self.rule(format_args!("prefix {:?}", ttype), |cc| rule(cc, assign)),
+            None => {
+                self.parser
+                    .error_at_previous(CompileErrors::ExpectedExpression);
+                return;
+            }
         }
 
-        while prec <= get_rule(self.parser.current.as_ref().unwrap().ttype).precedence {
+        while prec <= get_rule(self.parser.current_type(), modern).precedence {
             self.parser.advance();
-            // match get_rule(self.parser.previous.as_ref().unwrap().ttype).infix {
-            //     Some(rule) => rule(self, assign),
-            //     None => continue,
-            // }
-            get_rule(self.parser.previous.as_ref().unwrap().ttype)
-                .infix
-                .unwrap()(self, assign)
+            // a token with a precedence but no infix rule can't continue
+            // the expression
+            let ttype = self.parser.previous_type();
+            match get_rule(ttype, modern).infix {
+                Some(rule) => self.rule(format_args!("infix {:?}", ttype), |cc| rule(cc, assign)),
+                None => {
+                    self.parser
+                        .error_at_previous(CompileErrors::ExpectedExpression);
+                    return;
+                }
+            }
         }
 
         if assign && self.parser.match_token(TType::Equal) {
-            self.parser.error_at(
-                format!("{} Invalid assignment target.", CompileErrors::ParseError).as_str(),
-            )
+            self.parser
+                .error_at_previous(CompileErrors::InvalidAssignmentTarget)
         }
     }
 
     pub fn identififer_constant(&mut self, t: Option<Token<'src>>) -> Result<u8, CompileErrors> {
-        let name = &t.unwrap().lexeme.unwrap();
+        let name = t.and_then(|t| t.lexeme).unwrap_or_default();
         let str = create_string(self.vm, name);
         self.get_current_chunk().add(str.into())
     }
 
     fn resolve_local(&mut self, name: &'src str) -> Option<u8> {
-        for (index, local) in self.locals.iter().enumerate() {
-            if local.name == name {
-                return Some(index as u8);
-            }
+        // innermost first, so a shadowing local wins
+        let local = self.locals.iter().rev().find(|local| local.name == name)?;
+        // `var a = a;` has no value in the slot to read yet
+        if !local.initialized {
+            let slot = local.slot;
+            self.parser
+                .error_at_previous(CompileErrors::UninitializedLocal);
+            return Some(slot as u8);
         }
-        None
+        Some(local.slot as u8)
     }
 
     fn add_local(&mut self, name: &'src str) {
-        if self.locals.len() == u8::MAX as usize + 1 {
-            self.parser
-                .error_at(format!("{}", CompileErrors::TooManyLocals).as_str());
+        if self.stack_height > u8::MAX as usize {
+            self.parser.error_at_previous(CompileErrors::TooManyLocals);
             return;
         }
 
-        let local = Local::new(name, self.scope_depth);
+        let local = Local::new(name, self.scope_depth, self.stack_height);
         self.locals.push(local);
+        if let Some(token) = self.parser.previous.clone() {
+            self.define_symbol(name, &token, Binding::Local(self.stack_height));
+        }
+    }
+
+    fn define_symbol(&mut self, name: &str, token: &Token<'_>, binding: Binding) {
+        if let Some(symbols) = &mut self.symbols {
+            symbols.define(name, Span::from(token), binding);
+        }
     }
 
     pub fn declare_variable(&mut self) {
         if self.scope_depth == 0 {
             return;
         }
-        let name = self.parser.previous.as_ref().unwrap().lexeme.unwrap();
+        let name = self.parser.previous_lexeme();
 
         for local in self.locals.iter().rev() {
             if local.depth < self.scope_depth {
                 break;
             }
             if local.name == name {
-                self.parser
-                    .error_at(format!("{}", CompileErrors::DuplicateName).as_str());
+                self.parser.error_at_previous(CompileErrors::DuplicateName);
                 return;
             }
         }
@@ -391,17 +879,26 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
     }
 
     pub fn parse_variable(&mut self, error: &str) -> Result<u8, CompileErrors> {
-        self.parser.consume(TType::Identifer, error);
+        // `print` names the native in modern mode, and may be shadowed
+        if !(self.vm.config.modern && self.parser.match_token(TType::Print)) {
+            self.parser.consume(TType::Identifer, error);
+        }
         self.declare_variable();
         if self.scope_depth > 0 {
             return Ok(0_u8);
         }
+        if let Some(token) = self.parser.previous.clone() {
+            self.define_symbol(token.lexeme.unwrap_or_default(), &token, Binding::Global);
+        }
         self.identififer_constant(self.parser.previous.clone())
     }
 
+    // its value is on the stack now
     fn mark_initialized(&mut self) {
-        let last = self.locals.len() - 1;
-        self.locals[last].depth = self.scope_depth;
+        if let Some(local) = self.locals.last_mut() {
+            local.initialized = true;
+        }
+        self.stack_height += 1;
     }
 
     pub fn define_variable(&mut self, global: u8) {
@@ -413,16 +910,20 @@ impl<'src, 'vm> Compiler<'src, 'vm> {
     }
 
     pub fn named_variable(&mut self, token: Option<Token<'src>>, can_assign: bool) {
-        let name = token.as_ref().unwrap().lexeme.unwrap();
-        let (get_op, set_op, arg) = match self.resolve_local(name) {
+        let name = token.as_ref().and_then(|t| t.lexeme).unwrap_or_default();
+        let slot = self.resolve_local(name);
+        if let (Some(symbols), Some(token)) = (&mut self.symbols, &token) {
+            symbols.refer(name, Span::from(token), slot.map(usize::from));
+        }
+        let (get_op, set_op, arg) = match slot {
             Some(index) => (OpCode::GetLocal, OpCode::SetLocal, index),
-            None => (
-                OpCode::GetGlobal,
-                OpCode::SetGlobal,
-                self.identififer_constant(token)
-                    .map_err(|err| self.parser.error_at(format!("{}", err).as_str()))
-                    .unwrap(),
-            ),
+            None => match self.identififer_constant(token) {
+                Ok(constant) => (OpCode::GetGlobal, OpCode::SetGlobal, constant),
+                Err(err) => {
+                    self.parser.error_at_previous(err);
+                    (OpCode::GetGlobal, OpCode::SetGlobal, 0)
+                }
+            },
         };
 
         if can_assign && self.parser.match_token(TType::Equal) {
@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+
+// groups of natives a vm may be denied, everything else is pure computation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    // readLine
+    Io,
+    // clock, now_ms
+    Time,
+    // memoryUsed, gcCollect, objectCount
+    Memory,
+}
+
+#[derive(Debug, Clone)]
+pub struct VmConfig {
+    pub capabilities: Vec<Capability>,
+    // heap bytes allocated while running, beyond what was held when it started
+    pub max_heap_bytes: Option<usize>,
+    pub max_stack: Option<usize>,
+    // expressions and statements the compiler parses inside one another
+    // before giving up, so deep nesting is an error instead of a crash
+    pub max_nesting: usize,
+    // instructions left to execute, counts down across runs
+    pub fuel: Option<u64>,
+    // searched by `import` after the importing file's directory, before LOX_PATH
+    pub module_dirs: Vec<PathBuf>,
+    // built in runtime errors can't be caught, only thrown values
+    pub strict_compat: bool,
+    // `print` is the native function instead of a statement, so it takes
+    // any number of arguments and can be shadowed
+    pub modern: bool,
+    // `a < b < c` means `a < b and b < c`, with b evaluated once, instead
+    // of being a compile error
+    pub chain_comparisons: bool,
+    // compiled chunks record the source span of each instruction
+    pub debug_info: bool,
+    // heap bytes this vm may hold before the first automatic collection
+    pub gc_initial_threshold: usize,
+    // after each collection the next one waits until the heap has grown to
+    // this multiple of what survived
+    pub gc_growth_factor: f64,
+    // collect before every allocation made while a script runs
+    pub gc_stress: bool,
+    // objects and bytes freed and the pause, for every collection
+    pub gc_log: bool,
+    // print the disassembly of a chunk that failed to compile, on by
+    // default in debug builds
+    pub dump_failed_chunks: bool,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            capabilities: vec![Capability::Io, Capability::Time, Capability::Memory],
+            max_heap_bytes: None,
+            max_stack: None,
+            max_nesting: 256,
+            fuel: None,
+            module_dirs: Vec::new(),
+            strict_compat: false,
+            modern: false,
+            chain_comparisons: false,
+            debug_info: false,
+            gc_initial_threshold: 1024 * 1024,
+            gc_growth_factor: 2.0,
+            gc_stress: cfg!(feature = "gc_stress"),
+            gc_log: cfg!(feature = "gc_log"),
+            dump_failed_chunks: cfg!(debug_assertions) && !cfg!(feature = "trace"),
+        }
+    }
+}
+
+impl VmConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // no capabilities, limits still unset
+    pub fn sandboxed() -> Self {
+        Self {
+            capabilities: Vec::new(),
+            ..Self::default()
+        }
+    }
+
+    pub fn allow(mut self, capability: Capability) -> Self {
+        if !self.allows(capability) {
+            self.capabilities.push(capability);
+        }
+        self
+    }
+
+    pub fn deny(mut self, capability: Capability) -> Self {
+        self.capabilities.retain(|c| *c != capability);
+        self
+    }
+
+    pub fn max_heap_bytes(mut self, bytes: usize) -> Self {
+        self.max_heap_bytes = Some(bytes);
+        self
+    }
+
+    pub fn max_stack(mut self, slots: usize) -> Self {
+        self.max_stack = Some(slots);
+        self
+    }
+
+    pub fn max_nesting(mut self, depth: usize) -> Self {
+        self.max_nesting = depth;
+        self
+    }
+
+    pub fn fuel(mut self, instructions: u64) -> Self {
+        self.fuel = Some(instructions);
+        self
+    }
+
+    pub fn strict_compat(mut self) -> Self {
+        self.strict_compat = true;
+        self
+    }
+
+    pub fn modern(mut self) -> Self {
+        self.modern = true;
+        self
+    }
+
+    pub fn chain_comparisons(mut self) -> Self {
+        self.chain_comparisons = true;
+        self
+    }
+
+    pub fn debug_info(mut self) -> Self {
+        self.debug_info = true;
+        self
+    }
+
+    pub fn gc_initial_threshold(mut self, bytes: usize) -> Self {
+        self.gc_initial_threshold = bytes;
+        self
+    }
+
+    pub fn gc_growth_factor(mut self, factor: f64) -> Self {
+        self.gc_growth_factor = factor;
+        self
+    }
+
+    pub fn gc_stress(mut self) -> Self {
+        self.gc_stress = true;
+        self
+    }
+
+    pub fn gc_log(mut self) -> Self {
+        self.gc_log = true;
+        self
+    }
+
+    pub fn add_module_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.module_dirs.push(dir.into());
+        self
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{chunks::Chunk, profiler::Profile};
+
+// hit counts for every source line that compiled to at least one instruction
+#[derive(Debug, Default)]
+pub struct Coverage {
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl Coverage {
+    // `source` drops the implicit return the compiler places after the last line
+    pub fn new(chunk: &Chunk, profile: &Profile, source: Option<&str>) -> Self {
+        let last = source.map(|s| s.lines().count()).unwrap_or(usize::MAX);
+        let lines = chunk
+            .lines
+            .iter()
+            .map(|(_, line)| *line)
+            .filter(|line| *line <= last)
+            .map(|line| (line, profile.lines.get(&line).copied().unwrap_or(0)))
+            .collect();
+        Self { lines }
+    }
+
+    pub fn hit(&self) -> usize {
+        self.lines.values().filter(|count| **count > 0).count()
+    }
+
+    // the tracefile format read by genhtml, see geninfo(1)
+    pub fn lcov(&self, path: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "TN:");
+        let _ = writeln!(out, "SF:{}", path);
+        for (line, count) in &self.lines {
+            let _ = writeln!(out, "DA:{},{}", line, count);
+        }
+        let _ = writeln!(out, "LH:{}", self.hit());
+        let _ = writeln!(out, "LF:{}", self.lines.len());
+        let _ = writeln!(out, "end_of_record");
+        out
+    }
+
+    // each source line prefixed by its hit count, `#####` marks lines that
+    // never ran and `-` lines without code, like gcov
+    pub fn annotate(&self, source: &str) -> String {
+        let mut out = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let count = match self.lines.get(&line) {
+                Some(0) => "#####".to_string(),
+                Some(count) => count.to_string(),
+                None => "-".to_string(),
+            };
+            let _ = writeln!(out, "{:>9}:{:>5}: {}", count, line, text);
+        }
+        let _ = writeln!(
+            out,
+            "lines executed: {}/{} ({:.2}%)",
+            self.hit(),
+            self.lines.len(),
+            self.hit() as f64 * 100.0 / self.lines.len().max(1) as f64
+        );
+        out
+    }
+}
@@ -0,0 +1,32 @@
+use core::fmt;
+use std::ops::Range;
+
+use crate::{
+    chunks::Chunk,
+    heap::Heap,
+    table::Table,
+    value::{InternString, Value},
+};
+
+// read-only view of the vm handed to breakpoint callbacks
+#[derive(Debug)]
+pub struct VmInspector<'a> {
+    pub chunk: &'a Chunk,
+    // offset of the instruction the breakpoint replaced
+    pub offset: usize,
+    pub line: Option<usize>,
+    // source span of that instruction, with debug info only
+    pub span: Option<Range<usize>>,
+    pub stack: &'a [Value],
+    pub globals: &'a Table<InternString, Value>,
+    // to read the strings and objects the stack and globals point at
+    pub heap: &'a Heap,
+}
+
+pub struct DebugHook(pub Box<dyn FnMut(&VmInspector) + Send>);
+
+impl fmt::Debug for DebugHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DebugHook")
+    }
+}
@@ -1,19 +1,83 @@
-use crate::{chunks::Chunk, cprint, cprintln, opcode::OpCode, value::Value};
+use core::fmt;
+use std::io::{self, Write};
 
+use crate::{
+    chunks::Chunk,
+    cprint, cprintln,
+    error::RuntimeErrors,
+    heap::Heap,
+    opcode::{OpCode, Operand},
+    value::{LoxValue, Value},
+};
+
+// `heap` is the one the chunk's constants were allocated in
 pub trait Disassembler {
-    fn disassemble(&self, name: &str);
+    fn disassemble(&self, heap: &Heap, name: &str);
+    // code, constant table and line table regardless of build features
+    fn dump(&self, heap: &Heap, name: &str);
+    // the same listing as `disassemble`, uncolored
+    fn disassemble_to(&self, heap: &Heap, out: &mut impl Write) -> io::Result<()>;
+    fn instructions<'a>(&'a self, heap: &'a Heap) -> Instructions<'a>;
 }
 
 impl Disassembler for Chunk {
     #[allow(unused_variables)]
-    fn disassemble(&self, name: &str) {
+    fn disassemble(&self, heap: &Heap, name: &str) {
         if cfg!(feature = "debug") || cfg!(debug_assertions) {
-            cprintln!(Red, "=={}==", name);
-            let mut ip = TracingIp::new(self, 0);
-            while ip.valid() {
-                ip.disassemble_instruction();
+            print_code(self, heap, name);
+        }
+    }
+
+    fn dump(&self, heap: &Heap, name: &str) {
+        print_code(self, heap, name);
+
+        cprintln!(Red, "==constants==");
+        for (index, constant) in self.constants.iter().enumerate() {
+            cprint!(Green, "{:04} ", index);
+            cprintln!(Cyan, "{}", constant.repr(heap));
+        }
+
+        cprintln!(Red, "==lines==");
+        for (offset, line) in self.lines.iter() {
+            cprint!(Green, "{:04} ", offset);
+            cprintln!(LightPurple, "line {}", line);
+        }
+    }
+
+    fn disassemble_to(&self, heap: &Heap, out: &mut impl Write) -> io::Result<()> {
+        let mut previous = None;
+        for instruction in self.instructions(heap) {
+            match instruction.line {
+                line if instruction.offset > 0 && line == previous => {
+                    write!(out, "{:04}    | ", instruction.offset)?
+                }
+                line => write!(
+                    out,
+                    "{:04} {:04} ",
+                    instruction.offset,
+                    line.unwrap_or_default()
+                )?,
             }
+            writeln!(out, "{}", instruction)?;
+            previous = instruction.line;
         }
+        Ok(())
+    }
+
+    fn instructions<'a>(&'a self, heap: &'a Heap) -> Instructions<'a> {
+        Instructions {
+            chunk: self,
+            heap,
+            offset: 0,
+        }
+    }
+}
+
+fn print_code(chunk: &Chunk, heap: &Heap, name: &str) {
+    cprintln!(Red, "=={}==", name);
+    let mut ip = TracingIp::new(chunk, 0);
+    while ip.valid() {
+        ip.disassemble_instruction(heap);
     }
 }
 
@@ -22,6 +86,8 @@ pub struct TracingIp<'a> {
     pub chunk: &'a Chunk,
     pub offset: usize,
     pub line: Option<usize>,
+    // offset of the instruction being executed
+    pub start: usize,
 }
 
 impl<'a> TracingIp<'a> {
@@ -30,6 +96,7 @@ impl<'a> TracingIp<'a> {
             chunk,
             offset,
             line: None,
+            start: offset,
         }
     }
 
@@ -47,18 +114,18 @@ impl<'a> TracingIp<'a> {
     pub fn read_short(&mut self) -> u16 {
         let high = self.read() as u16;
         let low = self.read() as u16;
-        (high >> 8) | low
+        (high << 8) | low
     }
 
     pub fn read_constant(&mut self) -> Value {
         let off = self.read();
-        self.chunk.constants[off as usize].clone()
+        self.chunk.constants[off as usize]
     }
 
     pub fn peek(&self, distance: usize) -> Value {
         // println!("{:#?}", self.stack);
         // println!("{}", self.stack.len());
-        self.chunk.constants[self.chunk.constants.len() - 1 - distance].clone()
+        self.chunk.constants[self.chunk.constants.len() - 1 - distance]
     }
 
     pub fn get_line(&self) -> Option<usize> {
@@ -86,74 +153,120 @@ impl<'a> TracingIp<'a> {
         line
     }
 
-    pub fn disassemble_instruction(&mut self) {
+    pub fn disassemble_instruction(&mut self, heap: &Heap) {
         cprint!(Green, "{:04} ", self.offset);
         if self.offset > 0 && self.get_line() == self.get_prev_line() {
             cprint!(LightPurple, "   | ");
         } else {
             cprint! {LightPurple,"{:04} ", self.get_line().unwrap()};
         }
-        let byte = self.read();
-        match OpCode::try_from(byte) {
-            Ok(op) => match op {
-                OpCode::Constant => self.constant_instruction(&op),
-                OpCode::Addition => self.simple_instruction(&op),
-                OpCode::Subtract => self.simple_instruction(&op),
-                OpCode::Multiply => self.simple_instruction(&op),
-                OpCode::Divide => self.simple_instruction(&op),
-                OpCode::Not => self.simple_instruction(&op),
-                OpCode::Negate => self.simple_instruction(&op),
-                OpCode::Print => self.simple_instruction(&op),
-                OpCode::Jump => self.jump_instruction(&op, 1),
-                OpCode::JumpIfFalse => self.jump_instruction(&op, 1),
-                OpCode::Loop => self.jump_instruction(&op, -1),
-                OpCode::True => self.simple_instruction(&op),
-                OpCode::Pop => self.simple_instruction(&op),
-                OpCode::GetLocal => self.byte_instruction(&op),
-                OpCode::SetLocal => self.byte_instruction(&op),
-                OpCode::GetGlobal => self.constant_instruction(&op),
-                OpCode::DefineGlobal => self.constant_instruction(&op),
-                OpCode::SetGlobal => self.constant_instruction(&op),
-                OpCode::False => self.simple_instruction(&op),
-                OpCode::Equal => self.simple_instruction(&op),
-                OpCode::Greater => self.simple_instruction(&op),
-                OpCode::Less => self.simple_instruction(&op),
-                OpCode::Nil => self.simple_instruction(&op),
-                OpCode::Return => self.simple_instruction(&op),
-            },
-            Err(err) => cprintln!(LightRed, "{}", err),
+        let instruction = DisassembledInstruction::decode(self.chunk, heap, self.offset);
+        self.line = instruction.line;
+        self.offset = instruction.next_offset();
+        match instruction.opcode {
+            Some(_) => cprintln!(Cyan, "{}", instruction),
+            None => cprintln!(LightRed, "{}", RuntimeErrors::InvalidOpcode),
         }
     }
+}
 
-    fn simple_instruction(&self, instruction: &OpCode) {
-        cprintln!(Cyan, "{}", instruction)
-    }
+// one decoded instruction, for tools and tests that need more than the
+// colored listing on stdout
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassembledInstruction {
+    pub offset: usize,
+    pub line: Option<usize>,
+    pub byte: u8,
+    // None when `byte` is not a known opcode
+    pub opcode: Option<OpCode>,
+    pub operands: Vec<u8>,
+    // a copy of the constant an Operand::Constant index refers to
+    pub constant: Option<LoxValue>,
+    // absolute offset a jump lands on
+    pub target: Option<usize>,
+}
+
+impl DisassembledInstruction {
+    pub fn decode(chunk: &Chunk, heap: &Heap, offset: usize) -> Self {
+        let byte = chunk.code[offset];
+        let opcode = OpCode::try_from(byte).ok();
+        let operand = opcode.map(|op| op.operand()).unwrap_or(Operand::None);
+        let end = (offset + 1 + operand.size()).min(chunk.code.len());
+        let operands = chunk.code[offset + 1..end].to_vec();
 
-    fn constant_instruction(&mut self, instruction: &OpCode) {
-        let constant = self.read();
+        let mut constant = None;
+        let mut target = None;
+        match (operand, operands.as_slice()) {
+            (Operand::Constant, [index]) => {
+                constant = chunk
+                    .constants
+                    .get(*index as usize)
+                    .map(|constant| LoxValue::from_value(constant, heap))
+            }
+            (Operand::Jump(sign), [high, low]) => {
+                let jump = ((*high as isize) << 8) | *low as isize;
+                target = usize::try_from(end as isize + jump * sign).ok();
+            }
+            _ => {}
+        }
 
-        cprintln!(
-            Cyan,
-            "{:<16} {:<4} {}",
-            instruction,
+        Self {
+            offset,
+            line: chunk.line_at(offset),
+            byte,
+            opcode,
+            operands,
             constant,
-            self.chunk.constants[constant as usize]
-        );
+            target,
+        }
+    }
+
+    pub fn next_offset(&self) -> usize {
+        self.offset + 1 + self.operands.len()
     }
+}
 
-    fn byte_instruction(&mut self, instruction: &OpCode) {
-        let slot = self.read();
-        cprintln!(Cyan, "{:<16} {:<4}", instruction, slot);
+// the instruction column of the listing, in the form the assembler reads back
+impl fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.opcode {
+            Some(op) => op,
+            None => return write!(f, "<{:#04x}>", self.byte),
+        };
+        match (op.operand(), self.operands.as_slice()) {
+            (Operand::Constant, [index]) => match &self.constant {
+                Some(constant) => write!(f, "{:<16} {:<4} {}", op, index, constant.repr()),
+                None => write!(f, "{:<16} {:<4}", op, index),
+            },
+            (Operand::Byte, [slot]) => write!(f, "{:<16} {:<4}", op, slot),
+            (Operand::Jump(_), [high, low]) => write!(
+                f,
+                "{:<16} {:4} -> {:4}",
+                op,
+                ((*high as u16) << 8) | *low as u16,
+                self.target.map(|t| t as isize).unwrap_or(-1)
+            ),
+            _ => write!(f, "{}", op),
+        }
     }
+}
 
-    fn jump_instruction(&mut self, instruction: &OpCode, sign: isize) {
-        let jump = self.read_short() as isize;
-        cprintln!(
-            Cyan,
-            "{:<16} {:4} -> {:4}",
-            instruction,
-            jump,
-            self.offset as isize + jump * sign
-        );
+#[derive(Debug, Clone)]
+pub struct Instructions<'a> {
+    chunk: &'a Chunk,
+    heap: &'a Heap,
+    offset: usize,
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = DisassembledInstruction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.chunk.code.len() {
+            return None;
+        }
+        let instruction = DisassembledInstruction::decode(self.chunk, self.heap, self.offset);
+        self.offset = instruction.next_offset();
+        Some(instruction)
     }
 }
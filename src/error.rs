@@ -1,32 +1,248 @@
 use core::fmt;
+use std::{error::Error, ops::Range};
+
+use crate::{
+    native::Arity,
+    token::{TType, Token},
+};
+
+// where in the source something happened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    // 1-based, counted in chars from the start of the line
+    pub column: usize,
+    // byte offset and length in the source
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.len
+    }
+}
+
+impl From<&Token<'_>> for Span {
+    fn from(token: &Token<'_>) -> Self {
+        Self {
+            line: token.line,
+            column: token.column,
+            start: token.start,
+            len: token.len,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}:{}", self.line, self.column)
+    }
+}
+
+// a compile error and the token it was reported at, compiling collects
+// every one it finds
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub error: CompileErrors,
+    pub span: Span,
+    // the offending token's text, None at the end of the input
+    pub lexeme: Option<String>,
+    // the source line the span starts on
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(error: CompileErrors, token: &Token<'_>, source: &str) -> Self {
+        let span = Span::from(token);
+        let snippet = source.get(..span.start).map(|before| {
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            let text = source[line_start..].lines().next().unwrap_or_default();
+            text.to_string()
+        });
+        Self {
+            error,
+            span,
+            lexeme: (token.ttype != TType::Eof).then(|| token.lexeme.unwrap_or_default().into()),
+            snippet,
+        }
+    }
+
+    // a ';' was required where the input ended
+    pub fn is_missing_semicolon_at_end(&self) -> bool {
+        let semicolon = matches!(&self.error, CompileErrors::Expected(msg) if msg.contains("';'"));
+        semicolon && self.lexeme.is_none()
+    }
+
+    // the source line with a caret under the span
+    pub fn excerpt(&self) -> String {
+        let text = self.snippet.as_deref().unwrap_or_default();
+        let width = self
+            .lexeme
+            .as_deref()
+            .and_then(|lexeme| lexeme.lines().next())
+            .map_or(1, |l| l.chars().count().max(1));
+        let gutter = self.span.line.to_string().len();
+        format!(
+            "{:>gutter$} | {}\n{:>gutter$} | {}{}",
+            self.span.line,
+            text,
+            "",
+            " ".repeat(self.span.column.saturating_sub(1)),
+            "^".repeat(width),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] Error", self.span)?;
+        match &self.lexeme {
+            // multi-line lexemes are cut at the end of their first line
+            Some(lexeme) => write!(f, " at '{}'", lexeme.lines().next().unwrap_or(lexeme))?,
+            None => write!(f, " at end")?,
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+impl Error for Diagnostic {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum CompileErrors {
+    UnexpectedCharacter,
+    UnterminatedString,
+    InvalidUtf8,
+    MalformedNumber(&'static str),
+    // a token other than the one required, with what was expected
+    Expected(String),
+    ExpectedExpression,
+    InvalidAssignmentTarget,
     TooManyConstants,
     CantNegateNoneNumbers,
-    ParseError,
     InvalidPrecedence,
     TooManyLocals,
     DuplicateName,
     UninitializedLocal,
     TooMuchToJump,
     TooFarToLoop,
+    TooManyArguments,
+    ImportNotTopLevel,
+    InvalidModuleName,
+    Import(Box<ModuleErrors>),
+    // past `config.max_nesting` levels of expressions and statements
+    TooDeeplyNested(usize),
+    // `a < b < c`, which would compare a bool with c
+    ChainedComparison,
+}
+
+impl CompileErrors {
+    // the error a scanner error token stands for
+    pub fn lexical(ttype: TType) -> Option<Self> {
+        match ttype {
+            TType::UnexpectedCharacterError => Some(Self::UnexpectedCharacter),
+            TType::UnterminatedStringError => Some(Self::UnterminatedString),
+            TType::InvalidUtf8Error => Some(Self::InvalidUtf8),
+            _ => ttype.error_message().map(Self::MalformedNumber),
+        }
+    }
+
+    // stable identifier for tools, E00xx for compile errors
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnterminatedString => "E0001",
+            Self::UnexpectedCharacter => "E0002",
+            Self::MalformedNumber(_) => "E0003",
+            Self::Expected(_) => "E0004",
+            Self::ExpectedExpression => "E0005",
+            Self::InvalidAssignmentTarget => "E0006",
+            Self::TooManyConstants => "E0007",
+            Self::CantNegateNoneNumbers => "E0008",
+            Self::InvalidPrecedence => "E0009",
+            Self::TooManyLocals => "E0010",
+            Self::DuplicateName => "E0011",
+            Self::UninitializedLocal => "E0012",
+            Self::TooMuchToJump => "E0013",
+            Self::TooFarToLoop => "E0014",
+            Self::TooManyArguments => "E0015",
+            Self::ImportNotTopLevel => "E0016",
+            Self::InvalidModuleName => "E0017",
+            Self::InvalidUtf8 => "E0018",
+            Self::TooDeeplyNested(_) => "E0019",
+            Self::ChainedComparison => "E0020",
+            Self::Import(e) => e.code(),
+        }
+    }
+
+    // a suggestion shown under the rendered error
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::UnterminatedString => Some("add a closing '\"'"),
+            Self::InvalidUtf8 => Some("save the file as UTF-8"),
+            Self::Expected(msg) if msg.contains("';'") => Some("did you forget a ';'?"),
+            Self::Expected(msg) if msg.contains("'}'") => Some("did you forget a '}'?"),
+            Self::Expected(msg) if msg.contains("')'") => Some("did you forget a ')'?"),
+            Self::InvalidAssignmentTarget => {
+                Some("only variables and properties can be assigned to")
+            }
+            Self::DuplicateName => Some("use another name, or assign to it without 'var'"),
+            Self::UninitializedLocal => Some("a local can't be read in its own initializer"),
+            Self::ImportNotTopLevel => Some("move the import out of the block"),
+            Self::InvalidModuleName => Some("bind it to a name with `import \"path\" as name;`"),
+            Self::TooDeeplyNested(_) => Some("move inner parts into variables of their own"),
+            Self::ChainedComparison => Some("compare one pair at a time, `a < b and b < c`"),
+            Self::Import(e) => e.hint(),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for CompileErrors {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::UnexpectedCharacter => write!(f, "Unexpected character."),
+            Self::UnterminatedString => write!(f, "Unterminated string."),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 in source."),
+            Self::MalformedNumber(msg) => write!(f, "{}", msg),
+            Self::Expected(msg) => write!(f, "{}", msg),
+            Self::ExpectedExpression => write!(f, "Expected expression."),
+            Self::InvalidAssignmentTarget => write!(f, "Invalid assignment target."),
             Self::TooManyConstants => {
                 write!(f, "Too many constants in one chunk. Chunk overloaded.")
             }
             Self::CantNegateNoneNumbers => write!(f, "Cannot use unary operator on none numbers"),
-            Self::ParseError => write!(f, "Parse Error."),
             Self::InvalidPrecedence => write!(f, "Cannot convert usize to Precedence"),
             Self::TooManyLocals => write!(f, "Too many local variables in function"),
             Self::DuplicateName => write!(f, "Already a variable in scope with this name."),
             Self::UninitializedLocal => write!(f, "Local hasn't been initialized yet."),
             Self::TooMuchToJump => write!(f, "Too much code to jump over."),
             Self::TooFarToLoop => write!(f, "Loop body too large."),
+            Self::TooManyArguments => write!(f, "Can't have more than 255 arguments."),
+            Self::ImportNotTopLevel => write!(f, "Can only import at the top level."),
+            Self::InvalidModuleName => {
+                write!(
+                    f,
+                    "Module file name is not an identifier, name it with 'as'."
+                )
+            }
+            Self::Import(e) => write!(f, "{}", e),
+            Self::TooDeeplyNested(max) => {
+                write!(f, "Too deeply nested, the limit is {} levels.", max)
+            }
+            Self::ChainedComparison => write!(f, "Comparisons can't be chained."),
+        }
+    }
+}
+
+impl Error for CompileErrors {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Import(e) => Some(e.as_ref()),
+            _ => None,
         }
     }
 }
@@ -38,6 +254,34 @@ pub enum RuntimeErrors {
     TypeError(&'static str, String),
     InvalidAddition(String, String),
     UndefinedVariable(String),
+    NotCallable(String),
+    ArityMismatch(Arity, usize),
+    AssertionFailed(String),
+    Panic(String),
+    FormatError(String),
+    HostError(String),
+    NoProperties(String),
+    UndefinedProperty(String, String),
+    OutOfFuel,
+    StackOverflow(usize),
+    HeapLimitExceeded(usize),
+    ImportError(Box<ModuleErrors>),
+    // `exit()` unwinding the script, not a failure
+    Exit(i32),
+    // a `throw` no `catch` handled, with the value thrown
+    Uncaught(String),
+    // a native waiting on the host, with the token `VM::resume` takes
+    Pending(u64),
+    // a native that waited on the host under `VM::run` or another caller
+    // that can't suspend, so nothing can ever resume it
+    NotResumable(u64),
+    // stopped by Ctrl-C or an InterruptHandle
+    Interrupted,
+    // raised while running an imported module, with its path and line
+    InModule(String, Option<usize>, Box<RuntimeErrors>),
+    // a panic inside the interpreter, with its message and the offset and
+    // opcode of the instruction that was running
+    Internal(String, usize, String),
 }
 
 impl fmt::Display for RuntimeErrors {
@@ -48,12 +292,344 @@ impl fmt::Display for RuntimeErrors {
             Self::TypeError(t, v) => write!(f, "Expected a {}, but found value {}", t, v),
             Self::InvalidAddition(v1, v2) => write!(f, "Cannot add {} and {}", v1, v2),
             Self::UndefinedVariable(v) => write!(f, "Value {}, is not defined.", v),
+            Self::NotCallable(v) => write!(f, "Can only call functions, found {}.", v),
+            Self::ArityMismatch(expected, got) => {
+                write!(f, "Expected {} arguments but got {}.", expected, got)
+            }
+            Self::AssertionFailed(msg) => write!(f, "Assertion failed: {}", msg),
+            Self::Panic(msg) => write!(f, "Panic: {}", msg),
+            Self::FormatError(msg) => write!(f, "{}", msg),
+            Self::HostError(msg) => write!(f, "{}", msg),
+            Self::NoProperties(v) => write!(f, "Only objects have properties, found {}.", v),
+            Self::OutOfFuel => write!(f, "Instruction limit reached."),
+            Self::StackOverflow(max) => write!(f, "Stack overflow, limit is {} values.", max),
+            Self::HeapLimitExceeded(max) => write!(f, "Heap limit of {} bytes exceeded.", max),
+            Self::UndefinedProperty(object, name) => {
+                write!(f, "Undefined property '{}' on {}.", name, object)
+            }
+            Self::ImportError(msg) => write!(f, "{}", msg),
+            Self::Exit(code) => write!(f, "Exited with code {}.", code),
+            Self::Interrupted => write!(f, "Interrupted."),
+            Self::Pending(token) => write!(f, "Native call {} is pending.", token),
+            Self::NotResumable(token) => write!(
+                f,
+                "Native call {} suspended outside a resumable run.",
+                token
+            ),
+            Self::Uncaught(value) => write!(f, "Uncaught exception: {}", value),
+            Self::InModule(path, Some(line), error) => {
+                write!(f, "{}\n[line {}] in {}", error, line, path)
+            }
+            Self::InModule(path, None, error) => {
+                write!(f, "{}\nin {} (unknown line)", error, path)
+            }
+            Self::Internal(msg, offset, op) => write!(
+                f,
+                "Internal interpreter error in {} at offset {}: {}",
+                op, offset, msg
+            ),
+        }
+    }
+}
+
+impl RuntimeErrors {
+    // the status passed to `exit()`, also from inside an imported module
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            Self::Exit(code) => Some(*code),
+            Self::InModule(_, _, e) => e.exit_code(),
+            _ => None,
+        }
+    }
+
+    // stable identifier for tools, E01xx for runtime errors, errors raised
+    // inside a module keep the code of the error itself
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TypeError(_, _) => "E0101",
+            Self::UndefinedVariable(_) => "E0102",
+            Self::InvalidAddition(_, _) => "E0103",
+            Self::NotCallable(_) => "E0104",
+            Self::ArityMismatch(_, _) => "E0105",
+            Self::AssertionFailed(_) => "E0106",
+            Self::Panic(_) => "E0107",
+            Self::FormatError(_) => "E0108",
+            Self::HostError(_) => "E0109",
+            Self::NoProperties(_) => "E0110",
+            Self::UndefinedProperty(_, _) => "E0111",
+            Self::OutOfFuel => "E0112",
+            Self::StackOverflow(_) => "E0113",
+            Self::HeapLimitExceeded(_) => "E0114",
+            Self::InvalidOpcode => "E0115",
+            Self::StackUnderFlow => "E0116",
+            Self::Exit(_) => "E0117",
+            Self::Pending(_) => "E0119",
+            Self::Interrupted => "E0120",
+            Self::Uncaught(_) => "E0118",
+            Self::Internal(_, _, _) => "E0121",
+            Self::NotResumable(_) => "E0122",
+            Self::ImportError(e) => e.code(),
+            Self::InModule(_, _, e) => e.code(),
+        }
+    }
+
+    // a suggestion shown under the rendered error
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::UndefinedVariable(_) => Some("declare it with 'var' before using it"),
+            Self::InvalidAddition(_, _) => Some("'+' adds two numbers or joins two strings"),
+            Self::NotCallable(_) => Some("only functions can be called"),
+            Self::NoProperties(_) => Some("only modules and foreign objects have properties"),
+            Self::StackOverflow(_) => Some("check for recursion that never stops"),
+            Self::Uncaught(_) => Some("wrap the code in `try { ... } catch (e) { ... }`"),
+            Self::NotResumable(_) => {
+                Some("run the script with VM::step or VM::run_for to wait for natives")
+            }
+            Self::Internal(_, _, _) => {
+                Some("this is a bug in lox_byte, please report it with the script that ran")
+            }
+            Self::ImportError(e) => e.hint(),
+            Self::InModule(_, _, e) => e.hint(),
+            _ => None,
+        }
+    }
+}
+
+impl Error for RuntimeErrors {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ImportError(e) => Some(e.as_ref()),
+            Self::InModule(_, _, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ModuleErrors {
+    NotFound(String, String),
+    Cycle(Vec<String>),
+    Compile(String, Vec<Diagnostic>),
+}
+
+impl fmt::Display for ModuleErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(path, reason) => write!(f, "Cannot import '{}': {}.", path, reason),
+            Self::Cycle(chain) => write!(f, "Import cycle: {}.", chain.join(" -> ")),
+            Self::Compile(path, _) => write!(f, "Module '{}' failed to compile.", path),
+        }
+    }
+}
+
+impl ModuleErrors {
+    // E02xx, shared by imports failing at compile time and at runtime
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_, _) => "E0201",
+            Self::Cycle(_) => "E0202",
+            Self::Compile(_, _) => "E0203",
+        }
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::NotFound(_, _) => Some(
+                "modules are searched next to the importing file, in the module dirs and in LOX_PATH",
+            ),
+            Self::Cycle(_) => Some("move what both need into a module of its own"),
+            Self::Compile(_, _) => None,
         }
     }
 }
 
+impl Error for ModuleErrors {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Compile(_, errors) => errors.first().map(|e| e as &(dyn Error + 'static)),
+            _ => None,
+        }
+    }
+}
+
+// the first error, and how many followed it
+fn write_compile_errors(f: &mut fmt::Formatter<'_>, errors: &[Diagnostic]) -> fmt::Result {
+    match errors {
+        [] => write!(f, "Compile Error"),
+        [error] => write!(f, "Compile Error: {}", error),
+        [error, rest @ ..] => write!(f, "Compile Error: {} (and {} more)", error, rest.len()),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum VmErrors {
-    CompileError(CompileErrors),
+    CompileError(Vec<Diagnostic>),
     RuntimeError(RuntimeErrors),
 }
+
+impl fmt::Display for VmErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CompileError(errors) => write_compile_errors(f, errors),
+            Self::RuntimeError(e) => write!(f, "Runtime Error: {}", e),
+        }
+    }
+}
+
+impl Error for VmErrors {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CompileError(errors) => errors.first().map(|e| e as &(dyn Error + 'static)),
+            Self::RuntimeError(e) => Some(e),
+        }
+    }
+}
+
+// errors handed to embedders by `VM::eval`
+#[derive(Debug, Clone)]
+pub enum LoxError {
+    Compile(Vec<Diagnostic>),
+    Runtime {
+        error: RuntimeErrors,
+        line: Option<usize>,
+    },
+    // a LoxValue converted to a rust type it does not hold
+    Conversion {
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl LoxError {
+    pub fn from_vm(err: VmErrors, line: Option<usize>) -> Self {
+        match err {
+            VmErrors::CompileError(error) => Self::Compile(error),
+            VmErrors::RuntimeError(error) => Self::Runtime { error, line },
+        }
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Compile(errors) => write_compile_errors(f, errors),
+            Self::Runtime {
+                error,
+                line: Some(line),
+            } => write!(f, "Runtime Error: {} [line {}]", error, line),
+            Self::Runtime { error, line: None } => write!(f, "Runtime Error: {}", error),
+            Self::Conversion { expected, found } => {
+                write!(f, "Expected a {}, but found a {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for LoxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Compile(errors) => errors.first().map(|e| e as &(dyn Error + 'static)),
+            Self::Runtime { error, .. } => Some(error),
+            Self::Conversion { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BytecodeErrors {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    InvalidConstantTag(u8),
+    // offset and byte of an instruction that is not an opcode
+    InvalidOpcode(usize, u8),
+    // offset of an instruction and the constant index it reads past the table
+    InvalidConstant(usize, u8),
+    // offset of a jump and where it lands, off any instruction
+    InvalidJump(usize, isize),
+    InvalidUtf8,
+    UnserializableConstant(String),
+}
+
+impl fmt::Display for BytecodeErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "Not a lox_byte bytecode file."),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported bytecode version {}.", v),
+            Self::Truncated => write!(f, "Bytecode file is truncated."),
+            Self::InvalidConstantTag(t) => write!(f, "Invalid constant tag {}.", t),
+            Self::InvalidOpcode(offset, byte) => {
+                write!(f, "Invalid opcode {:#04x} at offset {}.", byte, offset)
+            }
+            Self::InvalidConstant(offset, index) => {
+                write!(f, "Invalid constant {} at offset {}.", index, offset)
+            }
+            Self::InvalidJump(offset, target) => {
+                write!(
+                    f,
+                    "Jump at offset {} lands on {}, not an instruction.",
+                    offset, target
+                )
+            }
+            Self::InvalidUtf8 => write!(f, "String constant is not valid utf-8."),
+            Self::UnserializableConstant(v) => write!(f, "Cannot serialize constant {}.", v),
+        }
+    }
+}
+
+impl Error for BytecodeErrors {}
+
+// every variant carries the line of the assembly source
+#[derive(Debug, Clone)]
+pub enum AssembleErrors {
+    UnknownOpcode(usize, String),
+    MissingOperand(usize),
+    InvalidOperand(usize, String),
+    TooManyConstants(usize),
+    DuplicateLabel(usize, String),
+    UnknownLabel(usize, String),
+    InvalidJump(usize, String),
+}
+
+impl fmt::Display for AssembleErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode(l, op) => write!(f, "[line {}] Unknown opcode '{}'.", l, op),
+            Self::MissingOperand(l) => write!(f, "[line {}] Missing operand.", l),
+            Self::InvalidOperand(l, v) => write!(f, "[line {}] Invalid operand '{}'.", l, v),
+            Self::TooManyConstants(l) => {
+                write!(f, "[line {}] {}", l, CompileErrors::TooManyConstants)
+            }
+            Self::DuplicateLabel(l, v) => write!(f, "[line {}] Label '{}' already defined.", l, v),
+            Self::UnknownLabel(l, v) => write!(f, "[line {}] Unknown label '{}'.", l, v),
+            Self::InvalidJump(l, v) => write!(f, "[line {}] Cannot jump to '{}'.", l, v),
+        }
+    }
+}
+
+impl Error for AssembleErrors {}
+
+// every variant carries the line of lox.toml
+#[derive(Debug, Clone)]
+pub enum ManifestErrors {
+    Syntax(usize, String),
+    UnknownKey(usize, String),
+    // the key and what its value should have been
+    InvalidValue(usize, String, &'static str),
+    DuplicateKey(usize, String),
+}
+
+impl fmt::Display for ManifestErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(l, v) => write!(f, "[line {}] {}.", l, v),
+            Self::UnknownKey(l, v) => write!(f, "[line {}] Unknown key '{}'.", l, v),
+            Self::InvalidValue(l, v, expected) => {
+                write!(f, "[line {}] '{}' must be {}.", l, v, expected)
+            }
+            Self::DuplicateKey(l, v) => write!(f, "[line {}] Key '{}' already set.", l, v),
+        }
+    }
+}
+
+impl Error for ManifestErrors {}
@@ -0,0 +1,188 @@
+use core::fmt;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    bind::{FromLox, IntoLox},
+    error::{RuntimeErrors, VmErrors},
+    heap::Heap,
+    native::NativeResult,
+    value::Value,
+    vm::VM,
+};
+
+// host data shared between the vm and the embedding application
+pub type ForeignData = Arc<Mutex<dyn Any + Send>>;
+pub type ForeignMethodFn =
+    Arc<dyn Fn(&mut VM, &ForeignData, &[Value]) -> NativeResult + Send + Sync>;
+
+// a rust value living on the lox heap
+#[derive(Clone)]
+pub struct ForeignObj {
+    pub type_name: &'static str,
+    pub type_id: TypeId,
+    pub data: ForeignData,
+}
+
+impl ForeignObj {
+    // runs `f` on the data when it holds a `T`
+    pub fn with<T: 'static, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        lock(&self.data).downcast_mut::<T>().map(f)
+    }
+}
+
+// the same rust value, not merely equal contents
+impl PartialEq for ForeignObj {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+}
+
+impl fmt::Display for ForeignObj {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} instance>", self.type_name)
+    }
+}
+
+impl fmt::Debug for ForeignObj {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ForeignObj({})", self.type_name)
+    }
+}
+
+#[derive(Clone)]
+pub struct ForeignMethod {
+    pub arity: usize,
+    pub function: ForeignMethodFn,
+}
+
+// methods and properties registered for one rust type
+#[derive(Default)]
+pub struct ForeignClass {
+    pub name: &'static str,
+    pub methods: HashMap<&'static str, ForeignMethod>,
+    pub getters: HashMap<&'static str, ForeignMethod>,
+    pub setters: HashMap<&'static str, ForeignMethod>,
+}
+
+impl fmt::Debug for ForeignClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForeignClass")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// returned by `VM::register_foreign`, each call adds to the type's class
+pub struct ForeignBuilder<'vm, T> {
+    class: &'vm mut ForeignClass,
+    marker: std::marker::PhantomData<T>,
+}
+
+impl<'vm, T: 'static> ForeignBuilder<'vm, T> {
+    pub fn new(class: &'vm mut ForeignClass) -> Self {
+        Self {
+            class,
+            marker: std::marker::PhantomData,
+        }
+    }
+
+    // called as `object.name(args)`
+    pub fn method<Args>(self, name: &'static str, method: impl HostMethod<T, Args>) -> Self {
+        self.class.methods.insert(name, erase(method));
+        self
+    }
+
+    // read as `object.name`
+    pub fn getter<R: IntoLox + 'static>(
+        self,
+        name: &'static str,
+        getter: impl Fn(&mut T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.class.getters.insert(name, erase(getter));
+        self
+    }
+
+    // assigned as `object.name = value`
+    pub fn setter<A: FromLox>(
+        self,
+        name: &'static str,
+        setter: impl Fn(&mut T, A) + Send + Sync + 'static,
+    ) -> Self {
+        self.class.setters.insert(name, erase(setter));
+        self
+    }
+}
+
+fn erase<T: 'static, Args>(method: impl HostMethod<T, Args>) -> ForeignMethod {
+    ForeignMethod {
+        arity: method.arity(),
+        function: Arc::new(move |vm, data, args| {
+            let result = {
+                let mut data = lock(data);
+                let receiver = data.downcast_mut::<T>().ok_or_else(|| {
+                    VmErrors::RuntimeError(RuntimeErrors::TypeError(
+                        std::any::type_name::<T>(),
+                        "another foreign object".into(),
+                    ))
+                })?;
+                method.call(receiver, args, &vm.heap)?
+            };
+            result(vm)
+        }),
+    }
+}
+
+// a method that panicked while holding the lock leaves the data as it was
+fn lock(data: &ForeignData) -> MutexGuard<'_, dyn Any + Send> {
+    data.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+type Deferred = Box<dyn FnOnce(&mut VM) -> NativeResult>;
+
+// rust methods taking the receiver first, `Args` is the tuple of the rest
+pub trait HostMethod<T, Args>: Send + Sync + 'static {
+    fn arity(&self) -> usize;
+    // runs with the receiver borrowed, the returned closure converts the
+    // result once that borrow has ended
+    fn call(&self, receiver: &mut T, args: &[Value], heap: &Heap) -> Result<Deferred, VmErrors>;
+}
+
+macro_rules! host_method {
+    ($count: expr; $($arg: ident),*) => {
+        impl<F, T, R, $($arg),*> HostMethod<T, ($($arg,)*)> for F
+        where
+            F: Fn(&mut T, $($arg),*) -> R + Send + Sync + 'static,
+            R: IntoLox + 'static,
+            $($arg: FromLox,)*
+        {
+            fn arity(&self) -> usize {
+                $count
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call(
+                &self,
+                receiver: &mut T,
+                args: &[Value],
+                heap: &Heap,
+            ) -> Result<Deferred, VmErrors> {
+                let mut args = args.iter();
+                $(let $arg = $arg::from_lox(args.next().unwrap(), heap)
+                    .map_err(VmErrors::RuntimeError)?;)*
+                let result = (self)(receiver, $($arg),*);
+                Ok(Box::new(move |vm| result.into_lox(vm)))
+            }
+        }
+    };
+}
+
+host_method!(0;);
+host_method!(1; A);
+host_method!(2; A, B);
+host_method!(3; A, B, C);
+host_method!(4; A, B, C, D);
+host_method!(5; A, B, C, D, E);
@@ -0,0 +1,148 @@
+use crate::{error::RuntimeErrors, heap::Heap, value::Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+// `{}` takes the next argument, `{:spec}` accepts `[[fill]align][0][width][.precision]`
+// in the same shape as rust's format strings, `{{` and `}}` are literal braces
+pub fn format(heap: &Heap, fmt: &str, args: &[Value]) -> Result<String, RuntimeErrors> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = fmt.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut body = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => body.push(c),
+                        None => return Err(error("Unclosed '{' in format string.")),
+                    }
+                }
+                let spec = match body.strip_prefix(':') {
+                    Some(spec) => parse_spec(spec)?,
+                    None if body.is_empty() => Spec::default(),
+                    None => return Err(error(&format!("Invalid placeholder '{{{}}}'.", body))),
+                };
+                let arg = args
+                    .next()
+                    .ok_or_else(|| error("Not enough arguments for format string."))?;
+                out.push_str(&render(arg, &spec, heap));
+            }
+            '}' => return Err(error("Unmatched '}' in format string.")),
+            c => out.push(c),
+        }
+    }
+
+    if args.next().is_some() {
+        return Err(error("Too many arguments for format string."));
+    }
+    Ok(out)
+}
+
+fn error(msg: &str) -> RuntimeErrors {
+    RuntimeErrors::FormatError(msg.to_owned())
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, RuntimeErrors> {
+    let mut parsed = Spec::default();
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+
+    let align = |c: char| match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    };
+    if chars.len() > 1 && align(chars[1]).is_some() {
+        parsed.fill = Some(chars[0]);
+        parsed.align = align(chars[1]);
+        i = 2;
+    } else if let Some(a) = chars.first().and_then(|c| align(*c)) {
+        parsed.align = Some(a);
+        i = 1;
+    }
+
+    if chars.get(i) == Some(&'0') {
+        parsed.zero = true;
+        i += 1;
+    }
+
+    let digits = |i: &mut usize| -> Option<usize> {
+        let start = *i;
+        while chars.get(*i).is_some_and(char::is_ascii_digit) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse().ok()
+    };
+    parsed.width = digits(&mut i).unwrap_or_default();
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        parsed.precision =
+            Some(digits(&mut i).ok_or_else(|| error("Expect a precision after '.'."))?);
+    }
+
+    if i != chars.len() {
+        return Err(error(&format!("Invalid format spec ':{}'.", spec)));
+    }
+    Ok(parsed)
+}
+
+fn render(arg: &Value, spec: &Spec, heap: &Heap) -> String {
+    let text = match (arg, spec.precision) {
+        (Value::Number(n), Some(p)) => format!("{:.*}", p, n),
+        (_, Some(p)) => arg.display(heap).to_string().chars().take(p).collect(),
+        (_, None) => arg.display(heap).to_string(),
+    };
+
+    let len = text.chars().count();
+    if len >= spec.width {
+        return text;
+    }
+    let padding = spec.width - len;
+
+    // zero padding goes between the sign and the digits
+    if spec.zero && spec.align.is_none() && matches!(arg, Value::Number(_)) {
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", text.as_str()),
+        };
+        return format!("{}{}{}", sign, "0".repeat(padding), digits);
+    }
+
+    let default = match arg {
+        Value::Number(_) => Align::Right,
+        _ => Align::Left,
+    };
+    let fill = spec.fill.unwrap_or(' ').to_string();
+    let (left, right) = match spec.align.unwrap_or(default) {
+        Align::Left => (0, padding),
+        Align::Right => (padding, 0),
+        Align::Center => (padding / 2, padding - padding / 2),
+    };
+    format!("{}{}{}", fill.repeat(left), text, fill.repeat(right))
+}
@@ -0,0 +1,235 @@
+use std::{iter, mem, ops::Range};
+
+use crate::{
+    error::{CompileErrors, Diagnostic},
+    scanner::Scanner,
+    token::{TType, Token},
+};
+
+const INDENT: &str = "  ";
+
+// what goes between two tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Gap {
+    None,
+    Space,
+    Newline,
+}
+
+// `lox_byte fmt`: the source laid out again from its tokens, one statement
+// per line, blocks indented, single spaces around operators and after
+// commas. Comments are kept where they were, at the end of a line or on
+// their own, and so are single blank lines between statements. Only a
+// source that scans can be formatted, the tokens themselves never change
+pub fn format_source(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let tokens: Vec<Token> = Scanner::new(source).collect();
+    let errors: Vec<Diagnostic> = tokens
+        .iter()
+        .filter_map(|t| CompileErrors::lexical(t.ttype).map(|e| Diagnostic::new(e, t, source)))
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut formatter = Formatter {
+        source,
+        out: String::with_capacity(source.len()),
+        indent: 0,
+        end: 0,
+        previous: None,
+        recent: Vec::new(),
+        unary: false,
+        parens: 0,
+        braces: Vec::new(),
+        closed_do: false,
+    };
+    for token in &tokens {
+        formatter.token(token);
+    }
+    formatter.comments(&source[formatter.end..]);
+    let mut out = formatter.out;
+    out.truncate(out.trim_end().len());
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    out: String,
+    indent: usize,
+    // where the previous token ended in the source
+    end: usize,
+    previous: Option<TType>,
+    // where the last two tokens are in `out`
+    recent: Vec<Range<usize>>,
+    // the previous token was a prefix operator
+    unary: bool,
+    // parentheses open in the innermost block, inside which ';' separates
+    // a for loop's clauses
+    parens: usize,
+    // for each open brace whether it began a `do` block, closing one ends a
+    // value rather than a statement, and the parentheses open outside it
+    braces: Vec<(bool, usize)>,
+    closed_do: bool,
+}
+
+impl Formatter<'_> {
+    fn token(&mut self, token: &Token) {
+        let gap = &self.source[self.end..token.start];
+        let (broken, newlines) = self.comments(gap);
+        if token.ttype == TType::RightBrace {
+            self.indent = self.indent.saturating_sub(1);
+        }
+        match self.gap(token.ttype, gap) {
+            Gap::Newline => {
+                let blank = newlines > 1
+                    && self.previous != Some(TType::LeftBrace)
+                    && token.ttype != TType::RightBrace;
+                self.newline(blank);
+            }
+            // a comment ended the line inside an expression
+            _ if broken => {
+                self.newline(false);
+                self.write(INDENT);
+            }
+            Gap::None if !self.glues(token) => {}
+            Gap::Space | Gap::None => self.out.push(' '),
+        }
+        let lexeme = token.lexeme.unwrap_or_default();
+        self.write(lexeme);
+        if self.recent.len() == 2 {
+            self.recent.remove(0);
+        }
+        self.recent
+            .push(self.out.len() - lexeme.len()..self.out.len());
+
+        self.unary = match token.ttype {
+            TType::Bang => true,
+            TType::Minus => !self.ends_value(),
+            _ => false,
+        };
+        match token.ttype {
+            TType::LeftParen => self.parens += 1,
+            TType::RightParen => self.parens = self.parens.saturating_sub(1),
+            TType::LeftBrace => {
+                self.indent += 1;
+                let parens = mem::take(&mut self.parens);
+                self.braces.push((self.previous == Some(TType::Do), parens));
+            }
+            TType::RightBrace => {
+                (self.closed_do, self.parens) = self.braces.pop().unwrap_or_default();
+            }
+            _ => {}
+        }
+        self.previous = Some(token.ttype);
+        self.end = token.start + token.len;
+    }
+
+    // writes the comments found between two tokens, returning whether the
+    // line has to be broken after them and how many newlines follow the
+    // last one
+    fn comments(&mut self, gap: &str) -> (bool, usize) {
+        let mut rest = gap;
+        let mut broken = false;
+        while let Some(start) = rest.find("//") {
+            let newlines = rest[..start].matches('\n').count();
+            let comment = rest[start..].lines().next().unwrap_or_default();
+            if newlines > 0 || self.at_line_start() {
+                let blank = newlines > 1 && self.previous != Some(TType::LeftBrace);
+                if !self.at_line_start() {
+                    self.newline(blank);
+                }
+                self.write(comment.trim_end());
+            } else {
+                self.out.push(' ');
+                self.out.push_str(comment.trim_end());
+            }
+            broken = true;
+            rest = &rest[start + comment.len()..];
+        }
+        (broken, rest.matches('\n').count())
+    }
+
+    fn gap(&self, next: TType, source_gap: &str) -> Gap {
+        use TType::*;
+        // nothing before the first token but perhaps comments
+        let Some(previous) = self.previous else {
+            return Gap::Newline;
+        };
+        match (previous, next) {
+            (LeftBrace, RightBrace) => Gap::None,
+            (_, RightBrace) | (LeftBrace, _) => Gap::Newline,
+            (SemiColon, SemiColon | RightParen) if self.parens > 0 => Gap::None,
+            (SemiColon, _) if self.parens > 0 => Gap::Space,
+            // `if (a) b; else c;` stays on one line
+            (SemiColon, Else) => Gap::Space,
+            (SemiColon, _) => Gap::Newline,
+            (RightBrace, Else | Catch) if !self.closed_do => Gap::Space,
+            (RightBrace, _) if !self.closed_do => Gap::Newline,
+            (_, SemiColon | Comma | RightParen | Dot) => Gap::None,
+            (LeftParen | Dot, _) => Gap::None,
+            _ if self.unary => Gap::None,
+            // a call
+            (Identifer | RightParen, LeftParen) => Gap::None,
+            // `print(...)` is a call in modern mode, left as written
+            (Print, LeftParen) if source_gap.is_empty() => Gap::None,
+            _ => Gap::Space,
+        }
+    }
+
+    // whether the last tokens would scan differently with `next` right
+    // after them, as `! =` would become `!=` and `1 . 5` a number
+    fn glues(&self, next: &Token) -> bool {
+        let Some(from) = self.recent.first().map(|range| range.start) else {
+            return false;
+        };
+        let lexeme = next.lexeme.unwrap_or_default();
+        let joined = format!("{}{}", &self.out[from..], lexeme);
+        let expected = self
+            .recent
+            .iter()
+            .map(|range| range.start - from..range.end - from)
+            .chain(iter::once(joined.len() - lexeme.len()..joined.len()));
+        !Scanner::new(&joined)
+            .map(|token| token.start..token.start + token.len)
+            .eq(expected)
+    }
+
+    // whether the previous token can end an operand, making a '-' after it
+    // a subtraction
+    fn ends_value(&self) -> bool {
+        use TType::*;
+        match self.previous {
+            Some(Number | String | Identifer | True | False | Nil | RightParen) => true,
+            Some(RightBrace) => self.closed_do,
+            _ => false,
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn newline(&mut self, blank: bool) {
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        if self.out.is_empty() {
+            return;
+        }
+        if !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        if blank && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.at_line_start() {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+        }
+        self.out.push_str(text);
+    }
+}
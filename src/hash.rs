@@ -0,0 +1,64 @@
+use std::hash::{BuildHasherDefault, Hasher};
+
+// the hasher behind the interned strings and every Table, globals and
+// module globals among them, which are looked up on each global access.
+// std's SipHash resists collisions chosen by an attacker but is slow on
+// short keys; with the `fxhash` feature the faster FxHash is used instead,
+// hashing the same keys the same way on every run
+#[cfg(feature = "fxhash")]
+pub type BuildHash = FxBuildHasher;
+#[cfg(not(feature = "fxhash"))]
+pub type BuildHash = std::collections::hash_map::RandomState;
+
+// rustc's FxHash: each word is mixed in with a rotate, xor and multiply
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FxHasher {
+    hash: u64,
+}
+
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+impl FxHasher {
+    fn add(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.add(u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        let mut rest = chunks.remainder();
+        if rest.len() >= 4 {
+            self.add(u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64);
+            rest = &rest[4..];
+        }
+        for &byte in rest {
+            self.add(byte as u64);
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.add(n as u64);
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.add(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.add(n);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.add(n as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
@@ -0,0 +1,352 @@
+use core::fmt;
+use std::{hash::Hash, marker::PhantomData, mem};
+
+use crate::{
+    foreign::ForeignObj,
+    module::Module,
+    native::NativeFn,
+    value::{InternString, Value},
+};
+
+// everything a Value can point at, stored inline in the heap's slots
+#[derive(Debug)]
+pub enum Obj {
+    String(String),
+    Native(NativeFn),
+    Foreign(ForeignObj),
+    Module(Module),
+}
+
+impl Obj {
+    // bytes charged to the heap for this object: its slot plus what it owns
+    // directly. Foreign data and natives' closures are opaque and count as
+    // nothing beyond the slot
+    pub fn size(&self) -> usize {
+        let owned = match self {
+            Obj::String(content) => content.capacity(),
+            Obj::Native(_) | Obj::Foreign(_) => 0,
+            Obj::Module(module) => {
+                module.name.capacity()
+                    + module.globals.len() * mem::size_of::<(InternString, Value)>()
+            }
+        };
+        mem::size_of::<Slot>() + owned
+    }
+
+    // the name lox gives a value of this object, see Value::type_name
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Obj::String(_) => "string",
+            Obj::Native(_) => "function",
+            Obj::Foreign(_) => "object",
+            Obj::Module(_) => "module",
+        }
+    }
+}
+
+// the types that can live on the heap, so a handle knows what it points at
+pub trait HeapObj: Sized {
+    fn wrap(self) -> Obj;
+    fn unwrap(obj: &Obj) -> Option<&Self>;
+    fn unwrap_mut(obj: &mut Obj) -> Option<&mut Self>;
+}
+
+macro_rules! heap_obj {
+    ($variant: ident, $type: ty) => {
+        impl HeapObj for $type {
+            fn wrap(self) -> Obj {
+                Obj::$variant(self)
+            }
+
+            fn unwrap(obj: &Obj) -> Option<&Self> {
+                match obj {
+                    Obj::$variant(content) => Some(content),
+                    _ => None,
+                }
+            }
+
+            fn unwrap_mut(obj: &mut Obj) -> Option<&mut Self> {
+                match obj {
+                    Obj::$variant(content) => Some(content),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+heap_obj!(String, String);
+heap_obj!(Native, NativeFn);
+heap_obj!(Foreign, ForeignObj);
+heap_obj!(Module, Module);
+
+// slot index plus the generation it was allocated in. A handle kept past
+// the collection that freed its object no longer matches the slot, even
+// once the slot has been reused
+pub struct ObjRef<T> {
+    index: u32,
+    generation: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ObjRef<T> {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+}
+
+// derives would require T: Clone and friends
+impl<T> Clone for ObjRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ObjRef<T> {}
+
+impl<T> PartialEq for ObjRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for ObjRef<T> {}
+
+// interning makes equal strings the same handle, so a string is hashed as
+// its handle, never its content: looking up a global costs one word
+// whatever the length of its name, as clox gets from caching `obj->hash`
+impl<T> Hash for ObjRef<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64((self.generation as u64) << 32 | self.index as u64);
+    }
+}
+
+impl<T> fmt::Debug for ObjRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjRef({}v{})", self.index, self.generation)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    generation: u32,
+    obj: Option<Obj>,
+    // what the object was charged when allocated or last remeasured
+    size: usize,
+}
+
+// every object the vm allocated, owned in one place. Values hold copyable
+// handles into it, and the collector frees slots for reuse
+#[derive(Debug, Default)]
+pub struct Heap {
+    slots: Vec<Slot>,
+    // indices of empty slots, reused before the vector grows
+    free: Vec<u32>,
+    live: usize,
+    // summed size of the live objects, this vm's own and nobody else's
+    bytes: usize,
+    // the most `bytes` has been
+    peak_bytes: usize,
+    // buffers of swept strings, handed to new ones
+    pool: StringPool,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc<T: HeapObj>(&mut self, content: T) -> ObjRef<T> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot::default());
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        let obj = content.wrap();
+        slot.size = obj.size();
+        slot.obj = Some(obj);
+        self.live += 1;
+        self.bytes += slot.size;
+        self.peak_bytes = self.peak_bytes.max(self.bytes);
+        ObjRef {
+            index,
+            generation: slot.generation,
+            marker: PhantomData,
+        }
+    }
+
+    // None once the object has been collected
+    pub fn try_get<T: HeapObj>(&self, handle: ObjRef<T>) -> Option<&T> {
+        let slot = self.slots.get(handle.index())?;
+        match slot.generation == handle.generation {
+            true => slot.obj.as_ref().and_then(T::unwrap),
+            false => None,
+        }
+    }
+
+    // panics on a handle to a collected object, which only a root the
+    // collector did not know about can leave behind
+    pub fn get<T: HeapObj>(&self, handle: ObjRef<T>) -> &T {
+        self.try_get(handle)
+            .expect("handle to an object that was collected")
+    }
+
+    pub fn get_mut<T: HeapObj>(&mut self, handle: ObjRef<T>) -> &mut T {
+        let slot = self
+            .slots
+            .get_mut(handle.index())
+            .filter(|slot| slot.generation == handle.generation);
+        slot.and_then(|slot| slot.obj.as_mut())
+            .and_then(T::unwrap_mut)
+            .expect("handle to an object that was collected")
+    }
+
+    // charges an object again after it grew or shrank through get_mut
+    pub fn remeasure<T: HeapObj>(&mut self, handle: ObjRef<T>) {
+        if let Some(slot) = self.slots.get_mut(handle.index()) {
+            if let (true, Some(obj)) = (slot.generation == handle.generation, &slot.obj) {
+                let size = obj.size();
+                self.bytes = self.bytes - slot.size + size;
+                self.peak_bytes = self.peak_bytes.max(self.bytes);
+                slot.size = size;
+            }
+        }
+    }
+
+    // live objects
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    // every live object with its slot index and size, in slot order
+    pub fn objects(&self) -> impl Iterator<Item = (usize, &Obj, usize)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, slot.obj.as_ref()?, slot.size)))
+    }
+
+    // an empty buffer with room for `len` bytes, reused from a swept string
+    // when the pool has one big enough
+    pub fn take_buffer(&mut self, len: usize) -> String {
+        self.pool.take(len)
+    }
+
+    // returns a buffer that never made it onto the heap
+    pub fn give_buffer(&mut self, buffer: String) {
+        self.pool.give(buffer);
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats
+    }
+
+    // bytes held by live objects
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn peak_bytes(&self) -> usize {
+        self.peak_bytes
+    }
+
+    // slots allocated so far, live or free, the size of a mark table
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    // frees every object whose slot is not marked, returning how many
+    pub fn sweep(&mut self, marked: &[bool]) -> usize {
+        let mut freed = 0;
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.obj.is_some() && !marked.get(index).copied().unwrap_or(false) {
+                if let Some(Obj::String(content)) = slot.obj.take() {
+                    self.pool.give(content);
+                }
+                slot.generation = slot.generation.wrapping_add(1);
+                self.bytes -= mem::take(&mut slot.size);
+                self.free.push(index as u32);
+                freed += 1;
+            }
+        }
+        self.live -= freed;
+        freed
+    }
+}
+
+// smallest and largest size class, 16 bytes to 4 KiB. Bigger buffers go
+// back to the allocator, so the pool holds at most about half a megabyte
+const MIN_CLASS: u32 = 4;
+const MAX_CLASS: u32 = 12;
+const PER_CLASS: usize = 32;
+
+// how often new strings found a buffer to reuse, see Heap::pool_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub reused: usize,
+    // buffers the allocator had to provide
+    pub fresh: usize,
+    // buffers handed back by the collector and kept
+    pub returned: usize,
+}
+
+// free lists of string buffers by size class, class n holding buffers of at
+// least 2^n bytes
+#[derive(Debug, Default)]
+struct StringPool {
+    classes: Vec<Vec<String>>,
+    stats: PoolStats,
+}
+
+impl StringPool {
+    fn take(&mut self, len: usize) -> String {
+        // the smallest class every buffer of which fits `len`
+        let class = len.next_power_of_two().trailing_zeros().max(MIN_CLASS);
+        let reused = self
+            .classes
+            .get_mut(class as usize)
+            .and_then(|buffers| buffers.pop());
+        match reused {
+            Some(buffer) => {
+                self.stats.reused += 1;
+                buffer
+            }
+            // sized to the class so it comes back to the same one
+            None if class <= MAX_CLASS => {
+                self.stats.fresh += 1;
+                String::with_capacity(1 << class)
+            }
+            None => {
+                self.stats.fresh += 1;
+                String::with_capacity(len)
+            }
+        }
+    }
+
+    fn give(&mut self, mut buffer: String) {
+        let capacity = buffer.capacity();
+        if capacity < 1 << MIN_CLASS {
+            return;
+        }
+        // the largest class the buffer fits
+        let class = (usize::BITS - 1 - capacity.leading_zeros()) as usize;
+        if class > MAX_CLASS as usize {
+            return;
+        }
+        if self.classes.len() <= class {
+            self.classes.resize_with(class + 1, Vec::new);
+        }
+        if self.classes[class].len() < PER_CLASS {
+            buffer.clear();
+            self.classes[class].push(buffer);
+            self.stats.returned += 1;
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{error::Span, scanner::Scanner, token::TType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenClass {
+    Keyword,
+    String,
+    Number,
+    Comment,
+    Identifier,
+    Operator,
+}
+
+impl TokenClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Keyword => "keyword",
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Comment => "comment",
+            Self::Identifier => "identifier",
+            Self::Operator => "operator",
+        }
+    }
+
+    fn of(ttype: TType) -> Self {
+        use TType::*;
+        match ttype {
+            Identifer => Self::Identifier,
+            String | UnterminatedStringError => Self::String,
+            Number
+            | MalformedHexError
+            | MalformedBinaryError
+            | MalformedExponentError
+            | MalformedSeparatorError => Self::Number,
+            Comment => Self::Comment,
+            And | Catch | Class | Do | Else | False | True | For | Fun | If | Import | Nil | Or
+            | Print | Return | Super | This | Throw | Try | Var | While => Self::Keyword,
+            // punctuation, and whatever did not scan
+            _ => Self::Operator,
+        }
+    }
+}
+
+// the class of every token in `source`, comments included, for editors and
+// the playground to colour. Unlike the compiler this never fails: a
+// malformed token is classed as what it was trying to be, and the spans
+// cover every non-blank byte but a leading shebang
+pub fn classify(source: &str) -> Vec<(Span, TokenClass)> {
+    Scanner::new(source)
+        .keeping_comments()
+        .map(|token| (Span::from(&token), TokenClass::of(token.ttype)))
+        .collect()
+}
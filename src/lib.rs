@@ -0,0 +1,64 @@
+pub mod assembler;
+pub mod bench;
+pub mod bind;
+pub mod bytecode;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod chunks;
+pub mod clock;
+pub mod compiler;
+pub mod config;
+pub mod coverage;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod foreign;
+pub mod format;
+pub mod formatter;
+pub mod hash;
+pub mod heap;
+pub mod highlight;
+pub mod json;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lsp;
+pub mod macros;
+pub mod manifest;
+pub mod memory;
+pub mod module;
+pub mod native;
+pub mod opcode;
+pub mod output;
+pub mod panic_hook;
+pub mod parse_log;
+pub mod parser;
+pub mod pretty;
+pub mod profiler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
+pub mod report;
+pub mod scanner;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod signal;
+pub mod symbols;
+pub mod table;
+pub mod token;
+pub mod trace;
+pub mod value;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
+
+pub use chunks::Chunk;
+pub use config::{Capability, VmConfig};
+pub use error::{CompileErrors, Diagnostic, LoxError, ModuleErrors, RuntimeErrors, VmErrors};
+pub use memory::GcStats;
+pub use report::{report_compile_errors, report_runtime_error};
+pub use value::{LoxValue, Value};
+pub use vm::{InterruptHandle, StepResult, VM};
+
+// compiles and runs `source` on a fresh vm
+pub fn interpret(source: &str) -> Result<(), VmErrors> {
+    VM::new().interpret(source)
+}
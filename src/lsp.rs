@@ -0,0 +1,382 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    compiler::Compiler,
+    error::{Diagnostic, Span},
+    json::Json,
+    native::Arity,
+    symbols::{Binding, Symbol, Symbols},
+    value::Value,
+    VmConfig, VM,
+};
+
+// a language server over stdio, see the lox-lsp binary. Every open document
+// is compiled again on each change, the diagnostics published and the
+// symbols the compiler recorded kept for definition, hover and outline
+// requests. Positions are lines and utf-16 columns, both from 0
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = response_error(Json::Null, -32700, &e);
+                write_message(&mut output, &error)?;
+                continue;
+            }
+        };
+        if message.get("method").as_str() == Some("exit") {
+            return Ok(if server.shut_down { 0 } else { 1 });
+        }
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    // the client went away without asking
+    Ok(1)
+}
+
+// the body of the next message, None once the input has ended
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn response(id: Json, result: Json) -> Json {
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)])
+}
+
+fn response_error(id: Json, code: i32, message: &str) -> Json {
+    let error = Json::object([
+        ("code", Json::Number(code as f64)),
+        ("message", message.into()),
+    ]);
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+struct Document {
+    text: String,
+    diagnostics: Vec<Diagnostic>,
+    symbols: Symbols,
+}
+
+impl Document {
+    fn new(uri: &str, text: String) -> Self {
+        let config = VmConfig {
+            // stdout carries the protocol
+            dump_failed_chunks: false,
+            ..VmConfig::default()
+        };
+        let mut vm = VM::with_config(config);
+        if let Some(path) = file_path(uri) {
+            // imports resolve next to the file
+            vm.set_script_path(&path);
+        }
+        let mut cc = Compiler::new(&text, &mut vm);
+        cc.symbols = Some(Symbols::default());
+        let diagnostics = cc.compile_chunk().err().unwrap_or_default();
+        let symbols = cc.symbols.take().unwrap_or_default();
+        Self {
+            text,
+            diagnostics,
+            symbols,
+        }
+    }
+
+    fn position(&self, offset: usize) -> Json {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Json::object([
+            ("line", before.matches('\n').count().into()),
+            (
+                "character",
+                before[line_start..].encode_utf16().count().into(),
+            ),
+        ])
+    }
+
+    fn range(&self, span: &Span) -> Json {
+        Json::object([
+            ("start", self.position(span.start)),
+            ("end", self.position(span.start + span.len)),
+        ])
+    }
+
+    // the byte offset of an lsp position, clamped to its line
+    fn offset(&self, position: &Json) -> Option<usize> {
+        let line = position.get("line").as_usize()?;
+        let character = position.get("character").as_usize()?;
+        let mut start = 0;
+        for _ in 0..line {
+            start += self.text[start..].find('\n')? + 1;
+        }
+        let text = self.text[start..].lines().next().unwrap_or_default();
+        let mut units = 0;
+        for (index, char) in text.char_indices() {
+            if units >= character {
+                return Some(start + index);
+            }
+            units += char.len_utf16();
+        }
+        Some(start + text.len())
+    }
+
+    fn location(&self, uri: &str, span: &Span) -> Json {
+        Json::object([("uri", uri.into()), ("range", self.range(span))])
+    }
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    // the arity of each native a script can call
+    natives: HashMap<String, Arity>,
+    shut_down: bool,
+}
+
+impl Server {
+    fn new() -> Self {
+        let vm = VM::new();
+        let natives = vm
+            .globals
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::Native(native) => {
+                    Some((vm.heap.get(*name).clone(), vm.heap.get(*native).arity))
+                }
+                _ => None,
+            })
+            .collect();
+        Self {
+            documents: HashMap::new(),
+            natives,
+            shut_down: false,
+        }
+    }
+
+    // the replies to one message, responses and notifications alike
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let id = message.get("id").clone();
+        let params = message.get("params");
+        let uri = params
+            .get("textDocument")
+            .get("uri")
+            .as_str()
+            .unwrap_or_default();
+        let result = match message.get("method").as_str().unwrap_or_default() {
+            "initialize" => Self::capabilities(),
+            "shutdown" => {
+                self.shut_down = true;
+                Json::Null
+            }
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str();
+                return self.update(uri, text.unwrap_or_default().into());
+            }
+            // the server asks for full syncs, so the last change is the text
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").as_array();
+                match changes
+                    .last()
+                    .and_then(|change| change.get("text").as_str())
+                {
+                    Some(text) => return self.update(uri, text.into()),
+                    None => return Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![Self::publish(uri, Vec::new())];
+            }
+            "textDocument/definition" => self.definition(uri, params.get("position")),
+            "textDocument/hover" => self.hover(uri, params.get("position")),
+            "textDocument/documentSymbol" => self.document_symbols(uri),
+            _ if id == Json::Null => return Vec::new(),
+            method => {
+                let error = format!("Unknown method '{}'", method);
+                return vec![response_error(id, -32601, &error)];
+            }
+        };
+        match id {
+            // a notification gets no response
+            Json::Null => Vec::new(),
+            id => vec![response(id, result)],
+        }
+    }
+
+    fn capabilities() -> Json {
+        let capabilities = Json::object([
+            // full text on every change
+            ("textDocumentSync", 1.into()),
+            ("definitionProvider", true.into()),
+            ("hoverProvider", true.into()),
+            ("documentSymbolProvider", true.into()),
+        ]);
+        let info = Json::object([
+            ("name", "lox-lsp".into()),
+            ("version", env!("CARGO_PKG_VERSION").into()),
+        ]);
+        Json::object([("capabilities", capabilities), ("serverInfo", info)])
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> Vec<Json> {
+        let document = Document::new(uri, text);
+        let diagnostics = document
+            .diagnostics
+            .iter()
+            .map(|e| {
+                let mut message = e.error.to_string();
+                if let Some(hint) = e.error.hint() {
+                    message.push_str("\nhelp: ");
+                    message.push_str(hint);
+                }
+                Json::object([
+                    ("range", document.range(&e.span)),
+                    // error
+                    ("severity", 1.into()),
+                    ("code", e.error.code().into()),
+                    ("source", "lox_byte".into()),
+                    ("message", message.into()),
+                ])
+            })
+            .collect();
+        self.documents.insert(uri.into(), document);
+        vec![Self::publish(uri, diagnostics)]
+    }
+
+    fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+        let params = Json::object([
+            ("uri", uri.into()),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]);
+        notification("textDocument/publishDiagnostics", params)
+    }
+
+    fn symbol_at(&self, uri: &str, position: &Json) -> Option<(&Document, Symbol<'_>)> {
+        let document = self.documents.get(uri)?;
+        let symbol = document.symbols.at(document.offset(position)?)?;
+        Some((document, symbol))
+    }
+
+    fn definition(&self, uri: &str, position: &Json) -> Json {
+        let Some((document, symbol)) = self.symbol_at(uri, position) else {
+            return Json::Null;
+        };
+        let definition = match symbol {
+            Symbol::Definition(definition) => Some(definition),
+            Symbol::Reference(reference) => document.symbols.definition_of(reference),
+        };
+        definition.map_or(Json::Null, |d| document.location(uri, &d.span))
+    }
+
+    fn hover(&self, uri: &str, position: &Json) -> Json {
+        let Some((document, symbol)) = self.symbol_at(uri, position) else {
+            return Json::Null;
+        };
+        let (name, span, definition) = match symbol {
+            Symbol::Definition(d) => (&d.name, &d.span, Some(d)),
+            Symbol::Reference(r) => (&r.name, &r.span, document.symbols.definition_of(r)),
+        };
+        let text = match (definition.map(|d| d.binding), self.natives.get(name)) {
+            (Some(Binding::Local(slot)), _) => format!("local `{}` in slot {}", name, slot),
+            (Some(Binding::Global), _) => format!("global `{}`", name),
+            (Some(Binding::Module), _) => format!("module `{}`", name),
+            (None, Some(arity)) => format!("native fn `{}`, arity {}", name, arity),
+            (None, None) => format!("global `{}`, not declared in this file", name),
+        };
+        let contents = Json::object([("kind", "markdown".into()), ("value", text.into())]);
+        Json::object([("contents", contents), ("range", document.range(span))])
+    }
+
+    // the globals and modules a document declares, in order
+    fn document_symbols(&self, uri: &str) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let symbols = document
+            .symbols
+            .definitions
+            .iter()
+            .filter_map(|d| {
+                // lsp SymbolKind
+                let kind = match d.binding {
+                    Binding::Global => 13,
+                    Binding::Module => 2,
+                    Binding::Local(_) => return None,
+                };
+                Some(Json::object([
+                    ("name", d.name.as_str().into()),
+                    ("kind", kind.into()),
+                    ("location", document.location(uri, &d.span)),
+                ]))
+            })
+            .collect();
+        Json::Array(symbols)
+    }
+}
+
+// the path of a file: uri, with its percent escapes decoded
+fn file_path(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escape = encoded
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (encoded[i], escape) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
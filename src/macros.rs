@@ -1,4 +1,57 @@
 use core::fmt;
+use std::{
+    env,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ColorMode {
+    // colored only when stdout is a terminal and NO_COLOR is unset
+    Auto,
+    Always,
+    Never,
+}
+
+impl TryFrom<&str> for ColorMode {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            other => Err(format!(
+                "Unknown color mode '{}', expected auto, always or never",
+                other
+            )),
+        }
+    }
+}
+
+static COLOR_MODE: AtomicU8 = AtomicU8::new(ColorMode::Auto as u8);
+
+pub fn set_color_mode(mode: ColorMode) {
+    COLOR_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn colors_enabled() -> bool {
+    colors_for(io::stdout().is_terminal())
+}
+
+// the same decision for an output stream other than stdout
+pub fn colors_for(terminal: bool) -> bool {
+    match COLOR_MODE.load(Ordering::Relaxed) {
+        mode if mode == ColorMode::Always as u8 => true,
+        mode if mode == ColorMode::Never as u8 => false,
+        // https://no-color.org: any non-empty value disables color
+        _ => {
+            !cfg!(target_arch = "wasm32")
+                && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && terminal
+        }
+    }
+}
 
 pub enum TermColor {
     NoColor,
@@ -48,7 +101,11 @@ impl fmt::Display for TermColor {
 macro_rules! cprintln {
     ($color: ident, $($args:tt)*) => {{
         use $crate::macros::TermColor;
-        println!("{}{}{}", TermColor::$color, format!($($args)*), TermColor::NoColor);
+        if $crate::macros::colors_enabled() {
+            println!("{}{}{}", TermColor::$color, format!($($args)*), TermColor::NoColor);
+        } else {
+            println!("{}", format!($($args)*));
+        }
     }};
 }
 
@@ -56,6 +113,77 @@ macro_rules! cprintln {
 macro_rules! cprint {
     ($color: ident, $($args:tt)*) => {{
         use $crate::macros::TermColor;
-        print!("{}{}{}", TermColor::$color, format!($($args)*), TermColor::NoColor);
+        if $crate::macros::colors_enabled() {
+            print!("{}{}{}", TermColor::$color, format!($($args)*), TermColor::NoColor);
+        } else {
+            print!("{}", format!($($args)*));
+        }
     }};
 }
+
+// formats into a String, wrapped in `color` only when `enabled` is true
+#[macro_export]
+macro_rules! cformat {
+    ($enabled: expr, $color: ident, $($args:tt)*) => {{
+        use $crate::macros::TermColor;
+        if $enabled {
+            format!("{}{}{}", TermColor::$color, format!($($args)*), TermColor::NoColor)
+        } else {
+            format!($($args)*)
+        }
+    }};
+}
+
+// a fieldless enum numbered by declaration order, with both conversions to
+// and from its integer type generated from the variant list so reordering
+// variants can never make a decode produce the wrong one
+//
+//     int_enum! {
+//         #[derive(Debug, Clone, Copy)]
+//         pub enum Mode: u8, Err(ModeError) = ModeError::Unknown {
+//             Read,
+//             Write,
+//         }
+//     }
+#[macro_export]
+macro_rules! int_enum {
+    (
+        $(#[$meta: meta])*
+        $vis: vis enum $name: ident: $repr: ident, Err($error: ty) = $invalid: path {
+            $($(#[$variant_meta: meta])* $variant: ident,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr($repr)]
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl $name {
+            // every variant, in discriminant order
+            pub const ALL: &'static [$name] = &[$($name::$variant,)*];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $($name::$variant => stringify!($variant),)*
+                }
+            }
+        }
+
+        impl From<$name> for $repr {
+            fn from(value: $name) -> Self {
+                value as $repr
+            }
+        }
+
+        impl TryFrom<$repr> for $name {
+            type Error = $error;
+            fn try_from(value: $repr) -> Result<Self, Self::Error> {
+                $(if value == $name::$variant as $repr {
+                    return Ok($name::$variant);
+                })*
+                Err($invalid)
+            }
+        }
+    };
+}
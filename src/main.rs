@@ -1,79 +1,625 @@
 use std::{
     env,
-    fs::File,
-    io::{self, Read, Write},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
     process,
 };
 
-use vm::VM;
+use lox_byte::{
+    assembler,
+    bench::{self, Summary},
+    bytecode,
+    clock::Clock,
+    compiler::Compiler,
+    coverage::Coverage,
+    cprintln,
+    disassembler::Disassembler,
+    formatter,
+    macros::{set_color_mode, ColorMode},
+    manifest::{Manifest, MANIFEST},
+    memory,
+    output::Sink,
+    panic_hook,
+    parse_log::ParseLog,
+    profiler::{time_report, Profile},
+    repl,
+    report::ErrorFormat,
+    report_compile_errors, report_runtime_error,
+    scanner::{decode_lossy, Scanner},
+    trace::Tracer,
+    watch::Watched,
+    VmErrors, VM,
+};
 
-use crate::error::VmErrors;
+// as a file name, the program is read from stdin
+const STDIN: &str = "-";
+// and what diagnostics call it
+const STDIN_NAME: &str = "<stdin>";
 
-pub mod chunks;
-pub mod compiler;
-pub mod disassembler;
-pub mod error;
-pub mod macros;
-pub mod memory;
-pub mod opcode;
-pub mod parser;
-pub mod scanner;
-pub mod token;
-pub mod value;
-pub mod vm;
+// the first argument when it names a mode rather than a script
+const MODES: &[&str] = &[
+    "run", "lex", "disasm", "asm", "compile", "fmt", "bench", "watch",
+];
 
 fn main() {
-    let mut vm = VM::new();
+    panic_hook::install();
+    // flags given on the command line override the project's lox.toml
+    let manifest = project_manifest();
+    let config = manifest.as_ref().map(|m| m.config.clone());
+    let mut vm = VM::with_config(config.unwrap_or_default());
+    let mut strip = false;
+    let mut reports = Reports::default();
+
+    // everything after `--` goes to the script untouched, flags included
+    let mut args: Vec<String> = env::args().collect();
+    let mut script_args = match args.iter().position(|arg| arg == "--") {
+        Some(index) => {
+            let rest = args.split_off(index + 1);
+            args.pop();
+            rest
+        }
+        None => Vec::new(),
+    };
+
+    // --color=auto|always|never, --error-format=human|json, --strict-compat,
+    // --modern, --chain-comparisons, --debug-info, --strip-debug-info,
+    // --gc-stress, --gc-log, --heap-dump-at-exit and --time may appear
+    // anywhere on the command line before `--`
+    let args: Vec<String> = args
+        .into_iter()
+        .filter(|arg| {
+            if let Some(mode) = arg.strip_prefix("--color=") {
+                match ColorMode::try_from(mode) {
+                    Ok(mode) => set_color_mode(mode),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(64)
+                    }
+                }
+                false
+            } else if let Some(format) = arg.strip_prefix("--error-format=") {
+                match ErrorFormat::try_from(format) {
+                    Ok(format) => vm.error_format = format,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(64)
+                    }
+                }
+                false
+            } else if arg == "--strict-compat" {
+                vm.config.strict_compat = true;
+                false
+            } else if arg == "--modern" {
+                vm.config.modern = true;
+                false
+            } else if arg == "--chain-comparisons" {
+                vm.config.chain_comparisons = true;
+                false
+            } else if arg == "--debug-info" {
+                vm.config.debug_info = true;
+                false
+            } else if arg == "--strip-debug-info" {
+                strip = true;
+                false
+            } else if arg == "--gc-stress" {
+                vm.config.gc_stress = true;
+                false
+            } else if arg == "--gc-log" {
+                vm.config.gc_log = true;
+                false
+            } else if arg == "--heap-dump-at-exit" {
+                reports.heap_dump = true;
+                false
+            } else if arg == "--time" {
+                reports.time = true;
+                false
+            } else {
+                true
+            }
+        })
+        .collect();
+    match args.as_slice() {
+        [] | [_] if io::stdin().is_terminal() => repl::repl(&mut vm),
+        // a program piped in
+        [] | [_] => run_file(STDIN, reports, &mut vm),
+        [_, flag, snippet] if flag == "-e" || flag == "--eval" => eval(snippet, &mut vm),
+        [_, mode, file] if mode == "lex" => lex_file(file),
+        [_, mode, file] if mode == "disasm" => disasm_file(file, &mut vm),
+        [_, mode] if mode == "run" => match manifest.and_then(|m| m.entry) {
+            Some(entry) => {
+                vm.script_args = script_args;
+                run_file(&entry.display().to_string(), reports, &mut vm)
+            }
+            None => {
+                eprintln!("Nothing to run, give a file or set `entry` in {}", MANIFEST);
+                process::exit(64)
+            }
+        },
+        [_, mode, file] if mode == "watch" => watch_file(file, &vm),
+        [_, flag, file] if flag == "--profile" => profile_file(file, &mut vm),
+        [_, flag, file] if flag == "--count-ops" => count_ops_file(file, &mut vm),
+        [_, flag, file] if flag == "--dump-parse" => dump_parse_file(file, &mut vm),
+        [_, flag, file] if flag == "--coverage" => coverage_file(file, false, &mut vm),
+        [_, flag, file] if flag == "--coverage=lcov" => coverage_file(file, true, &mut vm),
+        [_, flag, file] if flag == "--trace" || flag.starts_with("--trace=") => {
+            let spec = flag.trim_start_matches("--trace").trim_start_matches('=');
+            match Tracer::from_spec(spec) {
+                Ok(tracer) => vm.tracer = Some(tracer),
+                Err(e) => {
+                    eprintln!("Trace Error: {}", e);
+                    process::exit(74)
+                }
+            }
+            run_file(file, reports, &mut vm)
+        }
+        [_, mode, file] if mode == "asm" => asm_file(file, &mut vm),
+        [_, mode, input] if mode == "compile" => compile_file(input, None, strip, &mut vm),
+        [_, mode, input, flag, output] if mode == "compile" && flag == "-o" => {
+            compile_file(input, Some(output), strip, &mut vm)
+        }
+        [_, mode, file] if mode == "bench" => bench_file(file, 10, &mut vm),
+        [_, mode, file, flag, count] if mode == "bench" && flag == "--iterations" => {
+            match count.parse() {
+                Ok(iterations) if iterations > 0 => bench_file(file, iterations, &mut vm),
+                _ => {
+                    eprintln!("--iterations takes a count above 0, not '{}'", count);
+                    process::exit(64)
+                }
+            }
+        }
+        [_, mode, args @ ..] if mode == "fmt" && !args.is_empty() => fmt_files(args, &mut vm),
+        // the arguments after a script are its own
+        [_, mode, file, rest @ ..] if mode == "run" => {
+            script_args.splice(0..0, rest.iter().cloned());
+            vm.script_args = script_args;
+            run_file(file, reports, &mut vm)
+        }
+        [_, file, rest @ ..]
+            if !MODES.contains(&file.as_str()) && (file == STDIN || !file.starts_with('-')) =>
+        {
+            script_args.splice(0..0, rest.iter().cloned());
+            vm.script_args = script_args;
+            run_file(file, reports, &mut vm)
+        }
+        _ => {
+            cprintln!(
+                LightRed,
+                "Usage: lox_byte [run|lex|disasm|asm|watch] [file_name]"
+            );
+            cprintln!(
+                LightRed,
+                "       lox_byte [run] <file_name> [args...] [-- args...], read with args() and args(i)"
+            );
+            cprintln!(
+                LightRed,
+                "       a file name of - reads the program from stdin, as does piping it in"
+            );
+            cprintln!(
+                LightRed,
+                "       lox_byte run, in a project, runs the entry of its {}",
+                MANIFEST
+            );
+            cprintln!(
+                LightRed,
+                "       lox_byte compile <file_name> [-o <out.loxbc>] [--strip-debug-info]"
+            );
+            cprintln!(LightRed, "       lox_byte -e|--eval <source>");
+            cprintln!(LightRed, "       lox_byte fmt [--check] <file_name>...");
+            cprintln!(
+                LightRed,
+                "       lox_byte bench <file_name> [--iterations <count>]"
+            );
+            cprintln!(
+                LightRed,
+                "       --color=auto|always|never may be added to any mode"
+            );
+            cprintln!(
+                LightRed,
+                "       --error-format=human|json may be added to any mode"
+            );
+            cprintln!(
+                LightRed,
+                "       --strict-compat keeps runtime errors uncatchable"
+            );
+            cprintln!(
+                LightRed,
+                "       --modern makes print a native function, print(\"x\", y)"
+            );
+            cprintln!(
+                LightRed,
+                "       --chain-comparisons reads a < b < c as a < b and b < c"
+            );
+            cprintln!(
+                LightRed,
+                "       --debug-info records the source span of each instruction"
+            );
+            cprintln!(
+                LightRed,
+                "       --gc-stress collects before every allocation, --gc-log reports each collection"
+            );
+            cprintln!(
+                LightRed,
+                "       --heap-dump-at-exit lists the objects still on the heap when a run ends"
+            );
+            cprintln!(
+                LightRed,
+                "       --time reports compile and run time, peak stack and heap, and gc pauses"
+            );
+            cprintln!(LightRed, "       lox_byte --profile <file_name>");
+            cprintln!(LightRed, "       lox_byte --count-ops <file_name>");
+            cprintln!(LightRed, "       lox_byte --dump-parse <file_name>");
+            cprintln!(LightRed, "       lox_byte --coverage[=lcov] <file_name>");
+            cprintln!(
+                LightRed,
+                "       lox_byte --trace[=stderr|json|<log_file>] <file_name>"
+            );
+        }
+    }
+}
+
+// the nearest lox.toml above the working directory, if there is one
+fn project_manifest() -> Option<Manifest> {
+    let path = Manifest::find(&env::current_dir().ok()?)?;
+    match Manifest::load(&path) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            eprintln!("Manifest Error: {}: {}", path.display(), e);
+            process::exit(78)
+        }
+    }
+}
+
+fn open_file(path: &str) -> File {
+    File::open(path).unwrap_or_else(|e| {
+        eprintln!("IO Error: Cannot open '{}': {}", path, e);
+        process::exit(66)
+    })
+}
 
-    let mut args = env::args();
-    match args.len() {
-        0..=1 => repl(&mut vm),
-        2 => {
-            let file = args.nth(1).unwrap_or_default();
-            run_file(file, &mut vm);
+// bytes that aren't UTF-8 are kept as U+FFFD, with where they are so the
+// scanner can point at them
+fn read_source(path: &str) -> (String, Vec<usize>) {
+    let mut buffer = Vec::new();
+    if path == STDIN {
+        if let Err(e) = io::stdin().read_to_end(&mut buffer) {
+            eprintln!("IO Error: Cannot read stdin: {}", e);
+            process::exit(74)
         }
-        _ => cprintln!(LightRed, "Usage: lox_byte [file_name]"),
+    } else {
+        let mut file = open_file(path);
+        file.read_to_end(&mut buffer).expect("Failed to read file");
     }
+    decode_lossy(&buffer)
 }
 
-fn repl(vm: &mut VM) {
-    let mut buffer = String::new();
-    cprintln!(LightPurple, "{}", "Welcome to lox_byte repl.");
-    cprintln!(Purple, "\tExit -> ctrl + d");
+// compiles a source file into the vm, reporting invalid UTF-8 in it
+fn compile_source(path: &str, vm: &mut VM) -> Result<(), VmErrors> {
+    let (source, invalid_utf8) = read_source(path);
+    Compiler::new(&source, vm)
+        .invalid_utf8_at(invalid_utf8)
+        .compile()
+        .map_err(VmErrors::CompileError)
+}
+
+// written to stderr once a run ends, however it ends
+#[derive(Debug, Clone, Copy, Default)]
+struct Reports {
+    heap_dump: bool,
+    time: bool,
+}
+
+// accepts either lox source or a compiled .loxbc file
+fn run_file(path: &str, reports: Reports, vm: &mut VM) {
+    let clock = Clock::now();
+    let loaded = load_file(path, vm);
+    let compile = clock.elapsed();
+    let clock = Clock::now();
+    let result = loaded.and_then(|()| vm.run());
+    let run = clock.elapsed();
+    let _ = vm.out.flush();
+    if reports.heap_dump {
+        let dump = memory::heap_dump(vm);
+        let _ = vm.diagnostics.write_all(dump.as_bytes());
+    }
+    if reports.time {
+        let report = time_report(vm, compile, run);
+        let _ = vm.diagnostics.write_all(report.as_bytes());
+    }
+    exit_with(vm, result);
+}
+
+// runs the script on a fresh vm configured like `vm` each time it or a
+// module it imports is saved, clearing the screen first. Errors are
+// reported and the watching goes on, Ctrl-C stops it
+fn watch_file(path: &str, vm: &VM) {
     loop {
-        cprint!(LightCyan, "{}", "lox");
-        cprint!(LightBlue, " > ");
-        io::stdout().flush().expect("Failed to flush stdout");
-        buffer.clear();
-        match io::stdin().read_line(&mut buffer) {
-            Ok(0) => {
-                println!();
-                cprintln!(Cyan, "Exiting... Goodbye...");
-                process::exit(0);
-            }
-            Ok(_) => vm.interpret(&buffer).unwrap_or(()),
-            Err(err) => {
-                eprintln!("failed to get input: {}", err);
-                continue;
+        if io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+        }
+        let mut run = VM::with_config(vm.config.clone());
+        run.error_format = vm.error_format;
+        let result = execute_file(path, &mut run);
+        let _ = run.out.flush();
+        match result {
+            Ok(()) => {}
+            Err(VmErrors::CompileError(errors)) => report_compile_errors(&mut run, &errors),
+            // a script that exits has finished this run, not the watch
+            Err(VmErrors::RuntimeError(e)) if e.exit_code().is_some() => {}
+            Err(VmErrors::RuntimeError(e)) => report_runtime_error(&mut run, &e),
+        }
+        let watched = Watched::script(path, &run);
+        let imports = match watched.len() - 1 {
+            0 => String::new(),
+            1 => " and 1 import".into(),
+            n => format!(" and {} imports", n),
+        };
+        cprintln!(Gray, "watching {}{}, Ctrl-C to stop", path, imports);
+        watched.wait();
+    }
+}
+
+fn execute_file(path: &str, vm: &mut VM) -> Result<(), VmErrors> {
+    load_file(path, vm)?;
+    vm.run()
+}
+
+// compiles or deserializes a script into the vm, ready to run
+fn load_file(path: &str, vm: &mut VM) -> Result<(), VmErrors> {
+    // read whole, it can't be read again to show the lines around errors.
+    // Diagnostics name it, imports are looked up from the working directory
+    if path == STDIN {
+        vm.modules.script = Some(STDIN_NAME.into());
+        return compile_source(path, vm);
+    }
+    vm.set_script_path(path);
+    let file = open_file(path);
+    let mut reader = BufReader::new(file);
+    let head = reader.fill_buf().expect("Failed to read file");
+    if bytecode::is_bytecode(head) {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).expect("Failed to read file");
+        match bytecode::deserialize(vm, &bytes) {
+            Ok(chunk) => vm.chunks = chunk,
+            Err(e) => {
+                eprintln!("Load Error: {}", e);
+                process::exit(65)
             }
         }
+    } else {
+        compile_stream(reader, vm)?;
     }
+    Ok(())
 }
 
-fn run_file(path: String, vm: &mut VM) {
-    let mut file = File::open(path).expect("Failed to open file");
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer)
-        .expect("Failed to read file");
-    match vm.interpret(&buffer) {
+// source files are compiled as they are read instead of loaded whole
+fn compile_stream(mut reader: impl Read, vm: &mut VM) -> Result<(), VmErrors> {
+    let mut cc = Compiler::incremental(vm);
+    let mut block = vec![0; 64 * 1024];
+    // bytes of a char split across two reads
+    let mut carry = Vec::new();
+    loop {
+        let read = reader.read(&mut block).expect("Failed to read file");
+        if read == 0 {
+            break;
+        }
+        carry.extend_from_slice(&block[..read]);
+        // an incomplete char at the end waits for the next read, invalid
+        // bytes become U+FFFD and are reported by the scanner
+        let complete = match std::str::from_utf8(&carry) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => carry.len(),
+        };
+        let rest = carry.split_off(complete);
+        let (text, invalid_utf8) = decode_lossy(&carry);
+        cc.feed_decoded(&text, &invalid_utf8);
+        carry = rest;
+    }
+    if !carry.is_empty() {
+        let (text, invalid_utf8) = decode_lossy(&carry);
+        cc.feed_decoded(&text, &invalid_utf8);
+    }
+    cc.finish().map_err(VmErrors::CompileError)
+}
+
+// times `iterations` runs of a script, each on a fresh vm configured like
+// `vm`, then as many on `vm` itself, where globals, interned strings and
+// imported modules carry over. What the script prints is discarded
+fn bench_file(path: &str, iterations: usize, vm: &mut VM) {
+    let (source, _) = read_source(path);
+    let mut fresh = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let mut run = VM::with_config(vm.config.clone());
+        run.error_format = vm.error_format;
+        run.set_script_path(path);
+        run.out = Sink::new(io::sink());
+        match bench::sample(&mut run, &source) {
+            Ok(sample) => fresh.push(sample),
+            Err(e) => exit_with(&mut run, Err(e)),
+        }
+    }
+    vm.set_script_path(path);
+    vm.out = Sink::new(io::sink());
+    let mut shared = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        match bench::sample(vm, &source) {
+            Ok(sample) => shared.push(sample),
+            Err(e) => exit_with(vm, Err(e)),
+        }
+    }
+    println!("==bench {}==", path);
+    println!("fresh vm   {}", Summary::of(&fresh));
+    println!("shared vm  {}", Summary::of(&shared));
+}
+
+fn profile_file(path: &str, vm: &mut VM) {
+    vm.profile = Some(Profile::default());
+    let result = execute_file(path, vm);
+    if let Some(profile) = &vm.profile {
+        profile.report();
+    }
+    exit_with(vm, result);
+}
+
+// reported even when the script fails, counting up to the error
+fn count_ops_file(path: &str, vm: &mut VM) {
+    vm.op_counts = Some(Box::default());
+    let result = execute_file(path, vm);
+    if let Some(op_counts) = &vm.op_counts {
+        op_counts.report();
+    }
+    exit_with(vm, result);
+}
+
+// annotated source goes to stderr, lcov is written to ./lcov.info
+fn coverage_file(path: &str, lcov: bool, vm: &mut VM) {
+    vm.profile = Some(Profile::default());
+    let result = execute_file(path, vm);
+    let bytes = fs::read(path).expect("Failed to read file");
+    let source = match bytecode::is_bytecode(&bytes) {
+        true => None,
+        false => Some(String::from_utf8_lossy(&bytes).into_owned()),
+    };
+    let profile = vm.profile.take().unwrap_or_default();
+    let coverage = Coverage::new(&vm.chunks, &profile, source.as_deref());
+    match (lcov, source) {
+        (true, _) => {
+            fs::write("lcov.info", coverage.lcov(path)).expect("Failed to write lcov.info")
+        }
+        (false, Some(source)) => eprint!("{}", coverage.annotate(&source)),
+        (false, None) => {
+            eprintln!("Coverage Error: annotated output needs the source file, try --coverage=lcov")
+        }
+    }
+    exit_with(vm, result);
+}
+
+// `strip` leaves line and span tables out of the file
+fn compile_file(input: &str, output: Option<&String>, strip: bool, vm: &mut VM) {
+    vm.set_script_path(input);
+    if let Err(VmErrors::CompileError(errors)) = compile_source(input, vm) {
+        report_compile_errors(vm, &errors);
+        process::exit(65)
+    }
+    let output = match output {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(input).with_extension("loxbc"),
+    };
+    if strip {
+        vm.chunks.strip_debug_info();
+    }
+    let bytes = match bytecode::serialize(&vm.chunks, &vm.heap) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Compile Error: {}", e);
+            process::exit(65)
+        }
+    };
+    fs::write(&output, bytes).expect("Failed to write bytecode file");
+}
+
+fn eval(snippet: &str, vm: &mut VM) {
+    let result = vm.interpret_echo(snippet);
+    exit_with(vm, result);
+}
+
+fn exit_with(vm: &mut VM, result: Result<(), VmErrors>) {
+    let _ = vm.out.flush();
+    match result {
         Ok(()) => process::exit(0),
-        Err(VmErrors::CompileError(e)) => {
-            println!("Compile Error: {}", e);
-            process::exit(69)
+        Err(VmErrors::CompileError(errors)) => {
+            report_compile_errors(vm, &errors);
+            process::exit(65)
+        }
+        Err(VmErrors::RuntimeError(e)) => match e.exit_code() {
+            Some(code) => process::exit(code),
+            None => {
+                report_runtime_error(vm, &e);
+                process::exit(70)
+            }
+        },
+    }
+}
+
+// one token per line: `line:column type lexeme`
+fn lex_file(path: &str) {
+    let (buffer, invalid_utf8) = read_source(path);
+    for token in Scanner::new(&buffer).invalid_utf8_at(invalid_utf8) {
+        println!(
+            "{:>4}:{:<4} {:<24} {}",
+            token.line,
+            token.column,
+            format!("{:?}", token.ttype),
+            token.lexeme.unwrap_or_default()
+        );
+    }
+}
+
+// rewrites each file formatted, or with --check only names those that
+// would change and fails if there are any
+fn fmt_files(args: &[String], vm: &mut VM) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let mut unformatted = false;
+    for path in args.iter().filter(|arg| *arg != "--check") {
+        let (buffer, _) = read_source(path);
+        let formatted = match formatter::format_source(&buffer) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                vm.set_script_path(path);
+                report_compile_errors(vm, &errors);
+                process::exit(65)
+            }
+        };
+        if formatted == buffer {
+            continue;
+        }
+        if check {
+            cprintln!(LightRed, "{} is not formatted", path);
+            unformatted = true;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("IO Error: Cannot write '{}': {}", path, e);
+            process::exit(74)
         }
-        Err(VmErrors::RuntimeError(e)) => {
-            println!("Compile Error: {}", e);
-            process::exit(69)
+    }
+    process::exit(if unformatted { 1 } else { 0 })
+}
+
+// the rules the compiler entered, the tokens it consumed and the code it
+// emitted, as a tree. Printed even when compiling fails, up to the errors
+fn dump_parse_file(path: &str, vm: &mut VM) {
+    let (buffer, invalid_utf8) = read_source(path);
+    vm.set_script_path(path);
+    // the tree already shows the code
+    vm.config.dump_failed_chunks = false;
+    let mut cc = Compiler::new(&buffer, vm).invalid_utf8_at(invalid_utf8);
+    cc.parser.log = Some(ParseLog::default());
+    let result = cc.compile_chunk();
+    let log = cc.parser.log.take().unwrap_or_default();
+    print!("{}", log.render(&cc.compiling_chunk, &cc.vm.heap));
+    if let Err(errors) = result {
+        report_compile_errors(vm, &errors);
+        process::exit(65)
+    }
+}
+
+fn disasm_file(path: &str, vm: &mut VM) {
+    vm.set_script_path(path);
+    if let Err(VmErrors::CompileError(errors)) = compile_source(path, vm) {
+        report_compile_errors(vm, &errors);
+        process::exit(65)
+    }
+    vm.chunks.dump(&vm.heap, path);
+}
+
+fn asm_file(path: &str, vm: &mut VM) {
+    let (buffer, _) = read_source(path);
+    match assembler::assemble(vm, &buffer) {
+        Ok(chunk) => vm.chunks = chunk,
+        Err(e) => {
+            eprintln!("Assemble Error: {}", e);
+            process::exit(65)
         }
     }
+    let result = vm.run();
+    exit_with(vm, result);
 }
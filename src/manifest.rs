@@ -0,0 +1,226 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{Capability, VmConfig},
+    error::ManifestErrors,
+};
+
+pub const MANIFEST: &str = "lox.toml";
+
+// a project's lox.toml, read by the cli before any flags so they can
+// override it. A small subset of toml:
+//
+//     entry = "src/main.lox"
+//     module_dirs = ["lib"]
+//     natives = ["io", "time"]
+//     modern = true
+//
+//     [limits]
+//     max_heap_bytes = 16_000_000
+//     fuel = 1_000_000
+//
+// paths are relative to the directory holding the file
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub root: PathBuf,
+    // the script `lox_byte run` runs when given none
+    pub entry: Option<PathBuf>,
+    pub config: VmConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TomlValue {
+    String(String),
+    Integer(u64),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+impl Manifest {
+    // the nearest lox.toml in `dir` or a directory above it
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|dir| dir.join(MANIFEST))
+            .find(|path| path.is_file())
+    }
+
+    pub fn parse(text: &str, root: &Path) -> Result<Self, ManifestErrors> {
+        let mut manifest = Self {
+            root: root.into(),
+            entry: None,
+            config: VmConfig::default(),
+        };
+        let mut section = String::new();
+        let mut seen: Vec<String> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let Some(name) = name.strip_suffix(']') else {
+                    return Err(ManifestErrors::Syntax(line_number, "Unclosed '['".into()));
+                };
+                section = name.trim().into();
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ManifestErrors::Syntax(
+                    line_number,
+                    "Expect 'key = value'".into(),
+                ));
+            };
+            let key = match section.as_str() {
+                "" => key.trim().to_owned(),
+                section => format!("{}.{}", section, key.trim()),
+            };
+            if seen.contains(&key) {
+                return Err(ManifestErrors::DuplicateKey(line_number, key));
+            }
+            let value = parse_value(value.trim())
+                .map_err(|message| ManifestErrors::Syntax(line_number, message))?;
+            manifest.set(&key, value, line_number)?;
+            seen.push(key);
+        }
+        Ok(manifest)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let root = path.parent().unwrap_or(Path::new("."));
+        Self::parse(&text, root).map_err(|e| e.to_string())
+    }
+
+    fn set(&mut self, key: &str, value: TomlValue, line: usize) -> Result<(), ManifestErrors> {
+        let invalid = |expected| ManifestErrors::InvalidValue(line, key.into(), expected);
+        let config = &mut self.config;
+        match (key, value) {
+            ("entry", TomlValue::String(path)) => self.entry = Some(self.root.join(path)),
+            ("entry", _) => return Err(invalid("a path")),
+            ("module_dirs", TomlValue::Array(dirs)) => {
+                config.module_dirs = dirs.iter().map(|dir| self.root.join(dir)).collect()
+            }
+            ("module_dirs", _) => return Err(invalid("an array of paths")),
+            ("natives", TomlValue::Array(names)) => {
+                config.capabilities = names
+                    .iter()
+                    .map(|name| capability(name))
+                    .collect::<Option<_>>()
+                    .ok_or(invalid("an array of \"io\", \"time\" and \"memory\""))?
+            }
+            ("natives", _) => return Err(invalid("an array of \"io\", \"time\" and \"memory\"")),
+            ("modern", TomlValue::Bool(b)) => config.modern = b,
+            ("strict_compat", TomlValue::Bool(b)) => config.strict_compat = b,
+            ("chain_comparisons", TomlValue::Bool(b)) => config.chain_comparisons = b,
+            ("debug_info", TomlValue::Bool(b)) => config.debug_info = b,
+            ("modern" | "strict_compat" | "chain_comparisons" | "debug_info", _) => {
+                return Err(invalid("true or false"))
+            }
+            ("limits.max_heap_bytes", TomlValue::Integer(n)) => {
+                config.max_heap_bytes = Some(n as usize)
+            }
+            ("limits.max_stack", TomlValue::Integer(n)) => config.max_stack = Some(n as usize),
+            ("limits.max_nesting", TomlValue::Integer(n)) => config.max_nesting = n as usize,
+            ("limits.fuel", TomlValue::Integer(n)) => config.fuel = Some(n),
+            (
+                "limits.max_heap_bytes" | "limits.max_stack" | "limits.max_nesting" | "limits.fuel",
+                _,
+            ) => return Err(invalid("a whole number")),
+            (key, _) => return Err(ManifestErrors::UnknownKey(line, key.into())),
+        }
+        Ok(())
+    }
+}
+
+fn capability(name: &str) -> Option<Capability> {
+    match name {
+        "io" => Some(Capability::Io),
+        "time" => Some(Capability::Time),
+        "memory" => Some(Capability::Memory),
+        _ => None,
+    }
+}
+
+// a '#' outside a string starts a comment
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<TomlValue, String> {
+    match text {
+        "true" => return Ok(TomlValue::Bool(true)),
+        "false" => return Ok(TomlValue::Bool(false)),
+        _ => {}
+    }
+    if text.starts_with('"') {
+        let (string, rest) = parse_string(text)?;
+        return match rest.trim() {
+            "" => Ok(TomlValue::String(string)),
+            rest => Err(format!("Unexpected '{}' after the string", rest)),
+        };
+    }
+    if let Some(items) = text.strip_prefix('[') {
+        let mut rest = items.trim_start();
+        let mut strings = Vec::new();
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                return match after.trim() {
+                    "" => Ok(TomlValue::Array(strings)),
+                    after => Err(format!("Unexpected '{}' after the array", after)),
+                };
+            }
+            let (string, after) = parse_string(rest)?;
+            strings.push(string);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after.trim_start();
+            } else if !rest.starts_with(']') {
+                return Err("Expect ',' or ']' in an array".into());
+            }
+        }
+    }
+    // whole numbers may group their digits with '_'
+    let digits = text.replace('_', "");
+    match digits.parse() {
+        Ok(n) if !text.starts_with('_') && !text.ends_with('_') => Ok(TomlValue::Integer(n)),
+        _ => Err(format!("Invalid value '{}'", text)),
+    }
+}
+
+// a basic string at the start of `text` and what follows it
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let Some(body) = text.strip_prefix('"') else {
+        return Err("Expect a string".into());
+    };
+    let mut string = String::new();
+    let mut chars = body.char_indices();
+    while let Some((index, char)) = chars.next() {
+        match char {
+            '"' => return Ok((string, &body[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, '"')) => string.push('"'),
+                Some((_, '\\')) => string.push('\\'),
+                _ => return Err("Invalid escape in a string".into()),
+            },
+            char => string.push(char),
+        }
+    }
+    Err("Unterminated string".into())
+}
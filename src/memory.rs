@@ -1,30 +1,179 @@
-use std::{
-    alloc::{GlobalAlloc, Layout, System},
-    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+use std::time::Duration;
+
+use std::{fmt::Write as _, io::Write};
+
+use crate::{
+    clock::Clock,
+    heap::{Heap, Obj},
+    table::Table,
+    value::{InternString, LoxValue, Value},
+    vm::VM,
 };
 
-struct Counter;
+// what the collector has done over the life of a vm, see VM::gc_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcStats {
+    pub collections: usize,
+    // heap bytes around the latest collection
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+    // summed over every collection
+    pub freed: usize,
+    pub duration: Duration,
+}
+
+// mark everything reachable from the stack, globals and the current chunk's
+// constants, then free the rest of the heap. returns bytes freed
+pub fn collect_garbage(vm: &mut VM) -> usize {
+    let started = Clock::now();
+    let (before, objects) = (vm.heap.bytes(), vm.heap.len());
+    let mut marked = vec![false; vm.heap.capacity()];
+    trace_roots(vm, |index, _| marked[index] = true);
+
+    let swept = vm.heap.sweep(&marked);
+    vm.strings.retain(|_, handle| marked[handle.index()]);
+
+    let after = vm.heap.bytes();
+    let freed = before.saturating_sub(after);
+    let pause = started.elapsed();
+    let stats = &mut vm.gc_stats;
+    stats.collections += 1;
+    stats.bytes_before = before;
+    stats.bytes_after = after;
+    stats.freed += freed;
+    stats.duration += pause;
+    vm.next_gc = (after as f64 * vm.config.gc_growth_factor) as usize;
+    if vm.config.gc_log {
+        let _ = writeln!(
+            vm.diagnostics,
+            "-- gc: freed {} of {} objects, {} bytes in {:?}, next at {} bytes",
+            swept, objects, freed, pause, vm.next_gc
+        );
+    }
+    freed
+}
+
+// what keeps an object alive, as reported by the heap dump
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Root<'a> {
+    Stack(usize),
+    Global(InternString),
+    Constant,
+    // modules by the path they were imported from
+    Module(&'a str),
+    ModuleGlobal(&'a str, InternString),
+    ModuleConstant(&'a str),
+    // a global of a script whose import is still running
+    Importer(InternString),
+}
+
+impl Root<'_> {
+    pub fn describe(&self, heap: &Heap) -> String {
+        match self {
+            Root::Stack(slot) => format!("stack slot {}", slot),
+            Root::Global(name) => format!("global {}", heap.get(*name)),
+            Root::Constant => "constant".to_string(),
+            Root::Module(key) => format!("module {}", key),
+            Root::ModuleGlobal(key, name) => format!("global {} of {}", heap.get(*name), key),
+            Root::ModuleConstant(key) => format!("constant of {}", key),
+            Root::Importer(name) => format!("global {} of an importer", heap.get(*name)),
+        }
+    }
+}
 
-static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+// calls `reach` with every heap slot something live points at, and the
+// root it was found through. Nothing on the heap points into it, so the
+// roots are all there is to trace. A slot may be reached more than once
+pub fn trace_roots<'a>(vm: &'a VM, mut reach: impl FnMut(usize, Root<'a>)) {
+    for (slot, value) in vm.stack.iter().enumerate() {
+        if let Some(index) = value.heap_index() {
+            reach(index, Root::Stack(slot));
+        }
+    }
+    trace_table(&vm.globals, &mut reach, Root::Global);
+    let stepped = vm.execution.iter().flat_map(|e| e.chunk.constants.iter());
+    for index in vm
+        .chunks
+        .constants
+        .iter()
+        .chain(stepped)
+        .filter_map(Value::heap_index)
+    {
+        reach(index, Root::Constant);
+    }
 
-unsafe impl GlobalAlloc for Counter {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let ret = System.alloc(layout);
-        if !ret.is_null() {
-            ALLOCATED.fetch_add(layout.size(), SeqCst);
+    // imported modules stay loaded for the life of the vm: their chunks,
+    // their globals and the importer globals set aside while one runs
+    for (key, module) in vm.modules.loaded.iter() {
+        for index in module.chunk.constants.iter().filter_map(Value::heap_index) {
+            reach(index, Root::ModuleConstant(key));
         }
-        ret
+        if let Some(handle) = module.value {
+            reach(handle.index(), Root::Module(key));
+            trace_table(&vm.heap.get(handle).globals, &mut reach, |name| {
+                Root::ModuleGlobal(key, name)
+            });
+        }
+    }
+    for globals in vm.modules.suspended.iter() {
+        trace_table(globals, &mut reach, Root::Importer);
     }
+}
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        System.dealloc(ptr, layout);
-        ALLOCATED.fetch_sub(layout.size(), SeqCst);
+fn trace_table<'a>(
+    table: &Table<InternString, Value>,
+    reach: &mut impl FnMut(usize, Root<'a>),
+    root: impl Fn(InternString) -> Root<'a>,
+) {
+    for (name, value) in table.iter() {
+        reach(name.index(), root(*name));
+        if let Some(index) = value.heap_index() {
+            reach(index, root(*name));
+        }
     }
 }
 
-#[global_allocator]
-static A: Counter = Counter;
+// every object on the heap with its type, size and the first root that
+// reaches it. Objects nothing reaches are garbage the next collection frees
+pub fn heap_dump(vm: &VM) -> String {
+    let mut roots = vec![None; vm.heap.capacity()];
+    trace_roots(vm, |index, root| {
+        roots[index].get_or_insert(root);
+    });
+
+    let mut out = format!(
+        "-- heap: {} objects, {} bytes\n",
+        vm.heap.len(),
+        vm.heap.bytes()
+    );
+    for (index, obj, size) in vm.heap.objects() {
+        let root = match roots[index] {
+            Some(root) => root.describe(&vm.heap),
+            None => "unreachable".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{:>5} {:<8} {:>7}  {:<28} {}",
+            index,
+            obj.type_name(),
+            size,
+            root,
+            preview(obj)
+        );
+    }
+    out
+}
 
-pub fn get_allocated_bytes() -> usize {
-    ALLOCATED.load(SeqCst)
+// strings are quoted and cut short, a dump is read by eye
+fn preview(obj: &Obj) -> String {
+    match obj {
+        Obj::String(content) if content.chars().count() > 40 => {
+            let head: String = content.chars().take(40).collect();
+            format!("{}...", LoxValue::String(head).repr())
+        }
+        Obj::String(content) => LoxValue::String(content.clone()).repr(),
+        Obj::Native(native) => native.to_string(),
+        Obj::Foreign(foreign) => foreign.to_string(),
+        Obj::Module(module) => module.to_string(),
+    }
 }
@@ -0,0 +1,207 @@
+use core::fmt;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    chunks::Chunk,
+    heap::ObjRef,
+    table::Table,
+    value::{InternString, Value},
+};
+
+// a script loaded with `import`, its globals are read as properties
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub globals: Table<InternString, Value>,
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
+
+// compiled when the import is compiled, run the first time it executes
+#[derive(Debug, Clone)]
+pub struct LoadedModule {
+    pub name: String,
+    pub chunk: Chunk,
+    pub value: Option<ObjRef<Module>>,
+}
+
+// keyed by canonical path
+#[derive(Debug, Default)]
+pub struct Modules {
+    pub loaded: HashMap<String, LoadedModule>,
+    // modules being compiled, innermost last, for cycle detection
+    pub loading: Vec<String>,
+    // importer globals set aside while a module body runs
+    pub suspended: Vec<Table<InternString, Value>>,
+    // canonical path of the script being run, imports in it are relative
+    // to its directory
+    pub script: Option<String>,
+    // every module whose source was read, including those that failed to
+    // compile, for watch mode
+    pub read: Vec<String>,
+}
+
+impl Modules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the file whose imports are being resolved, None outside any file
+    pub fn importer(&self) -> Option<&str> {
+        self.loading
+            .last()
+            .or(self.script.as_ref())
+            .map(String::as_str)
+    }
+
+    // chain of imports ending back at `key`, if it is already being loaded
+    pub fn cycle(&self, key: &str) -> Option<Vec<String>> {
+        let chain = self.script.iter().chain(self.loading.iter());
+        let chain: Vec<&String> = chain.collect();
+        let start = chain.iter().position(|path| *path == key)?;
+        let mut cycle: Vec<String> = chain[start..].iter().map(|p| p.to_string()).collect();
+        cycle.push(key.into());
+        Some(cycle)
+    }
+}
+
+// where `import` gets module source from, files unless the embedder sets
+// one with `VM::set_module_resolver` to serve modules from memory, an
+// archive or a database
+pub trait ModuleResolver: Send {
+    // a key naming the module `path` refers to. imports resolving to the same
+    // key share one instance, and the key is what `importer` holds when the
+    // module imports others (None for a script not loaded through a resolver)
+    fn locate(&mut self, path: &str, importer: Option<&str>) -> Result<String, String>;
+
+    fn load(&mut self, key: &str) -> Result<String, String>;
+
+    // the source as read, for modules that may not be valid UTF-8. What
+    // `import` compiles, bytes that aren't UTF-8 are reported where they are
+    fn load_bytes(&mut self, key: &str) -> Result<Vec<u8>, String> {
+        self.load(key).map(String::into_bytes)
+    }
+}
+
+pub struct Resolver(pub Box<dyn ModuleResolver>);
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Resolver")
+    }
+}
+
+// the default, keyed by canonical path
+#[derive(Debug, Clone, Default)]
+pub struct FileResolver {
+    pub module_dirs: Vec<PathBuf>,
+}
+
+impl FileResolver {
+    pub fn new(module_dirs: Vec<PathBuf>) -> Self {
+        Self { module_dirs }
+    }
+}
+
+impl ModuleResolver for FileResolver {
+    fn locate(&mut self, path: &str, importer: Option<&str>) -> Result<String, String> {
+        let candidates = candidates(path, importer, &self.module_dirs);
+        let Some(found) = candidates.iter().find(|candidate| candidate.is_file()) else {
+            let searched: Vec<String> = candidates
+                .iter()
+                .filter_map(|c| c.parent().map(|dir| dir.display().to_string()))
+                .collect();
+            return Err(format!("not found in {}", searched.join(", ")));
+        };
+        let canonical = fs::canonicalize(found).map_err(|e| e.to_string())?;
+        Ok(canonical.display().to_string())
+    }
+
+    fn load(&mut self, key: &str) -> Result<String, String> {
+        self.load_bytes(key)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn load_bytes(&mut self, key: &str) -> Result<Vec<u8>, String> {
+        fs::read(key).map_err(|e| e.to_string())
+    }
+}
+
+// modules held as strings, imported by exactly the name they were added as
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    pub modules: HashMap<String, String>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn module(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.modules.insert(name.into(), source.into());
+        self
+    }
+}
+
+impl ModuleResolver for MemoryResolver {
+    fn locate(&mut self, path: &str, _importer: Option<&str>) -> Result<String, String> {
+        match self.modules.contains_key(path) {
+            true => Ok(path.into()),
+            false => Err("no such module".into()),
+        }
+    }
+
+    fn load(&mut self, key: &str) -> Result<String, String> {
+        self.modules
+            .get(key)
+            .cloned()
+            .ok_or_else(|| "no such module".into())
+    }
+}
+
+// where an import of `path` may be found, in the order tried: the importing
+// file's directory, `module_dirs`, then each directory of LOX_PATH. paths
+// starting with ./ or ../ are only looked up next to the importer
+pub fn candidates(path: &str, importer: Option<&str>, module_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return vec![path.to_path_buf()];
+    }
+    let base = importer
+        .and_then(|file| Path::new(file).parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    let explicit = matches!(
+        path.components().next(),
+        Some(Component::CurDir | Component::ParentDir)
+    );
+    let mut dirs = vec![base];
+    if !explicit {
+        dirs.extend(module_dirs.iter().cloned());
+        if let Some(lox_path) = env::var_os("LOX_PATH") {
+            dirs.extend(env::split_paths(&lox_path));
+        }
+    }
+    dirs.into_iter().map(|dir| dir.join(path)).collect()
+}
+
+// the global an import binds when it has no `as`: the file name without
+// its extension, when that is a valid identifier
+pub fn default_binding(path: &str) -> Option<&str> {
+    let file = path.rsplit(['/', '\\']).next()?;
+    let stem = file.split('.').next()?;
+    let mut chars = stem.chars();
+    let first = chars.next()?;
+    let valid = (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphabetic() || c == '_');
+    valid.then_some(stem)
+}
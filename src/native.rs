@@ -0,0 +1,241 @@
+use core::fmt;
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use crate::{
+    bind::FromLox,
+    clock::unix_millis,
+    config::Capability,
+    error::{RuntimeErrors, VmErrors},
+    format,
+    memory::collect_garbage,
+    value::{create_string, Value},
+    vm::VM,
+};
+
+pub type NativeResult = Result<Value, VmErrors>;
+pub type NativeFnPtr = fn(&mut VM, &[Value]) -> NativeResult;
+// natives registered through `VM::bind` capture their closure
+pub type NativeClosure = Arc<dyn Fn(&mut VM, &[Value]) -> NativeResult + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        match self {
+            Self::Exact(n) => arg_count == *n,
+            Self::AtLeast(n) => arg_count >= *n,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(n) => write!(f, "{}", n),
+            Self::AtLeast(n) => write!(f, "at least {}", n),
+        }
+    }
+}
+
+pub struct NativeFn {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub function: NativeClosure,
+}
+
+impl NativeFn {
+    pub fn new(
+        name: &'static str,
+        arity: Arity,
+        function: impl Fn(&mut VM, &[Value]) -> NativeResult + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            arity,
+            function: Arc::new(function),
+        }
+    }
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFn")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+// registered on every new VM, as far as its config allows
+pub fn define_natives(vm: &mut VM) {
+    vm.define_native("assert", 2, assert);
+    vm.define_native("panic", 1, panic);
+    vm.define_native("type", 1, type_of);
+    vm.define_variadic("format", 1, format);
+    vm.define_variadic("printf", 1, printf);
+    vm.define_variadic("exit", 0, exit);
+    vm.define_variadic("print", 0, print);
+    vm.define_variadic("args", 0, script_args);
+    if vm.config.allows(Capability::Time) {
+        vm.define_native("clock", 0, clock);
+        vm.define_native("now_ms", 0, now_ms);
+    }
+    if vm.config.allows(Capability::Io) {
+        vm.define_native("readLine", 0, read_line);
+    }
+    if vm.config.allows(Capability::Memory) {
+        vm.define_native("memoryUsed", 0, memory_used);
+        vm.define_native("gcCollect", 0, gc_collect);
+        vm.define_native("objectCount", 0, object_count);
+    }
+}
+
+// seconds since the VM was created
+fn clock(vm: &mut VM, _args: &[Value]) -> NativeResult {
+    Ok(vm.start.elapsed().as_secs_f64().into())
+}
+
+// wall clock milliseconds since the unix epoch
+fn now_ms(_vm: &mut VM, _args: &[Value]) -> NativeResult {
+    Ok(unix_millis().into())
+}
+
+// one line from stdin without the line ending, nil on eof
+fn read_line(vm: &mut VM, _args: &[Value]) -> NativeResult {
+    let mut buffer = String::new();
+    match io::stdin().read_line(&mut buffer) {
+        Ok(0) | Err(_) => Ok(Value::Nil),
+        Ok(_) => {
+            let line = buffer.trim_end_matches(['\n', '\r']);
+            Ok(create_string(vm, line).into())
+        }
+    }
+}
+
+fn assert(vm: &mut VM, args: &[Value]) -> NativeResult {
+    if args[0].is_falsy() {
+        return Err(VmErrors::RuntimeError(RuntimeErrors::AssertionFailed(
+            args[1].display(&vm.heap).to_string(),
+        )));
+    }
+    Ok(Value::Nil)
+}
+
+fn panic(vm: &mut VM, args: &[Value]) -> NativeResult {
+    Err(VmErrors::RuntimeError(RuntimeErrors::Panic(
+        args[0].display(&vm.heap).to_string(),
+    )))
+}
+
+// stops the script, the cli exits with `code` (0 when omitted)
+fn exit(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let code = match args {
+        [] => 0,
+        [Value::Number(code)] if code.fract() == 0.0 => *code as i32,
+        [value] => {
+            return Err(VmErrors::RuntimeError(RuntimeErrors::TypeError(
+                "whole number",
+                value.display(&vm.heap).to_string(),
+            )))
+        }
+        _ => {
+            return Err(VmErrors::RuntimeError(RuntimeErrors::ArityMismatch(
+                Arity::Exact(1),
+                args.len(),
+            )))
+        }
+    };
+    Err(VmErrors::RuntimeError(RuntimeErrors::Exit(code)))
+}
+
+// args() is how many arguments the script was given, args(i) the one at
+// index i as a string, nil past the end
+fn script_args(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let index = match args {
+        [] => return Ok((vm.script_args.len() as f64).into()),
+        [Value::Number(index)] if index.fract() == 0.0 => *index,
+        [value] => {
+            return Err(VmErrors::RuntimeError(RuntimeErrors::TypeError(
+                "whole number",
+                value.display(&vm.heap).to_string(),
+            )))
+        }
+        _ => {
+            return Err(VmErrors::RuntimeError(RuntimeErrors::ArityMismatch(
+                Arity::Exact(1),
+                args.len(),
+            )))
+        }
+    };
+    match usize::try_from(index as i64)
+        .ok()
+        .and_then(|i| vm.script_args.get(i))
+    {
+        Some(arg) => {
+            let arg = arg.clone();
+            Ok(create_string(vm, &arg).into())
+        }
+        None => Ok(Value::Nil),
+    }
+}
+
+fn type_of(vm: &mut VM, args: &[Value]) -> NativeResult {
+    Ok(create_string(vm, args[0].type_name()).into())
+}
+
+fn formatted(vm: &VM, args: &[Value]) -> Result<String, VmErrors> {
+    let fmt = String::from_lox(&args[0], &vm.heap).map_err(VmErrors::RuntimeError)?;
+    format::format(&vm.heap, &fmt, &args[1..]).map_err(VmErrors::RuntimeError)
+}
+
+fn format(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let text = formatted(vm, args)?;
+    Ok(create_string(vm, &text).into())
+}
+
+// format() written to stdout, lox strings have no escapes so the newline
+// is added the same way the print statement does
+fn printf(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let text = formatted(vm, args)?;
+    let _ = writeln!(vm.out, "{}", text);
+    Ok(Value::Nil)
+}
+
+// what the print statement is with `config.modern`, where `print` is an
+// identifier. The arguments are separated by spaces
+fn print(vm: &mut VM, args: &[Value]) -> NativeResult {
+    let text = args
+        .iter()
+        .map(|arg| arg.display(&vm.heap).to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = writeln!(vm.out, "{}", text);
+    Ok(Value::Nil)
+}
+
+// bytes held by objects on this vm's heap
+fn memory_used(vm: &mut VM, _args: &[Value]) -> NativeResult {
+    Ok((vm.heap.bytes() as f64).into())
+}
+
+// runs a collection and returns the number of bytes freed
+fn gc_collect(vm: &mut VM, _args: &[Value]) -> NativeResult {
+    Ok((collect_garbage(vm) as f64).into())
+}
+
+fn object_count(vm: &mut VM, _args: &[Value]) -> NativeResult {
+    Ok((vm.heap.len() as f64).into())
+}
@@ -1,86 +1,94 @@
 use core::fmt;
 
-use crate::error::RuntimeErrors;
+use crate::{error::RuntimeErrors, int_enum};
 
-#[derive(Debug, Default)]
-#[repr(u8)]
-pub enum OpCode {
-    Constant,
-    // binary
-    Addition,
-    Subtract,
-    Multiply,
-    Divide,
-    Not,
-    Negate,
+int_enum! {
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub enum OpCode: u8, Err(RuntimeErrors) = RuntimeErrors::InvalidOpcode {
+        Constant,
+        // binary
+        Addition,
+        Subtract,
+        Multiply,
+        Divide,
+        Not,
+        Negate,
 
-    Print,
-    Jump,
-    JumpIfFalse,
-    Loop,
+        Print,
+        Jump,
+        JumpIfFalse,
+        Loop,
+        Call,
 
-    True,
-    Pop,
-    GetLocal,
-    SetLocal,
-    GetGlobal,
-    DefineGlobal,
-    SetGlobal,
-    Equal,
-    False,
-    Greater,
-    Less,
+        True,
+        Pop,
+        GetLocal,
+        SetLocal,
+        GetGlobal,
+        DefineGlobal,
+        SetGlobal,
+        Equal,
+        False,
+        Greater,
+        Less,
 
-    Nil,
-    #[default]
-    Return,
+        Nil,
+        GetProperty,
+        SetProperty,
+        Import,
+        // pushes an exception handler, its operand jumps to the catch block
+        Try,
+        EndTry,
+        Throw,
+        Breakpoint,
+        #[default]
+        Return,
+    }
 }
 
 impl fmt::Display for OpCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Constant => write!(f, "Op_Constant"),
-            Self::Addition => write!(f, "Op_Addition"),
-            Self::Subtract => write!(f, "Op_Subtract"),
-            Self::Multiply => write!(f, "Op_Multiply"),
-            Self::Divide => write!(f, "Op_Divide"),
-            Self::Not => write!(f, "Op_Not"),
-            Self::Negate => write!(f, "Op_Negate"),
-            Self::Print => write!(f, "Op_Print"),
-            Self::Jump => write!(f, "Op_Jump"),
-            Self::JumpIfFalse => write!(f, "Op_JumpIfFalse"),
-            Self::Loop => write!(f, "Op_Loop"),
-            Self::True => write!(f, "Op_True"),
-            Self::Pop => write!(f, "Op_Pop"),
-            Self::GetLocal => write!(f, "Op_GetLocal"),
-            Self::SetLocal => write!(f, "Op_SetLocal"),
-            Self::GetGlobal => write!(f, "Op_GetGlobal"),
-            Self::DefineGlobal => write!(f, "Op_DefineGlobal"),
-            Self::SetGlobal => write!(f, "Op_SetGlobal"),
-            Self::False => write!(f, "Op_False"),
-            Self::Equal => write!(f, "Op_Equal"),
-            Self::Greater => write!(f, "Op_Greater"),
-            Self::Less => write!(f, "Op_Less"),
-            Self::Nil => write!(f, "Op_Nil"),
-            Self::Return => write!(f, "Op_Return"),
-        }
+        write!(f, "Op_{}", self.name())
     }
 }
 
-impl From<OpCode> for u8 {
-    fn from(value: OpCode) -> Self {
-        value as u8
+// how many bytes follow an opcode and how they are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    None,
+    // raw u8, a stack slot or argument count
+    Byte,
+    // u8 index into the constant table
+    Constant,
+    // u16 offset, forwards for 1 and backwards for -1
+    Jump(isize),
+}
+
+impl Operand {
+    // bytes following the opcode
+    pub fn size(&self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Byte | Self::Constant => 1,
+            Self::Jump(_) => 2,
+        }
     }
 }
 
-impl TryFrom<u8> for OpCode {
-    type Error = RuntimeErrors;
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        let last_op: u8 = Self::Return.into();
-        if value < last_op + 1 {
-            Ok(unsafe { std::mem::transmute(value) })
-        } else {
-            Err(RuntimeErrors::InvalidOpcode)
+impl OpCode {
+    pub fn operand(&self) -> Operand {
+        match self {
+            Self::Constant
+            | Self::GetGlobal
+            | Self::DefineGlobal
+            | Self::SetGlobal
+            | Self::GetProperty
+            | Self::SetProperty
+            | Self::Import => Operand::Constant,
+            Self::GetLocal | Self::SetLocal | Self::Call => Operand::Byte,
+            Self::Jump | Self::JumpIfFalse | Self::Try => Operand::Jump(1),
+            Self::Loop => Operand::Jump(-1),
+            _ => Operand::None,
         }
     }
 }
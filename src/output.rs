@@ -0,0 +1,94 @@
+use core::fmt;
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{Arc, Mutex},
+};
+
+use crate::macros::colors_for;
+
+// where the vm writes program output and diagnostics
+pub struct Sink {
+    writer: Box<dyn Write + Send>,
+    terminal: bool,
+}
+
+impl Sink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            terminal: false,
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self {
+            terminal: io::stdout().is_terminal(),
+            ..Self::new(io::stdout())
+        }
+    }
+
+    pub fn stderr() -> Self {
+        Self {
+            terminal: io::stderr().is_terminal(),
+            ..Self::new(io::stderr())
+        }
+    }
+
+    // whether text written here should carry color codes, never for
+    // captured or custom writers unless --color=always
+    pub fn colors(&self) -> bool {
+        colors_for(self.terminal)
+    }
+
+    // a sink that keeps everything written, read back through the buffer
+    pub fn capture() -> (Self, CaptureBuffer) {
+        let buffer = CaptureBuffer::default();
+        (Self::new(buffer.clone()), buffer)
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sink")
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CaptureBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl CaptureBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.buffer()).into_owned()
+    }
+
+    fn buffer(&self) -> std::sync::MutexGuard<'_, Vec<u8>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn clear(&self) {
+        self.buffer().clear();
+    }
+}
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+};
+
+thread_local! {
+    // how many `guarded` calls the thread is inside
+    static GUARDED: Cell<usize> = const { Cell::new(0) };
+    // where the last guarded panic happened, recorded by the hook
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// keeps rust's panic message off stderr for panics `guarded` catches, they
+// are reported as a diagnostic instead, and remembers where each happened
+// so the diagnostic can say. Others go to the hook installed before
+pub fn install() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if GUARDED.get() == 0 {
+            return previous(info);
+        }
+        let location = info.location().map(|l| l.to_string());
+        LOCATION.set(location);
+    }));
+}
+
+// runs `f`, turning a panic inside it into its message and, with the hook
+// installed, the place in the interpreter's source it came from
+pub fn guarded<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    GUARDED.set(GUARDED.get() + 1);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDED.set(GUARDED.get() - 1);
+    result.map_err(|payload| {
+        let message = message(payload.as_ref());
+        match LOCATION.take() {
+            Some(location) => format!("{} at {}", message, location),
+            None => message,
+        }
+    })
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return (*message).into();
+    }
+    match payload.downcast_ref::<String>() {
+        Some(message) => message.clone(),
+        None => "unknown panic".into(),
+    }
+}
@@ -0,0 +1,76 @@
+use std::{collections::HashMap, fmt, fmt::Write};
+
+use crate::{chunks::Chunk, disassembler::Disassembler, heap::Heap, token::Token};
+
+#[derive(Debug, Clone)]
+enum Event {
+    Enter(String),
+    Leave,
+    // the lexeme, None for the end of the source
+    Token(Option<String>),
+    // the offset of a byte written to the chunk
+    Emit(usize),
+}
+
+// what the single pass compiler did, in order: the grammar rules it entered,
+// the tokens it consumed and the bytes it emitted. The compiler records it
+// when its parser's `log` is set, for `--dump-parse`
+#[derive(Debug, Clone, Default)]
+pub struct ParseLog {
+    events: Vec<Event>,
+}
+
+impl ParseLog {
+    pub fn enter(&mut self, rule: impl fmt::Display) {
+        self.events.push(Event::Enter(rule.to_string()));
+    }
+
+    pub fn leave(&mut self) {
+        self.events.push(Event::Leave);
+    }
+
+    pub fn token(&mut self, token: &Token<'_>) {
+        // one line per event, even for multi-line strings
+        let lexeme = token.lexeme.map(|lexeme| lexeme.replace('\n', "\\n"));
+        self.events.push(Event::Token(lexeme));
+    }
+
+    pub fn emit(&mut self, offset: usize) {
+        self.events.push(Event::Emit(offset));
+    }
+
+    // the events as a tree, each rule indented under the one it was parsed
+    // in. Emitted bytes are shown as the instructions they begin in
+    // `chunk`, once jumps have been patched, operand bytes aren't shown
+    pub fn render(&self, chunk: &Chunk, heap: &Heap) -> String {
+        let instructions: HashMap<_, _> = chunk
+            .instructions(heap)
+            .map(|instruction| (instruction.offset, instruction))
+            .collect();
+        let mut out = String::new();
+        let mut depth = 0;
+        for event in &self.events {
+            let indent = "  ".repeat(depth);
+            let _ = match event {
+                Event::Enter(rule) => {
+                    depth += 1;
+                    writeln!(out, "{}{}", indent, rule)
+                }
+                Event::Leave => {
+                    depth = depth.saturating_sub(1);
+                    continue;
+                }
+                Event::Token(Some(lexeme)) => writeln!(out, "{}token '{}'", indent, lexeme),
+                Event::Token(None) => writeln!(out, "{}token end", indent),
+                Event::Emit(offset) => match instructions.get(offset) {
+                    Some(instruction) => {
+                        let instruction = instruction.to_string();
+                        writeln!(out, "{}{:04} {}", indent, offset, instruction.trim_end())
+                    }
+                    None => continue,
+                },
+            };
+        }
+        out
+    }
+}
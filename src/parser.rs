@@ -2,8 +2,10 @@
 
 use crate::{
     compiler::Compiler,
-    error::CompileErrors,
+    error::{CompileErrors, Diagnostic},
+    int_enum,
     opcode::OpCode,
+    parse_log::ParseLog,
     scanner::Scanner,
     token::{TType, Token},
     value::create_string,
@@ -17,6 +19,9 @@ pub struct Parser<'src> {
 
     pub had_error: bool,
     panic_mode: bool,
+    pub errors: Vec<Diagnostic>,
+    // the parse events, recorded only when set
+    pub log: Option<ParseLog>,
 }
 
 impl<'src> Parser<'src> {
@@ -27,6 +32,8 @@ impl<'src> Parser<'src> {
             current: None,
             had_error: false,
             panic_mode: false,
+            errors: Vec::new(),
+            log: None,
         }
     }
 
@@ -51,25 +58,37 @@ impl<'src> Parser<'src> {
         if cfg!(feature = "scanner_debug") {
             println!("{:?}", self.previous);
         }
+        if let (Some(log), Some(token)) = (&mut self.log, &self.previous) {
+            log.token(token);
+        }
         loop {
             let token = self.scanner.scan_token();
-            let error = TType::error_message(&token.ttype);
+            let error = CompileErrors::lexical(token.ttype);
             self.current = Some(token.clone());
             match error {
-                Some(e) => self.error_at(e),
+                Some(e) => self.error_at_current(e),
                 None => break,
             }
         }
     }
 
-    pub fn error_at(&mut self, msg: &str) {
+    pub fn error_at_current(&mut self, error: CompileErrors) {
+        self.error_at(self.current.clone(), error);
+    }
+
+    pub fn error_at_previous(&mut self, error: CompileErrors) {
+        self.error_at(self.previous.clone(), error);
+    }
+
+    fn error_at(&mut self, token: Option<Token<'src>>, error: CompileErrors) {
         if self.panic_mode {
             return;
         }
         self.had_error = true;
         self.panic_mode = true;
-        if let Some(tok) = &self.current {
-            println!("{}: {}", tok, msg);
+        if let Some(tok) = token {
+            let error = Diagnostic::new(error, &tok, self.scanner.source());
+            self.errors.push(error);
         }
     }
 
@@ -80,7 +99,28 @@ impl<'src> Parser<'src> {
                 return;
             }
         }
-        self.error_at(msg);
+        self.error_at_current(CompileErrors::Expected(msg.into()));
+    }
+
+    // the rules below read these instead of unwrapping the tokens, Eof has
+    // no lexeme and neither token exists before the first advance
+    pub fn current_type(&self) -> TType {
+        self.current.as_ref().map_or(TType::Eof, |t| t.ttype)
+    }
+
+    pub fn previous_type(&self) -> TType {
+        self.previous.as_ref().map_or(TType::Eof, |t| t.ttype)
+    }
+
+    pub fn previous_lexeme(&self) -> &'src str {
+        self.previous
+            .as_ref()
+            .and_then(|t| t.lexeme)
+            .unwrap_or_default()
+    }
+
+    pub fn previous_line(&self) -> usize {
+        self.previous.as_ref().map_or(1, |t| t.line)
     }
 
     pub fn get_panic(&mut self) -> bool {
@@ -92,21 +132,22 @@ impl<'src> Parser<'src> {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
-#[repr(usize)]
-pub enum Precedence {
-    #[default]
-    None,
-    Assignment,
-    Or,
-    And,
-    Equality,
-    Comparison,
-    Term,
-    Factor,
-    Unary,
-    Call,
-    Primary,
+int_enum! {
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Precedence: usize, Err(CompileErrors) = CompileErrors::InvalidPrecedence {
+        #[default]
+        None,
+        Assignment,
+        Or,
+        And,
+        Equality,
+        Comparison,
+        Term,
+        Factor,
+        Unary,
+        Call,
+        Primary,
+    }
 }
 
 type ParserFn = fn(&mut Compiler<'_, '_>, bool);
@@ -118,13 +159,18 @@ pub struct ParseRule {
     pub precedence: Precedence,
 }
 
-pub fn get_rule(tt: TType) -> ParseRule {
+// `modern` is `config.modern`, where `print` is read like any name
+pub fn get_rule(tt: TType, modern: bool) -> ParseRule {
     match tt {
         TType::LeftParen => ParseRule {
             prefix: Some(grouping),
-            // infix: Some(call),
-            // precedence: Precedence::Call,
-            ..ParseRule::default()
+            infix: Some(call),
+            precedence: Precedence::Call,
+        },
+        TType::Dot => ParseRule {
+            prefix: None,
+            infix: Some(dot),
+            precedence: Precedence::Call,
         },
         TType::Minus => ParseRule {
             prefix: Some(unary),
@@ -184,6 +230,14 @@ pub fn get_rule(tt: TType) -> ParseRule {
             prefix: Some(variable),
             ..ParseRule::default()
         },
+        TType::Do => ParseRule {
+            prefix: Some(do_),
+            ..ParseRule::default()
+        },
+        TType::Print if modern => ParseRule {
+            prefix: Some(variable),
+            ..ParseRule::default()
+        },
         TType::String => ParseRule {
             prefix: Some(string),
             ..ParseRule::default()
@@ -225,59 +279,187 @@ fn grouping(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
 }
 
 fn unary(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
-    let token = cc.parser.previous.as_ref().unwrap();
-    let op = token.ttype;
-    let line = token.line;
+    let op = cc.parser.previous_type();
+    let line = cc.parser.previous_line();
+    let span = cc.parser.previous.as_ref().map(Token::span);
     cc.parse_precedence(Precedence::Unary);
     match op {
-        TType::Bang => cc.emit_byte_with_line(OpCode::Not.into(), line),
-        TType::Minus => cc.emit_byte_with_line(OpCode::Negate.into(), line),
+        TType::Bang => cc.emit_byte_at(OpCode::Not.into(), line, span),
+        TType::Minus => cc.emit_byte_at(OpCode::Negate.into(), line, span),
         _ => unreachable!(),
     }
 }
 
 fn binary(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
-    let tt = cc.parser.previous.as_ref().unwrap().ttype;
-    let precedence: usize = get_rule(tt).precedence.into();
-    cc.parse_precedence(Precedence::try_from(precedence + 1).unwrap());
+    let tt = cc.parser.previous_type();
+    let operator = cc.parser.previous.clone();
+    if !right_operand(cc, tt) {
+        return;
+    }
+    if is_comparison(tt) && is_comparison(cc.parser.current_type()) {
+        if cc.vm.config.chain_comparisons {
+            return chained_comparison(cc, tt, operator);
+        }
+        cc.parser.error_at_current(CompileErrors::ChainedComparison);
+    }
+    emit_operator(cc, tt, operator.as_ref());
+}
+
+// parses the operand right of a binary operator, false if there is none
+fn right_operand(cc: &mut Compiler<'_, '_>, tt: TType) -> bool {
+    let precedence: usize = get_rule(tt, cc.vm.config.modern).precedence.into();
+    let precedence = match Precedence::try_from(precedence + 1) {
+        Ok(precedence) => precedence,
+        Err(err) => {
+            cc.parser.error_at_previous(err);
+            return false;
+        }
+    };
+    // the left operand waits on the stack under the right one
+    cc.stack_height += 1;
+    cc.parse_precedence(precedence);
+    cc.stack_height -= 1;
+    true
+}
+
+fn is_comparison(tt: TType) -> bool {
+    matches!(
+        tt,
+        TType::Greater | TType::GreaterEqual | TType::Less | TType::LessEqual
+    )
+}
 
+// `a < b < c` with `config.chain_comparisons`. The two operands being
+// compared stay in their stack slots while copies of them are compared, so
+// the right one can become the next left one. The first false result
+// short-circuits, and either way the result ends up in the left slot
+fn chained_comparison(cc: &mut Compiler<'_, '_>, tt: TType, operator: Option<Token<'_>>) {
+    let left = cc.stack_height;
+    if left >= u8::MAX as usize {
+        return cc.parser.error_at_current(CompileErrors::TooManyLocals);
+    }
+    let left = left as u8;
+    let (mut tt, mut operator) = (tt, operator);
+    let mut exits = Vec::new();
+    loop {
+        cc.emit_bytes(OpCode::GetLocal.into(), left);
+        cc.emit_bytes(OpCode::GetLocal.into(), left + 1);
+        emit_operator(cc, tt, operator.as_ref());
+        if !is_comparison(cc.parser.current_type()) {
+            break;
+        }
+        exits.push(cc.emit_jump(OpCode::JumpIfFalse));
+        cc.emit_byte(OpCode::Pop.into());
+        cc.emit_bytes(OpCode::GetLocal.into(), left + 1);
+        cc.emit_bytes(OpCode::SetLocal.into(), left);
+        cc.emit_byte(OpCode::Pop.into());
+        cc.emit_byte(OpCode::Pop.into());
+        cc.parser.advance();
+        (tt, operator) = (cc.parser.previous_type(), cc.parser.previous.clone());
+        if !right_operand(cc, tt) {
+            return;
+        }
+    }
+    for exit in exits {
+        cc.patch_jump(exit);
+    }
+    cc.emit_bytes(OpCode::SetLocal.into(), left);
+    cc.emit_byte(OpCode::Pop.into());
+    cc.emit_byte(OpCode::Pop.into());
+}
+
+// the operands are emitted by now, the operator is what failed if anything
+// does
+fn emit_operator(cc: &mut Compiler<'_, '_>, tt: TType, operator: Option<&Token<'_>>) {
+    let line = operator.map_or(cc.parser.previous_line(), |t| t.line);
+    let span = operator.map(Token::span);
+    let mut emit = |op: OpCode| cc.emit_byte_at(op.into(), line, span.clone());
     match tt {
-        TType::Plus => cc.emit_byte(OpCode::Addition.into()),
-        TType::Minus => cc.emit_byte(OpCode::Subtract.into()),
-        TType::Star => cc.emit_byte(OpCode::Multiply.into()),
-        TType::Slash => cc.emit_byte(OpCode::Divide.into()),
-        TType::BangEqual => cc.emit_bytes(OpCode::Equal.into(), OpCode::Not.into()),
-        TType::EqualEqual => cc.emit_byte(OpCode::Equal.into()),
-        TType::Greater => cc.emit_byte(OpCode::Greater.into()),
-        TType::GreaterEqual => cc.emit_bytes(OpCode::Less.into(), OpCode::Not.into()),
-        TType::Less => cc.emit_byte(OpCode::Less.into()),
-        TType::LessEqual => cc.emit_bytes(OpCode::Greater.into(), OpCode::Not.into()),
+        TType::Plus => emit(OpCode::Addition),
+        TType::Minus => emit(OpCode::Subtract),
+        TType::Star => emit(OpCode::Multiply),
+        TType::Slash => emit(OpCode::Divide),
+        TType::BangEqual => {
+            emit(OpCode::Equal);
+            emit(OpCode::Not)
+        }
+        TType::EqualEqual => emit(OpCode::Equal),
+        TType::Greater => emit(OpCode::Greater),
+        TType::GreaterEqual => {
+            emit(OpCode::Less);
+            emit(OpCode::Not)
+        }
+        TType::Less => emit(OpCode::Less),
+        TType::LessEqual => {
+            emit(OpCode::Greater);
+            emit(OpCode::Not)
+        }
         _ => unreachable!(),
     }
 }
 
-#[allow(dead_code)]
+fn do_(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
+    cc.do_expression();
+}
+
 fn call(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
-    unimplemented!("call")
+    let arg_count = cc.argument_list();
+    cc.emit_bytes(OpCode::Call.into(), arg_count);
+}
+
+fn dot(cc: &mut Compiler<'_, '_>, can_assign: bool) {
+    cc.parser
+        .consume(TType::Identifer, "Expect property name after '.'.");
+    let name = match cc.identififer_constant(cc.parser.previous.clone()) {
+        Ok(name) => name,
+        Err(err) => {
+            cc.parser.error_at_previous(err);
+            0
+        }
+    };
+    if can_assign && cc.parser.match_token(TType::Equal) {
+        cc.stack_height += 1;
+        cc.expression();
+        cc.stack_height -= 1;
+        cc.emit_bytes(OpCode::SetProperty.into(), name);
+    } else {
+        cc.emit_bytes(OpCode::GetProperty.into(), name);
+    }
 }
 
 fn number(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
-    let number: f64 = cc
-        .parser
-        .previous
-        .as_ref()
-        .unwrap()
-        .lexeme
-        .unwrap()
-        .parse::<f64>()
-        .unwrap();
-    cc.emit_constant(number.into())
+    let lexeme = cc.parser.previous_lexeme();
+    let number = match lexeme.get(..2) {
+        Some("0x") | Some("0X") => parse_radix(&lexeme[2..], 16),
+        Some("0b") | Some("0B") => parse_radix(&lexeme[2..], 2),
+        _ => lexeme.replace('_', "").parse::<f64>().ok(),
+    };
+    match number {
+        Some(number) => cc.emit_constant(number.into()),
+        None => cc
+            .parser
+            .error_at_previous(CompileErrors::MalformedNumber("Invalid number literal.")),
+    }
+}
+
+// digits are validated by the scanner, folding into an f64 avoids
+// overflowing an integer type on long literals
+fn parse_radix(digits: &str, radix: u32) -> Option<f64> {
+    digits
+        .chars()
+        .filter(|c| *c != '_')
+        .try_fold(0.0, |acc, c| {
+            Some(acc * radix as f64 + c.to_digit(radix)? as f64)
+        })
 }
 
 fn string(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
     let vm = &mut cc.vm;
-    let prev = cc.parser.previous.as_ref().unwrap().clone().lexeme.unwrap();
-    let w = create_string(vm, &prev[1..prev.len() - 1]);
+    let prev = cc.parser.previous_lexeme();
+    let body = prev
+        .get(1..prev.len().saturating_sub(1))
+        .unwrap_or_default();
+    let w = create_string(vm, body);
     cc.emit_constant(w.into())
 }
 
@@ -286,7 +468,7 @@ fn variable(cc: &mut Compiler<'_, '_>, can_assign: bool) {
 }
 
 fn literal(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
-    match cc.parser.previous.as_ref().unwrap().ttype {
+    match cc.parser.previous_type() {
         TType::False => cc.emit_byte(OpCode::False.into()),
         TType::True => cc.emit_byte(OpCode::True.into()),
         TType::Nil => cc.emit_byte(OpCode::Nil.into()),
@@ -311,32 +493,24 @@ fn or_(cc: &mut Compiler<'_, '_>, _can_assign: bool) {
     cc.patch_jump(end_jump);
 }
 
-impl From<Precedence> for usize {
-    fn from(value: Precedence) -> Self {
-        value as usize
-    }
-}
-
-impl TryFrom<usize> for Precedence {
-    type Error = CompileErrors;
-    fn try_from(value: usize) -> Result<Self, Self::Error> {
-        let last_prec: usize = Precedence::Primary.into();
-        if value < last_prec + 1 {
-            Ok(unsafe { std::mem::transmute(value) })
-        } else {
-            Err(CompileErrors::InvalidPrecedence)
-        }
-    }
-}
-
 #[derive(Debug)]
 pub struct Local<'loc> {
     pub name: &'loc str,
     pub depth: usize,
+    // stack slot of its value, past the operands of any expression it was
+    // declared in
+    pub slot: usize,
+    // false until its initializer has been compiled
+    pub initialized: bool,
 }
 
 impl<'loc> Local<'loc> {
-    pub fn new(name: &'loc str, depth: usize) -> Self {
-        Local { name, depth }
+    pub fn new(name: &'loc str, depth: usize, slot: usize) -> Self {
+        Local {
+            name,
+            depth,
+            slot,
+            initialized: false,
+        }
     }
 }
@@ -0,0 +1,99 @@
+use std::fmt::Write;
+
+use crate::{
+    cformat,
+    heap::Heap,
+    table::Table,
+    value::{InternString, Value},
+};
+
+// globals of a module listed before the rest are only counted
+const MAX_ENTRIES: usize = 16;
+// modules inside modules listed before the next is cut to `{ ... }`
+const MAX_DEPTH: usize = 2;
+
+// how the repl shows a value: its repr, colored by type when `colors`, and
+// for a module its globals one per line beneath it, aligned and sorted by
+// name. Big or deeply nested modules are cut short
+pub fn pretty(value: &Value, heap: &Heap, colors: bool) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value, heap, colors, 0);
+    out
+}
+
+// every global of `globals` the way `pretty` lists a module's, but all of
+// them and without the braces, for the repl's `:globals`
+pub fn pretty_globals(globals: &Table<InternString, Value>, heap: &Heap, colors: bool) -> String {
+    let mut out = String::new();
+    write_entries(&mut out, &entries(globals, heap), heap, colors, 0);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value, heap: &Heap, colors: bool, depth: usize) {
+    let repr = value.repr(heap);
+    let text = match value {
+        Value::Number(_) => cformat!(colors, Cyan, "{}", repr),
+        Value::String(_) => cformat!(colors, Green, "{}", repr),
+        Value::Bool(_) => cformat!(colors, Yellow, "{}", repr),
+        Value::Nil => cformat!(colors, Gray, "{}", repr),
+        Value::Native(_) | Value::Foreign(_) => cformat!(colors, LightBlue, "{}", repr),
+        Value::Module(module) => {
+            let module = heap.get(*module);
+            out.push_str(&cformat!(colors, Purple, "{}", repr));
+            let entries = entries(&module.globals, heap);
+            if entries.is_empty() {
+                return;
+            }
+            if depth >= MAX_DEPTH {
+                out.push_str(" { ... }");
+                return;
+            }
+            out.push_str(" {\n");
+            let shown = &entries[..entries.len().min(MAX_ENTRIES)];
+            write_entries(out, shown, heap, colors, depth + 1);
+            let indent = "  ".repeat(depth + 1);
+            if entries.len() > shown.len() {
+                let _ = writeln!(out, "{}... {} more", indent, entries.len() - shown.len());
+            }
+            let _ = write!(out, "{}}}", "  ".repeat(depth));
+            return;
+        }
+    };
+    out.push_str(&text);
+}
+
+// the globals a script defined, sorted by name. Natives are left out, every
+// module and script starts with the same ones
+fn entries<'h>(globals: &Table<InternString, Value>, heap: &'h Heap) -> Vec<(&'h str, Value)> {
+    let mut entries: Vec<_> = globals
+        .iter()
+        .filter(|(_, value)| !matches!(value, Value::Native(_)))
+        .map(|(name, value)| (heap.get(*name).as_str(), *value))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+// one `name: value` line each, the values lined up
+fn write_entries(
+    out: &mut String,
+    entries: &[(&str, Value)],
+    heap: &Heap,
+    colors: bool,
+    depth: usize,
+) {
+    let width = entries.iter().map(|(name, _)| name.chars().count()).max();
+    let indent = "  ".repeat(depth);
+    for (name, value) in entries {
+        let label = format!("{}:", name);
+        let _ = write!(
+            out,
+            "{}{:<2$} ",
+            indent,
+            label,
+            width.unwrap_or_default() + 1
+        );
+        write_value(out, value, heap, colors, depth);
+        out.push_str(if depth > 0 { ",\n" } else { "\n" });
+    }
+}
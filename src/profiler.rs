@@ -0,0 +1,134 @@
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+use crate::{opcode::OpCode, vm::VM};
+
+// instruction counts gathered by the vm while `VM::profile` is set
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub lines: HashMap<usize, u64>,
+    pub opcodes: HashMap<u8, u64>,
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl Profile {
+    pub fn record(&mut self, line: Option<usize>, byte: u8) {
+        self.instructions += 1;
+        if let Some(line) = line {
+            *self.lines.entry(line).or_default() += 1;
+        }
+        *self.opcodes.entry(byte).or_default() += 1;
+    }
+
+    // hottest first, ties broken by line / opcode so the output is stable
+    fn sorted<K: Copy + Ord>(counts: &HashMap<K, u64>) -> Vec<(u64, K)> {
+        let mut rows = counts
+            .iter()
+            .map(|(key, count)| (*count, *key))
+            .collect::<Vec<_>>();
+        rows.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        rows
+    }
+
+    // written to stderr so it does not mix with the program's own output
+    pub fn report(&self) {
+        let total = self.instructions.max(1) as f64;
+        eprintln!("==profile==");
+        eprintln!(
+            "{} instructions in {:.3}ms",
+            self.instructions,
+            self.elapsed.as_secs_f64() * 1000.0
+        );
+
+        eprintln!("==lines==");
+        for (count, line) in Self::sorted(&self.lines) {
+            eprintln!(
+                "line {:<6} {:>10} {:>6.2}%",
+                line,
+                count,
+                count as f64 * 100.0 / total
+            );
+        }
+
+        eprintln!("==opcodes==");
+        for (count, byte) in Self::sorted(&self.opcodes) {
+            opcode_row(byte, count, total);
+        }
+    }
+}
+
+fn opcode_row(byte: u8, count: u64, total: f64) {
+    let name = OpCode::try_from(byte)
+        .map(|op| op.to_string())
+        .unwrap_or_else(|_| format!("<{:#04x}>", byte));
+    eprintln!(
+        "{:<16} {:>10} {:>6.2}%",
+        name,
+        count,
+        count as f64 * 100.0 / total
+    );
+}
+
+// executions of each opcode, gathered while `VM::op_counts` is set. Only an
+// increment per instruction, unlike a profile there are no lines or timings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpCounts {
+    pub counts: [u64; 256],
+}
+
+impl Default for OpCounts {
+    fn default() -> Self {
+        Self { counts: [0; 256] }
+    }
+}
+
+impl OpCounts {
+    pub fn record(&mut self, byte: u8) {
+        self.counts[byte as usize] += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // the opcodes that ran, most executed first, ties by opcode
+    pub fn sorted(&self) -> Vec<(u64, u8)> {
+        let counts = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(byte, count)| (byte as u8, *count))
+            .collect();
+        Profile::sorted(&counts)
+    }
+
+    // written to stderr, like a profile
+    pub fn report(&self) {
+        let total = self.total();
+        eprintln!("==ops==");
+        eprintln!("{} instructions", total);
+        for (count, byte) in self.sorted() {
+            opcode_row(byte, count, total.max(1) as f64);
+        }
+    }
+}
+
+// what a run cost, for `--time`. The stack peak is in slots, the heap peak
+// counts every object alive at once, collected or not by the end
+pub fn time_report(vm: &VM, compile: Duration, run: Duration) -> String {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let stats = &vm.gc_stats;
+    let mut report = String::from("==time==\n");
+    let _ = writeln!(report, "compile      {:>10.3}ms", ms(compile));
+    let _ = writeln!(report, "run          {:>10.3}ms", ms(run));
+    let _ = writeln!(report, "peak stack   {:>10} slots", vm.peak_stack);
+    let _ = writeln!(report, "peak heap    {:>10} bytes", vm.heap.peak_bytes());
+    let _ = writeln!(
+        report,
+        "gc           {:>10} collections, {:.3}ms paused",
+        stats.collections,
+        ms(stats.duration)
+    );
+    report
+}
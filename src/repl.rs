@@ -0,0 +1,366 @@
+use std::{io::Write, process};
+
+use crate::{
+    cprintln, error::VmErrors, memory, pretty::pretty_globals, report_compile_errors,
+    report_runtime_error, signal::SigintGuard, vm::VM,
+};
+
+pub fn repl(vm: &mut VM) {
+    cprintln!(LightPurple, "{}", "Welcome to lox_byte repl.");
+    cprintln!(Purple, "\tExit -> ctrl + d");
+    cprintln!(Purple, "\tHeap objects -> :heap");
+    cprintln!(Purple, "\tGlobals -> :globals");
+    read_loop(vm);
+    println!();
+    cprintln!(Cyan, "Exiting... Goodbye...");
+    process::exit(0);
+}
+
+// a bare expression without a trailing ';' has its value printed, and a
+// statement missing only its final ';' runs as if it were there. Ctrl-C
+// while it runs stops it and returns to the prompt. `:heap` lists every
+// object on the heap instead, and `:globals` every global but the natives
+fn run_line(vm: &mut VM, line: &str) {
+    if line.trim() == ":heap" {
+        let dump = memory::heap_dump(vm);
+        let _ = vm.out.write_all(dump.as_bytes());
+        return;
+    }
+    if line.trim() == ":globals" {
+        let globals = pretty_globals(&vm.globals, &vm.heap, vm.out.colors());
+        let _ = vm.out.write_all(globals.as_bytes());
+        return;
+    }
+    let guard = SigintGuard::install();
+    let result = vm.interpret_line(line);
+    drop(guard);
+    match result {
+        Ok(()) => {}
+        Err(VmErrors::CompileError(errors)) => report_compile_errors(vm, &errors),
+        Err(VmErrors::RuntimeError(e)) => match e.exit_code() {
+            Some(code) => {
+                let _ = vm.out.flush();
+                process::exit(code)
+            }
+            None => report_runtime_error(vm, &e),
+        },
+    }
+}
+
+#[cfg(feature = "editor")]
+mod editor {
+    use std::{borrow::Cow, env, path::PathBuf};
+
+    use rustyline::{
+        completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+        history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+    };
+
+    use crate::{
+        macros::{colors_enabled, TermColor},
+        token::KEYWORDS,
+        vm::VM,
+    };
+
+    #[derive(Default)]
+    pub struct LoxHelper {
+        // globals and natives defined so far, refreshed after every line
+        globals: Vec<String>,
+    }
+
+    impl LoxHelper {
+        pub fn refresh(&mut self, vm: &VM) {
+            self.globals = vm
+                .globals
+                .keys()
+                .map(|name| vm.heap.get(*name).clone())
+                .collect();
+        }
+    }
+
+    impl Helper for LoxHelper {}
+
+    // keywords and globals starting with the identifier under the cursor
+    impl Completer for LoxHelper {
+        type Candidate = String;
+
+        fn complete(
+            &self,
+            line: &str,
+            pos: usize,
+            _ctx: &Context<'_>,
+        ) -> rustyline::Result<(usize, Vec<String>)> {
+            let start = line[..pos]
+                .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(0, |i| i + 1);
+            let prefix = &line[start..pos];
+            if prefix.is_empty() {
+                return Ok((pos, Vec::new()));
+            }
+
+            let mut candidates: Vec<String> = KEYWORDS
+                .iter()
+                .map(|(word, _)| *word)
+                .chain(self.globals.iter().map(String::as_str))
+                .filter(|name| name.starts_with(prefix))
+                .map(str::to_owned)
+                .collect();
+            candidates.sort();
+            candidates.dedup();
+            Ok((start, candidates))
+        }
+    }
+
+    impl Hinter for LoxHelper {
+        type Hint = String;
+    }
+
+    impl Validator for LoxHelper {}
+
+    impl Highlighter for LoxHelper {
+        fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+            &'s self,
+            prompt: &'p str,
+            _default: bool,
+        ) -> Cow<'b, str> {
+            if !colors_enabled() {
+                return Cow::Borrowed(prompt);
+            }
+            let (name, arrow) = prompt.split_at(prompt.find(' ').unwrap_or(0));
+            Cow::Owned(format!(
+                "{}{}{}{}{}",
+                TermColor::LightCyan,
+                name,
+                TermColor::LightBlue,
+                arrow,
+                TermColor::NoColor
+            ))
+        }
+    }
+
+    pub type LoxEditor = Editor<LoxHelper, DefaultHistory>;
+
+    pub fn history_path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".lox_byte_history"))
+    }
+
+    pub fn new_editor(vm: &VM) -> rustyline::Result<LoxEditor> {
+        let mut editor = LoxEditor::new()?;
+        let mut helper = LoxHelper::default();
+        helper.refresh(vm);
+        editor.set_helper(Some(helper));
+        if let Some(path) = history_path() {
+            // a missing history file is expected on first run
+            let _ = editor.load_history(&path);
+        }
+        Ok(editor)
+    }
+
+    pub fn is_interrupt(err: &ReadlineError) -> bool {
+        matches!(err, ReadlineError::Interrupted)
+    }
+
+    pub fn is_eof(err: &ReadlineError) -> bool {
+        matches!(err, ReadlineError::Eof)
+    }
+}
+
+// arrow keys, ctrl-a/e and history recall, saved to ~/.lox_byte_history
+#[cfg(feature = "editor")]
+fn read_loop(vm: &mut VM) {
+    let mut editor = match editor::new_editor(vm) {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("failed to start line editor: {}", err);
+            return;
+        }
+    };
+
+    loop {
+        match editor.readline("lox > ") {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                run_line(vm, &line);
+                if let Some(helper) = editor.helper_mut() {
+                    helper.refresh(vm);
+                }
+            }
+            // ctrl-c clears the line, ctrl-d exits
+            Err(err) if editor::is_interrupt(&err) => continue,
+            Err(err) if editor::is_eof(&err) => break,
+            Err(err) => {
+                eprintln!("failed to get input: {}", err);
+                break;
+            }
+        }
+    }
+
+    if let Some(path) = editor::history_path() {
+        if let Err(err) = editor.save_history(&path) {
+            eprintln!("failed to save history: {}", err);
+        }
+    }
+}
+
+#[cfg(not(feature = "editor"))]
+fn read_loop(vm: &mut VM) {
+    use std::io::{self, Write};
+
+    use crate::cprint;
+
+    let mut buffer = String::new();
+    loop {
+        cprint!(LightCyan, "{}", "lox");
+        cprint!(LightBlue, " > ");
+        io::stdout().flush().expect("Failed to flush stdout");
+        buffer.clear();
+        match io::stdin().read_line(&mut buffer) {
+            Ok(0) => return,
+            Ok(_) => run_line(vm, &buffer),
+            Err(err) => {
+                eprintln!("failed to get input: {}", err);
+                continue;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Sink;
+
+    // runs each line as if typed at the prompt, returning the vm with what
+    // was printed and what was reported
+    fn session(lines: &[&str]) -> (VM, String, String) {
+        let mut vm = VM::new();
+        let (out, output) = Sink::capture();
+        let (diagnostics, errors) = Sink::capture();
+        vm.out = out;
+        vm.diagnostics = diagnostics;
+        for line in lines {
+            run_line(&mut vm, line);
+        }
+        (vm, output.contents(), errors.contents())
+    }
+
+    #[test]
+    fn an_interrupt_stops_one_line_and_is_then_cleared() {
+        let mut vm = VM::new();
+        let (out, output) = Sink::capture();
+        let (diagnostics, errors) = Sink::capture();
+        vm.out = out;
+        vm.diagnostics = diagnostics;
+        vm.interrupt_handle().interrupt();
+        run_line(&mut vm, "print 1;");
+        run_line(&mut vm, "print 2;");
+        assert!(errors.contents().contains("Interrupted."));
+        assert_eq!(output.contents(), "2\n");
+    }
+
+    #[test]
+    fn runtime_error_leaves_an_empty_stack() {
+        let (vm, _, errors) = session(&["print 1 + (2 + nil);"]);
+        assert!(errors.contains("Cannot add 2 and nil"), "{errors}");
+        assert!(vm.stack.is_empty(), "{:?}", vm.stack);
+    }
+
+    #[test]
+    fn locals_after_an_error_read_their_own_slots() {
+        let (_, output, _) = session(&["print 1 + (2 + nil);", "{ var a = 5; print a; }"]);
+        assert_eq!(output, "5\n");
+    }
+
+    #[test]
+    fn globals_survive_an_error() {
+        let (_, output, errors) = session(&[
+            "var count = 1;",
+            "count = count + missing;",
+            "count = count + 1;",
+            "count",
+        ]);
+        assert!(errors.contains("missing"), "{errors}");
+        assert_eq!(output, "2\n");
+    }
+
+    #[test]
+    fn interned_strings_survive_an_error() {
+        let (_, output, _) = session(&[
+            "var greeting = \"hi\";",
+            "print greeting + nil;",
+            "print greeting == \"hi\";",
+        ]);
+        assert_eq!(output, "true\n");
+    }
+
+    #[test]
+    fn compile_error_then_continue() {
+        let (vm, output, errors) = session(&["var x = ;", "var x = 3;", "x * 2"]);
+        assert!(errors.contains("Expected expression."), "{errors}");
+        assert_eq!(output, "6\n");
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn echo_quotes_strings_but_print_does_not() {
+        let (vm, output, _) = session(&["var s = \"a\\b\";", "s", "print s;", "\"1\" + \"\"", "1"]);
+        assert_eq!(output, "\"a\\\\b\"\na\\b\n\"1\"\n1\n");
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn error_inside_a_block_drops_its_locals() {
+        let (vm, output, _) = session(&[
+            "{ var a = 1; var b = 2; print a + b + nil; }",
+            "{ var c = 7; print c; }",
+        ]);
+        assert_eq!(output, "7\n");
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn heap_command_names_what_keeps_each_object() {
+        let (_, output, _) = session(&["var kept = \"a\" + \"b\";", ":heap"]);
+        assert!(output.starts_with("-- heap: "));
+        let line = output
+            .lines()
+            .find(|line| line.ends_with("\"ab\""))
+            .unwrap();
+        assert!(line.contains(" string "));
+        assert!(line.contains("global kept"));
+    }
+
+    #[test]
+    fn globals_command_lists_globals_aligned_and_sorted() {
+        let (_, output, _) = session(&["var beta = 2; var a = \"x\"; var c;", ":globals"]);
+        assert_eq!(output, "a:    \"x\"\nbeta: 2\nc:    nil\n");
+    }
+
+    #[test]
+    fn statements_before_an_error_on_the_same_line_take_effect() {
+        let (_, output, errors) = session(&[
+            "var a = 1; print a; print +;",
+            "var b = a + 1; print nil + 1; var c = 3;",
+            "print b;",
+            "print c;",
+        ]);
+        assert_eq!(output, "1\n2\n");
+        assert!(errors.contains("line 1:27"));
+        assert!(errors.contains("Cannot add nil and 1"));
+        assert!(errors.contains("Value c, is not defined."));
+    }
+
+    #[test]
+    fn a_missing_final_semicolon_is_supplied() {
+        let (_, output, errors) = session(&[
+            "var a = 1\n",
+            "print a; print a + 1",
+            "var b = 2 print b;",
+            "{ var c = 3; print c }",
+        ]);
+        assert_eq!(output, "1\n2\n");
+        assert!(errors.contains("after variable declaration"), "{errors}");
+        assert!(errors.contains("after print statement"), "{errors}");
+        assert!(VM::new().interpret_echo("var a = 1").is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use std::{env, io::Write, ops::Range, path::Path};
+
+use crate::{
+    cformat,
+    error::{CompileErrors, Diagnostic, ModuleErrors, RuntimeErrors},
+    trace::json_string,
+    vm::VM,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Human,
+    // one object per line, for editors and CI
+    Json,
+}
+
+impl TryFrom<&str> for ErrorFormat {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "Unknown error format '{}', expected human or json",
+                other
+            )),
+        }
+    }
+}
+
+// an error laid out the way rustc prints them:
+//
+// error[E0004]: Expect ';' after value.
+//  --> script.lox:1:9
+//   |
+// 1 | print 1 print 2;
+//   |         ^^^^^
+//   = help: did you forget a ';'?
+struct Report<'a> {
+    title: &'static str,
+    code: &'static str,
+    message: String,
+    path: Option<&'a str>,
+    line: Option<usize>,
+    // char column and width of the underline, runtime errors in code
+    // compiled without debug info only know their line and underline all of it
+    span: Option<(usize, usize)>,
+    snippet: Option<&'a str>,
+    hint: Option<&'static str>,
+    notes: Vec<String>,
+}
+
+impl Report<'_> {
+    fn render(&self, color: bool) -> String {
+        let mut out = format!(
+            "{}{}\n",
+            cformat!(color, LightRed, "{}[{}]: ", self.title, self.code),
+            cformat!(color, White, "{}", self.message)
+        );
+        let width = self.line.map_or(1, |line| line.to_string().len());
+        let pad = " ".repeat(width);
+        let location = match (self.path, self.line, self.span) {
+            (Some(path), Some(line), Some((column, _))) => format!("{}:{}:{}", path, line, column),
+            (Some(path), Some(line), None) => format!("{}:{}", path, line),
+            (Some(path), None, _) => format!("{} (unknown line)", path),
+            (None, Some(line), Some((column, _))) => format!("line {}:{}", line, column),
+            (None, Some(line), None) => format!("line {}", line),
+            (None, None, _) => "unknown line".into(),
+        };
+        out += &format!(
+            "{}{} {}\n",
+            pad,
+            cformat!(color, LightBlue, "-->"),
+            location
+        );
+        let bar = cformat!(color, LightBlue, "|");
+        if let (Some(line), Some(text)) = (self.line, self.snippet) {
+            let (offset, len) = match self.span {
+                Some((column, len)) => (column.saturating_sub(1), len),
+                None => {
+                    let indent = text.chars().take_while(|c| c.is_whitespace()).count();
+                    (indent, text.trim().chars().count())
+                }
+            };
+            out += &format!("{} {}\n", pad, bar);
+            out += &format!(
+                "{} {} {}\n",
+                cformat!(color, LightBlue, "{}", line),
+                bar,
+                text
+            );
+            out += &format!(
+                "{} {} {}{}\n",
+                pad,
+                bar,
+                " ".repeat(offset),
+                cformat!(color, LightRed, "{}", "^".repeat(len.max(1)))
+            );
+        }
+        let equals = cformat!(color, LightBlue, "=");
+        if let Some(hint) = self.hint {
+            out += &format!("{} {} help: {}\n", pad, equals, hint);
+        }
+        for note in &self.notes {
+            out += &format!("{} {} note: {}\n", pad, equals, note);
+        }
+        out
+    }
+
+    fn render_json(&self) -> String {
+        let (column, len) = match self.span {
+            Some((column, len)) => (column.to_string(), len.to_string()),
+            None => ("null".into(), "null".into()),
+        };
+        let optional = |text: Option<&str>| text.map_or("null".into(), json_string);
+        let notes = self
+            .notes
+            .iter()
+            .map(|note| json_string(note))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\"severity\":\"error\",\"code\":{},\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"length\":{},\"hint\":{},\"notes\":[{}]}}\n",
+            json_string(self.code),
+            json_string(&self.message),
+            optional(self.path),
+            self.line.map_or("null".into(), |line| line.to_string()),
+            column,
+            len,
+            optional(self.hint),
+            notes
+        )
+    }
+
+    fn write(&self, vm: &mut VM) {
+        let text = match vm.error_format {
+            ErrorFormat::Human => self.render(vm.diagnostics.colors()),
+            ErrorFormat::Json => self.render_json(),
+        };
+        let _ = write!(vm.diagnostics, "{}", text);
+    }
+}
+
+// paths are shown relative to the working directory when they are inside it
+fn display_path(path: &str) -> String {
+    env::current_dir()
+        .ok()
+        .and_then(|cwd| {
+            Path::new(path)
+                .strip_prefix(cwd)
+                .ok()
+                .map(|p| p.display().to_string())
+        })
+        .unwrap_or_else(|| path.to_string())
+}
+
+// each error with its source line, followed by those of a module that
+// failed to compile because of it
+pub fn report_compile_errors(vm: &mut VM, errors: &[Diagnostic]) {
+    let path = vm.modules.script.as_deref().map(display_path);
+    write_compile_errors(vm, errors, path.as_deref());
+}
+
+fn write_compile_errors(vm: &mut VM, errors: &[Diagnostic], path: Option<&str>) {
+    for error in errors {
+        let width = error
+            .lexeme
+            .as_deref()
+            .and_then(|lexeme| lexeme.lines().next())
+            .map_or(1, |l| l.chars().count());
+        let report = Report {
+            title: "error",
+            code: error.error.code(),
+            message: error.error.to_string(),
+            path,
+            line: Some(error.span.line),
+            span: Some((error.span.column, width)),
+            snippet: error.snippet.as_deref(),
+            hint: error.error.hint(),
+            notes: Vec::new(),
+        };
+        report.write(vm);
+        if let CompileErrors::Import(module) = &error.error {
+            if let ModuleErrors::Compile(path, errors) = module.as_ref() {
+                write_compile_errors(vm, errors, Some(path));
+            }
+        }
+    }
+}
+
+// char column and width of a byte span, cut at the end of its first line
+fn column_span(source: &str, span: &Range<usize>) -> Option<(usize, usize)> {
+    let before = source.get(..span.start)?;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    let text = source.get(span.clone())?;
+    let width = text.lines().next().unwrap_or(text).chars().count();
+    Some((column, width))
+}
+
+// the line that failed, inside the module that raised it if there is one,
+// with a note for each import it was reached through
+pub fn report_runtime_error(vm: &mut VM, err: &RuntimeErrors) {
+    let mut frames = vec![(None, vm.error_line)];
+    let mut error = err;
+    while let RuntimeErrors::InModule(path, line, inner) = error {
+        frames.push((Some(path.as_str()), *line));
+        error = inner;
+    }
+    let (key, line) = frames.pop().unwrap_or_default();
+    let source = line.and_then(|_| vm.source_of(key));
+    let snippet = line.and_then(|line| source.as_deref()?.lines().nth(line.checked_sub(1)?));
+    // the span is of the script's chunk, not of a module's
+    let span = match (key, &vm.error_span, &source) {
+        (None, Some(span), Some(source)) => column_span(source, span),
+        _ => None,
+    };
+    let script = vm.modules.script.as_deref().map(display_path);
+    let path = key.map(display_path).or(script.clone());
+    let notes = frames
+        .iter()
+        .rev()
+        .map(|(key, line)| {
+            let path = key.map(display_path).or(script.clone());
+            let path = path.as_deref().unwrap_or("script");
+            match line {
+                Some(line) => format!("imported from {}:{}", path, line),
+                None => format!("imported from {} (unknown line)", path),
+            }
+        })
+        .collect();
+    let report = Report {
+        title: "runtime error",
+        code: error.code(),
+        message: error.to_string(),
+        path: path.as_deref(),
+        line,
+        span,
+        snippet,
+        hint: error.hint(),
+        notes,
+    };
+    report.write(vm);
+}
@@ -1,4 +1,4 @@
-use std::{char, iter::Peekable, str::CharIndices};
+use std::{char, iter::Peekable, ops::Range, str::CharIndices};
 
 use crate::token::{TType, Token};
 
@@ -8,48 +8,116 @@ pub struct Scanner<'a> {
     token_start: usize,
     chars: Peekable<CharIndices<'a>>,
     line: usize,
+    // byte offset of the first char on the current line
+    line_start: usize,
+    token_line: usize,
+    token_column: usize,
+    // byte offset `token_column` was counted up to, each token's column
+    // counts on from the previous one rather than from its line's start
+    column_offset: usize,
+    // `//` comments come out as Comment tokens instead of being skipped
+    comments: bool,
+    // where decode_lossy put a U+FFFD for bytes that weren't UTF-8, sorted
+    invalid_utf8: Vec<usize>,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
         let mut chars = source.char_indices().peekable();
-        Self {
+        let mut scanner = Self {
             source,
             token_start: chars.peek().map(|(index, _c)| *index).unwrap_or_default(),
             chars,
             line: 1,
+            line_start: 0,
+            token_line: 1,
+            token_column: 1,
+            column_offset: 0,
+            comments: false,
+            invalid_utf8: Vec::new(),
+        };
+        scanner.skip_shebang();
+        scanner
+    }
+
+    // `#!/usr/bin/env lox_byte` as the first line of an executable script,
+    // the newline is left for skip_whitespace so line numbers stay correct
+    fn skip_shebang(&mut self) {
+        if self.source.starts_with("#!") {
+            while let Some((_index, char)) = self.chars.peek() {
+                if *char == '\n' {
+                    break;
+                }
+                self.advance();
+            }
         }
     }
 
+    // for a source that continues an earlier one, see Compiler::feed
+    pub fn starting_at_line(mut self, line: usize) -> Self {
+        self.line = line;
+        self.token_line = line;
+        self
+    }
+
+    // scans the source from `offset` on, with lines, columns and spans
+    // still counted from its start
+    pub fn skipping_to(mut self, offset: usize) -> Self {
+        while self.chars.next_if(|(index, _)| *index < offset).is_some() {}
+        let before = &self.source[..offset];
+        self.line = 1 + before.matches('\n').count();
+        self.token_line = self.line;
+        self.line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        self.token_start = offset;
+        self
+    }
+
+    // for highlighting, which shows comments the compiler never sees
+    pub fn keeping_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    // for a source from decode_lossy, a U+FFFD anywhere else is only a char
+    pub fn invalid_utf8_at(mut self, offsets: Vec<usize>) -> Self {
+        self.invalid_utf8 = offsets;
+        self
+    }
+
+    fn has_invalid_utf8(&self, range: Range<usize>) -> bool {
+        let first = self
+            .invalid_utf8
+            .partition_point(|&offset| offset < range.start);
+        self.invalid_utf8
+            .get(first)
+            .is_some_and(|&offset| offset < range.end)
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
     fn advance(&mut self) -> Option<char> {
         self.chars.next().map(|(_index, char)| char)
     }
 
     fn match_char(&mut self, expected: char) -> bool {
         match self.chars.peek() {
-            Some((_index, char)) => {
-                if *char == expected {
-                    let _ = self.advance();
-                    true
-                } else {
-                    false
-                }
+            Some((_index, char)) if *char == expected => {
+                let _ = self.advance();
+                true
             }
-            None => false,
+            _ => false,
         }
     }
 
     fn match_str(&mut self, expected: &str) -> bool {
-        let strlen = expected.len();
         let byte = match self.chars.peek() {
             Some((index, _char)) => *index,
             None => return false,
         };
-        let offset = byte + strlen;
-        if offset > self.source.len() {
-            return false;
-        }
-        if expected == &self.source[byte..offset] {
+        // comparing the rest of the source avoids slicing inside a char
+        if self.source[byte..].starts_with(expected) {
             // consume character if true
             for _char in 0..expected.chars().count() {
                 let _ = self.advance();
@@ -72,40 +140,53 @@ impl<'a> Scanner<'a> {
     }
 
     fn make_token(&mut self, ttype: TType) -> Token<'a> {
-        Token::new(ttype, Some(self.content()), self.line)
+        let content = self.content();
+        Token::new(
+            ttype,
+            Some(content),
+            self.token_line,
+            self.token_column,
+            self.token_start,
+            content.len(),
+        )
+    }
+
+    fn newline(&mut self) {
+        self.advance();
+        self.line += 1;
+        self.line_start = self.current();
     }
 
     fn skip_whitespace(&mut self) {
         loop {
-            match self.chars.peek() {
-                Some((_index, ' ')) | Some((_index, '\t')) | Some((_index, '\r')) => {
-                    self.advance();
-                }
-                Some((_index, '\n')) => {
-                    self.line += 1;
+            match self.chars.peek().map(|(_index, char)| *char) {
+                Some(' ') | Some('\t') | Some('\r') => {
                     self.advance();
                 }
-                Some((_index, '/')) => {
-                    if self.match_str("//") {
-                        while let Some((_index, char)) = self.chars.peek() {
-                            if *char == '\n' {
-                                break;
-                            } else {
-                                self.advance();
-                            }
-                        }
-                    } else {
-                        return;
-                    }
-                }
+                Some('\n') => self.newline(),
+                Some('/') if !self.comments && self.match_str("//") => self.skip_line(),
                 _ => return,
             }
         }
     }
 
+    // up to the newline, which is left for skip_whitespace
+    fn skip_line(&mut self) {
+        while self.chars.next_if(|(_index, char)| *char != '\n').is_some() {}
+    }
+
     pub fn scan_token(&mut self) -> Token<'a> {
         self.skip_whitespace();
         self.token_start = self.current();
+        self.token_line = self.line;
+        if self.line_start > self.column_offset {
+            self.token_column = 1;
+            self.column_offset = self.line_start;
+        }
+        self.token_column += self.source[self.column_offset..self.token_start]
+            .chars()
+            .count();
+        self.column_offset = self.token_start;
         // let next = self.chars.peek().map(|(_, char)| *char);
 
         let c = self.advance();
@@ -117,7 +198,14 @@ impl<'a> Scanner<'a> {
         }
 
         match c {
-            None => Token::new(TType::Eof, None, self.line),
+            None => Token::new(
+                TType::Eof,
+                None,
+                self.line,
+                self.token_column,
+                self.token_start,
+                0,
+            ),
             Some(char) => match char {
                 '(' => self.make_token(TType::LeftParen),
                 ')' => self.make_token(TType::RightParen),
@@ -128,6 +216,10 @@ impl<'a> Scanner<'a> {
                 '.' => self.make_token(TType::Dot),
                 '+' => self.make_token(TType::Plus),
                 '-' => self.make_token(TType::Minus),
+                '/' if self.comments && self.match_char('/') => {
+                    self.skip_line();
+                    self.make_token(TType::Comment)
+                }
                 '/' => self.make_token(TType::Slash),
                 '*' => self.make_token(TType::Star),
                 '!' => {
@@ -159,6 +251,12 @@ impl<'a> Scanner<'a> {
                     }
                 }
                 '"' => self.string_literal(),
+                // what bytes that weren't valid UTF-8 were decoded to
+                char::REPLACEMENT_CHARACTER
+                    if self.has_invalid_utf8(self.token_start..self.token_start + 1) =>
+                {
+                    self.make_token(TType::InvalidUtf8Error)
+                }
                 _ => self.make_token(TType::UnexpectedCharacterError),
             },
         }
@@ -166,7 +264,7 @@ impl<'a> Scanner<'a> {
 
     fn identifier(&mut self) -> Token<'a> {
         while match self.chars.peek() {
-            Some((_index, char)) => is_identifier(&Some(*char)),
+            Some((_index, char)) => is_identifier(&Some(*char)) || is_digit(&Some(*char)),
             None => false,
         } {
             self.advance();
@@ -177,100 +275,143 @@ impl<'a> Scanner<'a> {
     }
 
     fn identifier_type(&mut self) -> TType {
-        let word = self.content();
-        if word.is_empty() {
-            return TType::Identifer;
-        }
-        match &word[..1] {
-            "a" => check_key(word, "and", 1, TType::And),
-            "c" => check_key(word, "class", 1, TType::Class),
-            "e" => check_key(word, "else", 1, TType::Else),
-            "f" => {
-                if word.len() < 2 {
-                    TType::Identifer
-                } else {
-                    match &word[1..2] {
-                        "a" => check_key(word, "false", 2, TType::False),
-                        "o" => check_key(word, "for", 2, TType::For),
-                        "u" => check_key(word, "fun", 2, TType::Fun),
-                        _ => TType::Identifer,
-                    }
-                }
-            }
-            "i" => check_key(word, "if", 1, TType::If),
-            "n" => check_key(word, "nil", 1, TType::Nil),
-            "o" => check_key(word, "or", 1, TType::Or),
-            "p" => check_key(word, "print", 1, TType::Print),
-            "r" => check_key(word, "return", 1, TType::Return),
-            "s" => check_key(word, "super", 1, TType::Super),
-            "t" => {
-                if word.len() < 2 {
-                    TType::Identifer
-                } else {
-                    match &word[1..2] {
-                        "h" => check_key(word, "this", 2, TType::This),
-                        "r" => check_key(word, "true", 2, TType::True),
-                        _ => TType::Identifer,
-                    }
-                }
-            }
-            "v" => check_key(word, "var", 1, TType::Var),
-            "w" => check_key(word, "while", 1, TType::While),
-            _ => TType::Identifer,
-        }
+        TType::keyword(self.content()).unwrap_or(TType::Identifer)
     }
 
+    // the opening quote has already been consumed, anything up to the
+    // closing quote belongs to the literal
     fn string_literal(&mut self) -> Token<'a> {
-        loop {
-            match self.chars.peek() {
-                Some((_index, '"')) => {
+        while let Some(&(_index, char)) = self.chars.peek() {
+            match char {
+                '"' => {
                     self.advance();
+                    let end = self.current();
+                    if self.has_invalid_utf8(self.token_start..end) {
+                        return self.make_token(TType::InvalidUtf8Error);
+                    }
                     return self.make_token(TType::String);
                 }
-                Some((_index, '\n')) => {
-                    self.advance();
-                    self.line += 1;
-                }
-                None => return self.make_token(TType::UnterminatedStringError),
+                '\n' => self.newline(),
                 _ => {
                     self.advance();
                 }
             }
         }
+        self.make_token(TType::UnterminatedStringError)
     }
 
-    fn consume_number(&mut self) {
-        while match self.chars.peek() {
-            Some((_index, char)) => is_digit(&Some(*char)),
-            None => false,
-        } {
+    // the first digit has already been consumed
+    fn consume_number(&mut self) -> Option<usize> {
+        self.consume_digits(10, 1)
+    }
+
+    // returns the number of digits seen, or None when a '_' separator
+    // leads, trails or is doubled
+    fn consume_digits(&mut self, radix: u32, mut count: usize) -> Option<usize> {
+        let mut separator = false;
+        let mut malformed = false;
+        while let Some(&(_index, char)) = self.chars.peek() {
+            if char == '_' {
+                malformed |= count == 0 || separator;
+                separator = true;
+            } else if char.is_digit(radix) {
+                count += 1;
+                separator = false;
+            } else {
+                break;
+            }
             self.advance();
         }
+        if malformed || separator {
+            None
+        } else {
+            Some(count)
+        }
+    }
+
+    fn radix_literal(&mut self, radix: u32, error: TType) -> Token<'a> {
+        // skip the 'x' / 'b'
+        self.advance();
+        match self.consume_digits(radix, 0) {
+            None => self.make_token(TType::MalformedSeparatorError),
+            Some(0) => self.make_token(error),
+            Some(_) => self.make_token(TType::Number),
+        }
     }
 
     fn number_literal(&mut self) -> Token<'a> {
-        self.consume_number();
+        if self.content() == "0" {
+            match self.chars.peek() {
+                Some((_index, 'x')) | Some((_index, 'X')) => {
+                    return self.radix_literal(16, TType::MalformedHexError)
+                }
+                Some((_index, 'b')) | Some((_index, 'B')) => {
+                    return self.radix_literal(2, TType::MalformedBinaryError)
+                }
+                _ => {}
+            }
+        }
+
+        let mut valid = self.consume_number().is_some();
         let mut ch = self.chars.clone();
         if let Some((_index, '.')) = ch.next() {
             if let Some((_index, char)) = ch.next() {
                 if is_digit(&Some(char)) {
                     self.advance();
-                    self.consume_number();
+                    self.advance();
+                    valid &= self.consume_number().is_some();
                 }
             }
         }
-        self.make_token(TType::Number)
+
+        if self.match_char('e') || self.match_char('E') {
+            if !self.match_char('+') {
+                self.match_char('-');
+            }
+            match self.consume_digits(10, 0) {
+                None => valid = false,
+                Some(0) => return self.make_token(TType::MalformedExponentError),
+                Some(_) => {}
+            }
+        }
+
+        if valid {
+            self.make_token(TType::Number)
+        } else {
+            self.make_token(TType::MalformedSeparatorError)
+        }
     }
 }
 
-fn check_key(word: &str, kw: &str, pos: usize, ttype: TType) -> TType {
-    if word[pos..] == kw[pos..] {
-        ttype
-    } else {
-        TType::Identifer
+// yields tokens up to, but not including, the Eof token
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.scan_token();
+        match token.ttype {
+            TType::Eof => None,
+            _ => Some(token),
+        }
     }
 }
 
+// bytes read as source, those that aren't UTF-8 replaced by U+FFFD as
+// String::from_utf8_lossy does, with the offsets of the replacements for
+// Scanner::invalid_utf8_at. A U+FFFD the source spells out is left alone
+pub fn decode_lossy(bytes: &[u8]) -> (String, Vec<usize>) {
+    let mut text = String::with_capacity(bytes.len());
+    let mut invalid = Vec::new();
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        if !chunk.invalid().is_empty() {
+            invalid.push(text.len());
+            text.push(char::REPLACEMENT_CHARACTER);
+        }
+    }
+    (text, invalid)
+}
+
 fn is_identifier(char: &Option<char>) -> bool {
     match char {
         Some(c) => c.is_ascii_alphabetic() || *c == '_',
@@ -284,3 +425,211 @@ fn is_digit(char: &Option<char>) -> bool {
         None => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Vec<Token<'_>> {
+        Scanner::new(source).collect()
+    }
+
+    fn kinds(source: &str) -> Vec<(TType, &str)> {
+        scan(source)
+            .into_iter()
+            .map(|t| (t.ttype, t.lexeme.unwrap_or_default()))
+            .collect()
+    }
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(kinds("\"abc\""), vec![(TType::String, "\"abc\"")]);
+        assert_eq!(kinds("\"\""), vec![(TType::String, "\"\"")]);
+        assert_eq!(
+            kinds("\"a + b; // not a comment\" ;"),
+            vec![
+                (TType::String, "\"a + b; // not a comment\""),
+                (TType::SemiColon, ";"),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_string_keeps_start_position() {
+        let tokens = scan("\"one\ntwo\" x");
+        assert_eq!(tokens[0].ttype, TType::String);
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 6));
+        assert_eq!(tokens[0].span(), 0..9);
+        assert_eq!(tokens[1].span(), 10..11);
+    }
+
+    #[test]
+    fn unterminated_string() {
+        assert_eq!(
+            kinds("\"abc"),
+            vec![(TType::UnterminatedStringError, "\"abc")]
+        );
+        assert_eq!(kinds("\""), vec![(TType::UnterminatedStringError, "\"")]);
+    }
+
+    #[test]
+    fn number_literals() {
+        for source in [
+            "0", "42", "3.14", "0xFF", "0Xff", "0b1010", "1e9", "1.5e-3", "2E+2", "1_000",
+            "0xFF_FF", "0b1_0",
+        ] {
+            assert_eq!(kinds(source), vec![(TType::Number, source)], "{source}");
+        }
+        assert_eq!(kinds("1."), vec![(TType::Number, "1"), (TType::Dot, ".")]);
+    }
+
+    #[test]
+    fn malformed_number_literals() {
+        assert_eq!(kinds("0x"), vec![(TType::MalformedHexError, "0x")]);
+        assert_eq!(
+            kinds("0b2"),
+            vec![(TType::MalformedBinaryError, "0b"), (TType::Number, "2"),]
+        );
+        assert_eq!(kinds("1e"), vec![(TType::MalformedExponentError, "1e")]);
+        for source in ["1__0", "1_", "0x_F", "1.5_", "1e_5"] {
+            assert_eq!(
+                kinds(source),
+                vec![(TType::MalformedSeparatorError, source)],
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn shebang_is_skipped() {
+        let tokens = scan("#!/usr/bin/env lox_byte\nprint 1;");
+        assert_eq!(tokens[0].ttype, TType::Print);
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn every_registered_keyword_scans() {
+        for (word, ttype) in crate::token::KEYWORDS {
+            assert_eq!(kinds(word), vec![(*ttype, *word)]);
+        }
+        assert_eq!(kinds("f"), vec![(TType::Identifer, "f")]);
+        assert_eq!(kinds("classy"), vec![(TType::Identifer, "classy")]);
+    }
+
+    #[test]
+    fn keyword_literals_and_identifiers() {
+        assert_eq!(
+            kinds("true false nil truthy _nil"),
+            vec![
+                (TType::True, "true"),
+                (TType::False, "false"),
+                (TType::Nil, "nil"),
+                (TType::Identifer, "truthy"),
+                (TType::Identifer, "_nil"),
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers_may_contain_digits() {
+        assert_eq!(
+            kinds("v0 a1b2 _9"),
+            vec![
+                (TType::Identifer, "v0"),
+                (TType::Identifer, "a1b2"),
+                (TType::Identifer, "_9"),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii_strings_and_comments() {
+        assert_eq!(
+            kinds("print \"héllo 世界 🦀\"; // コメント 🎉\nx"),
+            vec![
+                (TType::Print, "print"),
+                (TType::String, "\"héllo 世界 🦀\""),
+                (TType::SemiColon, ";"),
+                (TType::Identifer, "x"),
+            ]
+        );
+        // a lone '/' before a multi-byte char is still division
+        assert_eq!(
+            kinds("/🦀"),
+            vec![(TType::Slash, "/"), (TType::UnexpectedCharacterError, "🦀")]
+        );
+    }
+
+    #[test]
+    fn columns_count_chars_not_bytes() {
+        let tokens = scan("\"世界\" 🦀 x");
+        assert_eq!(tokens[1].ttype, TType::UnexpectedCharacterError);
+        assert_eq!((tokens[1].column, tokens[1].span()), (6, 9..13));
+        assert_eq!((tokens[2].column, tokens[2].span()), (8, 14..15));
+    }
+
+    #[test]
+    fn columns_count_on_from_the_previous_token() {
+        let source = format!("{}é\n  y", "x ".repeat(1000));
+        let tokens = scan(&source);
+        assert_eq!(tokens[999].column, 1999);
+        assert_eq!((tokens[1000].column, tokens[1001].column), (2001, 3));
+        let tokens: Vec<_> = Scanner::new("a é b").skipping_to(2).collect();
+        assert_eq!((tokens[0].column, tokens[1].column), (3, 5));
+    }
+
+    #[test]
+    fn invalid_bytes_are_their_own_error() {
+        let (source, invalid) = decode_lossy(b"var x = \xff; \"a\xc3\";");
+        assert_eq!(invalid, [8, 15]);
+        let tokens: Vec<_> = Scanner::new(&source).invalid_utf8_at(invalid).collect();
+        assert_eq!(tokens[3].ttype, TType::InvalidUtf8Error);
+        assert_eq!(tokens[3].lexeme, Some("\u{FFFD}"));
+        // a string with a bad byte in it is one error
+        assert_eq!(tokens[5].ttype, TType::InvalidUtf8Error);
+        assert_eq!(tokens[5].span(), 13..19);
+    }
+
+    #[test]
+    fn a_replacement_character_in_valid_source_is_a_char() {
+        let (source, invalid) = decode_lossy("\"\u{FFFD}\" \u{FFFD}".as_bytes());
+        assert!(invalid.is_empty());
+        assert_eq!(
+            kinds(&source),
+            vec![
+                (TType::String, "\"\u{FFFD}\""),
+                (TType::UnexpectedCharacterError, "\u{FFFD}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skipping_keeps_positions_in_the_whole_source() {
+        let tokens: Vec<_> = Scanner::new("var a;\nprint a; x").skipping_to(7).collect();
+        assert_eq!(tokens[0].lexeme, Some("print"));
+        assert_eq!(
+            (tokens[0].line, tokens[0].column, tokens[0].span()),
+            (2, 1, 7..12)
+        );
+        assert_eq!((tokens[3].line, tokens[3].column), (2, 10));
+    }
+
+    #[test]
+    fn comments_are_tokens_only_when_kept() {
+        let source = "a / b // half\n// é\nc";
+        assert_eq!(kinds(source).len(), 4);
+        let kept: Vec<_> = Scanner::new(source)
+            .keeping_comments()
+            .map(|t| (t.ttype, t.lexeme.unwrap_or_default()))
+            .collect();
+        assert_eq!(
+            kept[3..],
+            [
+                (TType::Comment, "// half"),
+                (TType::Comment, "// é"),
+                (TType::Identifer, "c"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set by the SIGINT handler, the vm checks and clears it before each
+// instruction so Ctrl-C stops a runaway script instead of the process
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// loads before swapping, so the check on each instruction doesn't write
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed) && INTERRUPTED.swap(false, Ordering::Relaxed)
+}
+
+// catches Ctrl-C while alive, the previous handler is put back on drop
+//
+//     let _guard = signal::SigintGuard::install();
+//     vm.interpret(line)
+#[derive(Debug)]
+pub struct SigintGuard {
+    #[cfg(unix)]
+    previous: libc::sighandler_t,
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+impl SigintGuard {
+    pub fn install() -> Self {
+        // a Ctrl-C pressed before the script started is not for it
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        #[cfg(unix)]
+        {
+            let handler = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // SAFETY: the handler only stores to an atomic
+            let previous = unsafe { libc::signal(libc::SIGINT, handler) };
+            Self { previous }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+}
+
+impl Drop for SigintGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: restores whatever was installed before `install`
+        unsafe {
+            libc::signal(libc::SIGINT, self.previous);
+        }
+        INTERRUPTED.store(false, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::Span;
+
+// what a declared name refers to at run time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Global,
+    // a stack slot, counted from the bottom of the script's stack
+    Local(usize),
+    // the global an import binds its module to
+    Module,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub span: Span,
+    pub binding: Binding,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    // the local it resolved to, None for globals, which are looked up by
+    // name once the whole source has been seen
+    pub local: Option<usize>,
+}
+
+// every declaration and every use of a name in one source, in source order.
+// The compiler records them when its `symbols` is set, for editor tooling
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+impl Symbols {
+    pub fn define(&mut self, name: &str, span: Span, binding: Binding) {
+        self.definitions.push(Definition {
+            name: name.into(),
+            span,
+            binding,
+        });
+    }
+
+    // a use of `name`, resolved to the local in `slot` when it is one. The
+    // live local in a slot is the last one declared there under that name
+    pub fn refer(&mut self, name: &str, span: Span, slot: Option<usize>) {
+        let local = slot.and_then(|slot| {
+            self.definitions
+                .iter()
+                .rposition(|d| d.name == name && d.binding == Binding::Local(slot))
+        });
+        self.references.push(Reference {
+            name: name.into(),
+            span,
+            local,
+        });
+    }
+
+    // where a reference's name was declared. A global is whichever
+    // top level declaration of the name comes first, None for natives and
+    // names never declared
+    pub fn definition_of(&self, reference: &Reference) -> Option<&Definition> {
+        match reference.local {
+            Some(index) => self.definitions.get(index),
+            None => self
+                .definitions
+                .iter()
+                .find(|d| d.name == reference.name && !matches!(d.binding, Binding::Local(_))),
+        }
+    }
+
+    // the definition or reference whose name covers byte `offset`
+    pub fn at(&self, offset: usize) -> Option<Symbol<'_>> {
+        let covers = |span: &Span| span.start <= offset && offset <= span.start + span.len;
+        if let Some(definition) = self.definitions.iter().find(|d| covers(&d.span)) {
+            return Some(Symbol::Definition(definition));
+        }
+        self.references
+            .iter()
+            .find(|r| covers(&r.span))
+            .map(Symbol::Reference)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Symbol<'a> {
+    Definition(&'a Definition),
+    Reference(&'a Reference),
+}
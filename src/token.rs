@@ -1,20 +1,40 @@
 use core::fmt;
+use std::ops::Range;
 
 #[derive(Debug, Clone)]
 pub struct Token<'a> {
     pub ttype: TType,
     pub lexeme: Option<&'a str>,
     pub line: usize,
+    // 1-based, counted in chars from the start of the line
+    pub column: usize,
+    // byte span into the source
+    pub start: usize,
+    pub len: usize,
 }
 
 impl<'a> Token<'a> {
-    pub fn new(ttype: TType, lexeme: Option<&'a str>, line: usize) -> Self {
+    pub fn new(
+        ttype: TType,
+        lexeme: Option<&'a str>,
+        line: usize,
+        column: usize,
+        start: usize,
+        len: usize,
+    ) -> Self {
         Self {
             ttype,
             lexeme,
             line,
+            column,
+            start,
+            len,
         }
     }
+
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.start + self.len
+    }
 }
 
 impl<'a> From<TType> for Token<'a> {
@@ -23,24 +43,23 @@ impl<'a> From<TType> for Token<'a> {
             ttype: value,
             lexeme: None,
             line: 1,
+            column: 1,
+            start: 0,
+            len: 0,
         }
     }
 }
 
 impl<'a> fmt::Display for Token<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[line {}] Error", self.line)?;
+        write!(f, "[line {}:{}] Error", self.line, self.column)?;
         match self.ttype {
             TType::Eof => write!(f, " at end"),
-            TType::UnexpectedCharacterError | TType::UnterminatedStringError => {
-                write!(
-                    f,
-                    " {} at '{}'",
-                    self.ttype.error_message().unwrap(),
-                    self.lexeme.unwrap_or_default()
-                )
+            _ => {
+                // multi-line lexemes are cut at the end of their first line
+                let lexeme = self.lexeme.unwrap_or_default();
+                write!(f, " at '{}'", lexeme.lines().next().unwrap_or(lexeme))
             }
-            _ => write!(f, " at '{}'", self.lexeme.unwrap_or_default()),
         }
     }
 }
@@ -74,33 +93,91 @@ pub enum TType {
     Number,
     // keywords
     And,
+    Catch,
     Class,
+    Do,
     Else,
     False,
     True,
     For,
     Fun,
     If,
+    Import,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
+    Try,
     Var,
     While,
     // extra
+    // only from a scanner keeping comments
+    Comment,
     Eof,
     UnexpectedCharacterError,
     UnterminatedStringError,
+    InvalidUtf8Error,
+    MalformedHexError,
+    MalformedBinaryError,
+    MalformedExponentError,
+    MalformedSeparatorError,
 }
 
 impl TType {
-    pub fn error_message(&self) -> Option<&str> {
+    pub fn error_message(&self) -> Option<&'static str> {
         match self {
             Self::UnexpectedCharacterError => Some("Unexpected character."),
             Self::UnterminatedStringError => Some("Unterminated string."),
+            Self::InvalidUtf8Error => Some("Invalid UTF-8 in source."),
+            Self::MalformedHexError => Some("Expect hex digits after '0x'."),
+            Self::MalformedBinaryError => Some("Expect binary digits after '0b'."),
+            Self::MalformedExponentError => Some("Expect digits in exponent."),
+            Self::MalformedSeparatorError => Some("Digit separator '_' must sit between digits."),
             _ => None,
         }
     }
 }
+
+// registers reserved words, generating both the lookup used by the scanner
+// and a table for tooling (completion, highlighting)
+macro_rules! keywords {
+    ($($word: literal => $ttype: ident),* $(,)?) => {
+        pub const KEYWORDS: &[(&str, TType)] = &[$(($word, TType::$ttype)),*];
+
+        impl TType {
+            pub fn keyword(word: &str) -> Option<TType> {
+                match word {
+                    $($word => Some(TType::$ttype),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+keywords! {
+    "and" => And,
+    "catch" => Catch,
+    "class" => Class,
+    "do" => Do,
+    "else" => Else,
+    "false" => False,
+    "for" => For,
+    "fun" => Fun,
+    "if" => If,
+    "import" => Import,
+    "nil" => Nil,
+    "or" => Or,
+    "print" => Print,
+    "return" => Return,
+    "super" => Super,
+    "this" => This,
+    "throw" => Throw,
+    "true" => True,
+    "try" => Try,
+    "var" => Var,
+    "while" => While,
+}
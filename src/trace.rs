@@ -0,0 +1,133 @@
+use core::fmt;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{chunks::Chunk, disassembler::DisassembledInstruction, heap::Heap, value::Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    // one object per instruction, for tools
+    Json,
+}
+
+// execution trace written before every instruction the vm dispatches
+pub struct Tracer {
+    out: Box<dyn Write + Send>,
+    format: TraceFormat,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tracer({:?})", self.format)
+    }
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write + Send>, format: TraceFormat) -> Self {
+        Self { out, format }
+    }
+
+    // `stderr`, `json` or a file path, as given to --trace=
+    pub fn from_spec(spec: &str) -> io::Result<Self> {
+        Ok(match spec {
+            "" | "stderr" => Self::new(Box::new(io::stderr()), TraceFormat::Text),
+            "json" => Self::new(Box::new(io::stderr()), TraceFormat::Json),
+            path => Self::new(
+                Box::new(BufWriter::new(File::create(path)?)),
+                TraceFormat::Text,
+            ),
+        })
+    }
+
+    pub fn start(&mut self) {
+        if self.format == TraceFormat::Text {
+            let _ = writeln!(self.out, "Execution Trace");
+        }
+    }
+
+    pub fn instruction(
+        &mut self,
+        chunk: &Chunk,
+        offset: usize,
+        stack: &[Value],
+        heap: &Heap,
+        (objects, strings): (usize, usize),
+    ) {
+        let instruction = DisassembledInstruction::decode(chunk, heap, offset);
+        let name = match instruction.opcode {
+            Some(op) => op.to_string(),
+            None => format!("<{:#04x}>", instruction.byte),
+        };
+        let line = instruction.line.unwrap_or_default();
+
+        let _ = match self.format {
+            TraceFormat::Text => {
+                let stack = stack
+                    .iter()
+                    .map(|value| format!(" {} ", value.display(heap)))
+                    .collect::<String>();
+                let _ = writeln!(self.out, "[{}]", stack);
+                let _ = writeln!(
+                    self.out,
+                    "heap: {}, strings: {}, bytes: {}",
+                    objects,
+                    strings,
+                    heap.bytes()
+                );
+                writeln!(self.out, "{:04} {:04} {}", offset, line, instruction)
+            }
+            TraceFormat::Json => {
+                let stack = stack
+                    .iter()
+                    .map(|value| json_string(&value.display(heap).to_string()))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let operands = instruction
+                    .operands
+                    .iter()
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let constant = instruction
+                    .constant
+                    .map(|c| json_string(&c.to_string()))
+                    .unwrap_or("null".into());
+                writeln!(
+                    self.out,
+                    "{{\"offset\":{},\"line\":{},\"opcode\":{},\"operands\":[{}],\"constant\":{},\"stack\":[{}]}}",
+                    offset,
+                    line,
+                    json_string(&name),
+                    operands,
+                    constant,
+                    stack
+                )
+            }
+        };
+    }
+
+    pub fn flush(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for char in text.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -1,21 +1,25 @@
 use core::fmt;
-use std::{
-    hash::{Hash, Hasher},
-    rc::{Rc, Weak},
-};
 
 use crate::{
-    error::{RuntimeErrors, VmErrors},
+    error::LoxError,
+    foreign::ForeignObj,
+    heap::{Heap, ObjRef},
+    module::Module,
+    native::NativeFn,
     vm::VM,
 };
 
-pub type ObjRoot<T> = Rc<HeapElement<T>>;
-pub type ObjRef<T> = Weak<HeapElement<T>>;
+// a string handle from `VM::strings`, equal names share one handle so
+// tables keyed by it compare and hash without touching the heap
+pub type InternString = ObjRef<String>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Value {
     Number(f64),
     String(ObjRef<String>),
+    Native(ObjRef<NativeFn>),
+    Foreign(ObjRef<ForeignObj>),
+    Module(ObjRef<Module>),
     Bool(bool),
     Nil,
 }
@@ -29,34 +33,66 @@ impl Value {
         }
     }
 
-    pub fn negate(&self) -> Result<Self, VmErrors> {
-        let value: f64 = self.to_owned().try_into()?;
-        Ok(Value::from(-value))
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Native(_) => "function",
+            Self::Foreign(_) => "object",
+            Self::Module(_) => "module",
+            Self::Bool(_) => "bool",
+            Self::Nil => "nil",
+        }
     }
-}
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // the heap slot this value points at, if any
+    pub fn heap_index(&self) -> Option<usize> {
         match self {
-            Self::Number(num) => write!(f, "{num}"),
-            Self::String(str) => {
-                let word = &str.upgrade().unwrap().content;
-                write!(f, "{}", word)
-            }
-            Self::Bool(bool) => write!(f, "{bool}"),
-            Self::Nil => write!(f, "nil"),
+            Self::String(str) => Some(str.index()),
+            Self::Native(native) => Some(native.index()),
+            Self::Foreign(foreign) => Some(foreign.index()),
+            Self::Module(module) => Some(module.index()),
+            _ => None,
         }
     }
-}
 
-impl PartialEq for Value {
-    fn eq(&self, other: &Self) -> bool {
+    // how `print` shows the value, reading objects out of `heap`
+    pub fn display<'h>(&self, heap: &'h Heap) -> Display<'h> {
+        Display { value: *self, heap }
+    }
+
+    // how the repl echo and the disassembler show a value, see LoxValue::repr
+    pub fn repr(&self, heap: &Heap) -> String {
+        LoxValue::from_value(self, heap).repr()
+    }
+
+    // `==` in lox. Interned strings share a handle, anything built another
+    // way (allocated directly, loaded later) is compared by content
+    pub fn equals(&self, other: &Self, heap: &Heap) -> bool {
         match (self, other) {
-            (Self::Number(a), Self::Number(b)) => a == b,
-            (Self::String(a), Self::String(b)) => Weak::ptr_eq(a, b),
-            (Self::Bool(a), Self::Bool(b)) => a == b,
-            (Self::Nil, Self::Nil) => true,
-            _ => false,
+            (Self::String(a), Self::String(b)) => a == b || heap.get(*a) == heap.get(*b),
+            (Self::Foreign(a), Self::Foreign(b)) => heap.get(*a) == heap.get(*b),
+            _ => self == other,
+        }
+    }
+}
+
+pub struct Display<'h> {
+    value: Value,
+    heap: &'h Heap,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let heap = self.heap;
+        match self.value {
+            Value::Number(num) => write!(f, "{num}"),
+            Value::String(str) => write!(f, "{}", heap.get(str)),
+            Value::Native(native) => write!(f, "{}", heap.get(native)),
+            Value::Foreign(foreign) => write!(f, "{}", heap.get(foreign)),
+            Value::Module(module) => write!(f, "{}", heap.get(module)),
+            Value::Bool(bool) => write!(f, "{bool}"),
+            Value::Nil => write!(f, "nil"),
         }
     }
 }
@@ -79,131 +115,219 @@ impl From<ObjRef<String>> for Value {
     }
 }
 
-impl TryFrom<Value> for f64 {
-    type Error = VmErrors;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::Number(n) => Ok(n),
-            _ => Err(VmErrors::RuntimeError(RuntimeErrors::TypeError(
-                "number",
-                value.to_string(),
-            ))),
-        }
+impl From<ObjRef<NativeFn>> for Value {
+    fn from(value: ObjRef<NativeFn>) -> Self {
+        Self::Native(value)
     }
 }
 
-impl TryFrom<Value> for String {
-    type Error = VmErrors;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::String(s) => Ok(s.upgrade().unwrap().content.clone()),
-            _ => Err(VmErrors::RuntimeError(RuntimeErrors::TypeError(
-                "string",
-                value.to_string(),
-            ))),
-        }
+impl From<ObjRef<ForeignObj>> for Value {
+    fn from(value: ObjRef<ForeignObj>) -> Self {
+        Self::Foreign(value)
     }
 }
 
-impl TryFrom<Value> for bool {
-    type Error = VmErrors;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
-        match value {
-            Value::Bool(b) => Ok(b),
-            _ => Err(VmErrors::RuntimeError(RuntimeErrors::TypeError(
-                "bool",
-                value.to_string(),
-            ))),
-        }
+impl From<ObjRef<Module>> for Value {
+    fn from(value: ObjRef<Module>) -> Self {
+        Self::Module(value)
     }
 }
 
-#[derive(Debug)]
-pub struct HeapElement<T> {
-    pub content: T,
+// an owned copy of a Value that outlives the vm it came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoxValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    // natives are returned by name
+    Native(String),
+    // shares the rust value with the vm
+    Foreign(ForeignObj),
+    // modules are returned by name
+    Module(String),
+}
+
+impl LoxValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Bool(_) => "bool",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Native(_) => "function",
+            Self::Foreign(_) => "object",
+            Self::Module(_) => "module",
+        }
+    }
+
+    // natives and modules cannot be rebuilt from a name, so they come back
+    // as nil
+    pub fn into_value(self, vm: &mut VM) -> Value {
+        match self {
+            Self::Nil | Self::Native(_) | Self::Module(_) => Value::Nil,
+            Self::Foreign(foreign) => vm.alloc(foreign).into(),
+            Self::Bool(b) => Value::Bool(b),
+            Self::Number(n) => Value::Number(n),
+            Self::String(s) => create_string(vm, &s).into(),
+        }
+    }
 }
 
-impl<T> HeapElement<T> {
-    pub fn new(content: T) -> Self {
-        Self { content }
+impl LoxValue {
+    // strings quoted and escaped so `"1"` and `1` can be told apart,
+    // everything else as `print` would show it
+    pub fn repr(&self) -> String {
+        match self {
+            Self::String(str) => {
+                let mut out = String::from('"');
+                for char in str.chars() {
+                    match char {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        _ => out.push(char),
+                    }
+                }
+                out.push('"');
+                out
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn from_value(value: &Value, heap: &Heap) -> Self {
+        match *value {
+            Value::Nil => Self::Nil,
+            Value::Bool(b) => Self::Bool(b),
+            Value::Number(n) => Self::Number(n),
+            Value::String(s) => Self::String(heap.get(s).clone()),
+            Value::Native(n) => Self::Native(heap.get(n).name.into()),
+            Value::Foreign(f) => Self::Foreign(heap.get(f).clone()),
+            Value::Module(m) => Self::Module(heap.get(m).name.clone()),
+        }
     }
 }
 
-impl<T> fmt::Display for HeapElement<T>
-where
-    T: fmt::Display,
-{
+impl fmt::Display for LoxValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.content)
+        match self {
+            Self::Nil => write!(f, "nil"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write!(f, "{s}"),
+            Self::Native(name) => write!(f, "<native fn {name}>"),
+            Self::Foreign(foreign) => write!(f, "{foreign}"),
+            Self::Module(name) => write!(f, "<module {name}>"),
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct InternString(pub ObjRoot<String>);
+impl From<()> for LoxValue {
+    fn from(_: ()) -> Self {
+        Self::Nil
+    }
+}
 
-// required by hashset
-impl Hash for InternString {
-    fn hash<H: Hasher>(&self, h: &mut H) {
-        self.0.content.hash(h)
+impl From<bool> for LoxValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
     }
 }
 
-impl PartialEq for InternString {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.content == other.0.content
+impl From<f64> for LoxValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
     }
 }
 
-// required by hashset
-impl Eq for InternString {}
+impl From<i32> for LoxValue {
+    fn from(value: i32) -> Self {
+        Self::Number(value.into())
+    }
+}
 
-impl fmt::Display for InternString {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0.content)
+impl From<String> for LoxValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
     }
 }
 
-impl std::borrow::Borrow<str> for InternString {
-    fn borrow(&self) -> &str {
-        self.0.content.borrow()
+impl From<&str> for LoxValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.into())
     }
 }
 
-impl TryFrom<Value> for InternString {
-    type Error = VmErrors;
-    fn try_from(value: Value) -> Result<Self, Self::Error> {
+impl TryFrom<LoxValue> for bool {
+    type Error = LoxError;
+    fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
         match value {
-            Value::String(str) => Ok(Self(str.upgrade().unwrap())),
-            _ => Err(VmErrors::RuntimeError(RuntimeErrors::TypeError(
-                "string",
-                value.to_string(),
-            ))),
+            LoxValue::Bool(b) => Ok(b),
+            other => Err(LoxError::Conversion {
+                expected: "bool",
+                found: other.type_name(),
+            }),
         }
     }
 }
 
-impl TryFrom<String> for InternString {
-    type Error = VmErrors;
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        Ok(Self(Rc::new(HeapElement::<String>::new(value))))
+impl TryFrom<LoxValue> for f64 {
+    type Error = LoxError;
+    fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+        match value {
+            LoxValue::Number(n) => Ok(n),
+            other => Err(LoxError::Conversion {
+                expected: "number",
+                found: other.type_name(),
+            }),
+        }
     }
 }
 
-pub trait Objs: fmt::Display + fmt::Debug {}
-
-impl Objs for ObjRoot<String> {}
+impl TryFrom<LoxValue> for String {
+    type Error = LoxError;
+    fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+        match value {
+            LoxValue::String(s) => Ok(s),
+            other => Err(LoxError::Conversion {
+                expected: "string",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
 
 pub fn create_string(vm: &mut VM, str: &str) -> ObjRef<String> {
     match vm.strings.get(str) {
-        Some(InternString(root)) => Rc::downgrade(root),
+        Some(&handle) => handle,
+        None => {
+            vm.before_alloc();
+            let mut buffer = vm.heap.take_buffer(str.len());
+            buffer.push_str(str);
+            let handle = vm.heap.alloc(buffer);
+            vm.strings.insert(str.to_owned(), handle);
+            handle
+        }
+    }
+}
+
+// interns a string the caller built, usually in a buffer from
+// Heap::take_buffer. An already interned one sends the buffer back
+pub fn intern_string(vm: &mut VM, str: String) -> ObjRef<String> {
+    match vm.strings.get(str.as_str()) {
+        Some(&handle) => {
+            vm.heap.give_buffer(str);
+            handle
+        }
         None => {
-            let element = HeapElement::<String>::new(str.to_owned());
-            let root = Rc::new(element);
-            let oref = Rc::downgrade(&root);
-            let intern = InternString(Rc::clone(&root));
-            vm.strings.insert(intern);
-            vm.objs.push(Box::new(root));
-            oref
+            vm.before_alloc();
+            let key = str.clone();
+            let handle = vm.heap.alloc(str);
+            vm.strings.insert(key, handle);
+            handle
         }
     }
 }
@@ -1,236 +1,1336 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    mem,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use crate::{
+    bind::{type_error, FromLox, HostFn},
+    bytecode,
     chunks::Chunk,
-    compiler::Compiler,
-    cprint, cprintln,
+    clock::Clock,
+    compiler::{declaration_ranges, Compiler, Trailing},
+    config::VmConfig,
+    debugger::{DebugHook, VmInspector},
     disassembler::TracingIp,
-    error::{RuntimeErrors, VmErrors},
-    memory::get_allocated_bytes,
+    error::{Diagnostic, LoxError, ModuleErrors, RuntimeErrors, VmErrors},
+    foreign::{ForeignBuilder, ForeignClass, ForeignData, ForeignMethod, ForeignObj},
+    hash::BuildHash,
+    heap::{Heap, HeapObj, ObjRef},
+    memory::{self, GcStats},
+    module::{FileResolver, LoadedModule, Module, ModuleResolver, Modules, Resolver},
+    native::{define_natives, Arity, NativeFn, NativeFnPtr},
     opcode::OpCode,
-    value::{create_string, InternString, Objs, Value},
+    output::Sink,
+    panic_hook,
+    pretty::pretty,
+    profiler::{OpCounts, Profile},
+    report::ErrorFormat,
+    scanner::{decode_lossy, Scanner},
+    table::Table,
+    trace::{TraceFormat, Tracer},
+    value::{create_string, intern_string, InternString, LoxValue, Value},
 };
 
 type InterpretRes = Result<(), VmErrors>;
 type VMRes<T> = Result<T, VmErrors>;
 
+// room left for a number in a concatenation, enough for most without
+// growing the buffer
+const NUMBER_LEN: usize = 24;
+
+// one vm per worker thread: everything it owns has to be Send
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<VM>();
+};
+
+// what `VM::call_function` calls
+#[derive(Debug, Clone)]
+pub enum Callee<'a> {
+    Name(&'a str),
+    Value(Value),
+}
+
+impl<'a> From<&'a str> for Callee<'a> {
+    fn from(name: &'a str) -> Self {
+        Self::Name(name)
+    }
+}
+
+impl From<Value> for Callee<'_> {
+    fn from(value: Value) -> Self {
+        Self::Value(value)
+    }
+}
+
+// an active `try`, where to resume and how much of the stack to keep
+#[derive(Debug, Clone, Copy)]
+pub struct Handler {
+    pub catch: usize,
+    pub stack: usize,
+}
+
+// stops whatever the vm it came from is running, from any thread. The run
+// ends with RuntimeErrors::Interrupted at its next instruction
+//
+//     let handle = vm.interrupt_handle();
+//     thread::spawn(move || {
+//         thread::sleep(timeout);
+//         handle.interrupt();
+//     });
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    // clears a request no run has picked up yet
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    // a plain load on each instruction, the flag is only written back once
+    // it has been seen set
+    fn take(&self) -> bool {
+        self.0.load(Ordering::Relaxed) && self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+// a run that is being stepped through, see VM::step
+#[derive(Debug, Clone)]
+pub struct Execution {
+    // its own copy, like `run` takes, so breakpoints set meanwhile still apply
+    // only to later runs
+    pub chunk: Chunk,
+    pub offset: usize,
+    pub line: Option<usize>,
+    // stack depth and handler count when it started
+    pub depth: usize,
+    pub base: usize,
+    pub heap_baseline: usize,
+    // offset of a breakpoint already reported as Paused
+    pub paused: Option<usize>,
+    // token of the native call waiting for `VM::resume`
+    pub pending: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum StepResult {
+    // one instruction ran, there are more
+    Continue,
+    // the next instruction is a breakpoint, stepping again executes it
+    Paused,
+    // the chunk returned, with what it left on top of the stack or nil
+    Done(Value),
+    Error(VmErrors),
+    // a native is waiting on the host, nothing runs until `VM::resume` is
+    // given this token
+    Pending(u64),
+}
+
+// where a stepped run currently is
+#[derive(Debug, Clone)]
+pub struct Frame {
+    // of the next instruction
+    pub offset: usize,
+    pub line: Option<usize>,
+    pub span: Option<Range<usize>>,
+}
+
 #[derive(Debug)]
 pub struct VM {
     pub stack: Vec<Value>,
-    pub objs: Vec<Box<dyn Objs>>,
-    // interned string db
-    pub strings: HashSet<InternString>,
-    pub globals: HashMap<InternString, Value>,
+    pub heap: Heap,
+    // interned string db, content to the one handle every copy shares
+    pub strings: HashMap<String, InternString, BuildHash>,
+    pub globals: Table<InternString, Value>,
     pub chunks: Chunk,
+    pub start: Clock,
+    // line of the instruction that raised the last runtime error
+    pub error_line: Option<usize>,
+    // and its source span, when the chunk was compiled with debug info
+    pub error_span: Option<Range<usize>>,
+    // patched offsets and the opcode each one replaced
+    pub breakpoints: HashMap<usize, u8>,
+    pub debug_hook: Option<DebugHook>,
+    // instruction counts, collected only while set
+    pub profile: Option<Profile>,
+    // executions of each opcode, counted only while set
+    pub op_counts: Option<Box<OpCounts>>,
+    pub tracer: Option<Tracer>,
+    pub foreign_classes: HashMap<TypeId, ForeignClass>,
+    // `print` output and error messages, stdout and stderr unless replaced
+    pub out: Sink,
+    pub diagnostics: Sink,
+    pub config: VmConfig,
+    pub modules: Modules,
+    pub resolver: Option<Resolver>,
+    // the last script compiled from a string, shown around runtime errors
+    pub source: Option<String>,
+    // what followed the script on the command line, read with args()
+    pub script_args: Vec<String>,
+    pub error_format: ErrorFormat,
+    pub handlers: Vec<Handler>,
+    // set between calls to `step`
+    pub execution: Option<Execution>,
+    // the token the next pending native call gets
+    pub next_token: u64,
+    pub interrupt: InterruptHandle,
+    // instructions being executed, nested while an import runs. Only then
+    // may an allocation collect, see `before_alloc`
+    pub running: usize,
+    // allocated bytes that trigger the next automatic collection
+    pub next_gc: usize,
+    pub gc_stats: GcStats,
+    // the deepest the stack has been between two instructions
+    pub peak_stack: usize,
 }
 
 impl VM {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        Self {
+        Self::with_config(VmConfig::default())
+    }
+
+    // natives are registered according to `config.capabilities`
+    pub fn with_config(config: VmConfig) -> Self {
+        let next_gc = config.gc_initial_threshold;
+        let mut vm = Self {
             stack: Vec::<Value>::new(),
-            objs: Vec::new(),
-            strings: HashSet::<InternString>::new(),
-            globals: HashMap::new(),
+            heap: Heap::new(),
+            strings: HashMap::default(),
+            globals: Table::new(),
             chunks: Chunk::default(),
+            start: Clock::now(),
+            error_line: None,
+            error_span: None,
+            breakpoints: HashMap::new(),
+            debug_hook: None,
+            profile: None,
+            op_counts: None,
+            // the `trace` feature keeps the old always-on stdout trace
+            tracer: cfg!(feature = "trace")
+                .then(|| Tracer::new(Box::new(io::stdout()), TraceFormat::Text)),
+            foreign_classes: HashMap::new(),
+            out: Sink::stdout(),
+            diagnostics: Sink::stderr(),
+            config,
+            modules: Modules::new(),
+            resolver: None,
+            source: None,
+            script_args: Vec::new(),
+            error_format: ErrorFormat::Human,
+            handlers: Vec::new(),
+            execution: None,
+            next_token: 0,
+            interrupt: InterruptHandle::default(),
+            running: 0,
+            next_gc,
+            gc_stats: GcStats::default(),
+            peak_stack: 0,
+        };
+        define_natives(&mut vm);
+        vm
+    }
+
+    pub fn define_native(&mut self, name: &'static str, arity: usize, function: NativeFnPtr) {
+        self.register_native(NativeFn::new(name, Arity::Exact(arity), function));
+    }
+
+    // natives accepting `min_arity` or more arguments
+    pub fn define_variadic(&mut self, name: &'static str, min_arity: usize, function: NativeFnPtr) {
+        self.register_native(NativeFn::new(name, Arity::AtLeast(min_arity), function));
+    }
+
+    // a rust closure as a global native, arguments and the return value are
+    // converted through FromLox / IntoLox
+    pub fn bind<Args>(&mut self, name: &'static str, function: impl HostFn<Args>) {
+        let arity = Arity::Exact(function.arity());
+        self.register_native(NativeFn::new(name, arity, move |vm, args| {
+            function.call(vm, args)
+        }));
+    }
+
+    fn register_native(&mut self, native: NativeFn) {
+        let name = native.name;
+        let native = self.alloc(native);
+        self.define_global(name, native.into());
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        // interning the name may collect, `value` has no other root yet
+        self.stack.push(value);
+        let key = create_string(self, name);
+        self.stack.pop();
+        self.globals.insert(key, value);
+    }
+
+    // moves `content` onto the heap, the returned handle stays valid until
+    // a collection finds it unreachable
+    pub fn alloc<T: HeapObj>(&mut self, content: T) -> ObjRef<T> {
+        self.before_alloc();
+        self.heap.alloc(content)
+    }
+
+    // collects once the heap has grown past `next_gc`, or before every
+    // allocation with `config.gc_stress` so whatever holds a value the
+    // collector can't see fails on the spot. Only allocations a running
+    // script makes collect, the host's own between runs never do
+    pub fn before_alloc(&mut self) {
+        if self.running > 0 && (self.config.gc_stress || self.heap.bytes() > self.next_gc) {
+            memory::collect_garbage(self);
+        }
+    }
+
+    // collects now, whether or not a script is running, so everything the
+    // host holds outside the stack and globals must be unreachable. Returns
+    // the updated stats
+    pub fn collect_garbage(&mut self) -> GcStats {
+        memory::collect_garbage(self);
+        self.gc_stats
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.gc_stats
+    }
+
+    // methods and properties lox scripts may use on values of type `T`
+    pub fn register_foreign<T: 'static>(&mut self, name: &'static str) -> ForeignBuilder<'_, T> {
+        let class = self
+            .foreign_classes
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| ForeignClass {
+                name,
+                ..ForeignClass::default()
+            });
+        ForeignBuilder::new(class)
+    }
+
+    // moves `value` onto the lox heap so it can be handed to scripts
+    pub fn foreign<T: Send + 'static>(&mut self, value: T) -> Value {
+        let type_id = TypeId::of::<T>();
+        let type_name = match self.foreign_classes.get(&type_id) {
+            Some(class) => class.name,
+            None => std::any::type_name::<T>(),
+        };
+        let data: ForeignData = Arc::new(Mutex::new(value));
+        self.alloc(ForeignObj {
+            type_name,
+            type_id,
+            data,
+        })
+        .into()
+    }
+
+    // patches the first instruction on `line` of the compiled chunk, returning
+    // its offset. the hook set with `on_breakpoint` runs each time it is hit
+    pub fn set_breakpoint(&mut self, line: usize) -> Option<usize> {
+        let offset = self.chunks.instruction_at_line(line)?;
+        let breakpoint: u8 = OpCode::Breakpoint.into();
+        if self.chunks.code[offset] != breakpoint {
+            self.breakpoints.insert(offset, self.chunks.code[offset]);
+            self.chunks.code[offset] = breakpoint;
         }
+        Some(offset)
+    }
+
+    pub fn clear_breakpoint(&mut self, line: usize) {
+        if let Some(offset) = self.chunks.instruction_at_line(line) {
+            if let Some(original) = self.breakpoints.remove(&offset) {
+                self.chunks.code[offset] = original;
+            }
+        }
+    }
+
+    pub fn on_breakpoint(&mut self, hook: impl FnMut(&VmInspector) + Send + 'static) {
+        self.debug_hook = Some(DebugHook(Box::new(hook)));
     }
 
     pub fn interpret(&mut self, src: &str) -> InterpretRes {
-        let mut cc = Compiler::new(src, self);
-        cc.compile().map_err(VmErrors::CompileError)?;
-        let result = self.run();
-        if let Err(VmErrors::RuntimeError(e)) = result {
-            eprintln!("Runtime Error: {}", e);
+        self.compile(src)?;
+        self.run()
+    }
+
+    // writes `value` to `out` as the repl echoes it, colored when `out`
+    // takes colors, see pretty
+    pub fn reprln(&mut self, value: Value) {
+        let text = pretty(&value, &self.heap, self.out.colors());
+        let _ = writeln!(self.out, "{text}");
+    }
+
+    // like interpret, but a trailing expression without ';' is echoed in
+    // its repr form, so strings come out quoted. Each top level declaration
+    // is compiled and run before the next, so one with an error leaves
+    // those before it in effect and those after it unrun
+    pub fn interpret_echo(&mut self, src: &str) -> InterpretRes {
+        self.echo_declarations(src, false)
+    }
+
+    // like interpret_echo, for input typed at the repl: a ';' missing from
+    // the very end is supplied when it is all that kept the input from
+    // compiling
+    pub fn interpret_line(&mut self, src: &str) -> InterpretRes {
+        self.echo_declarations(src, true)
+    }
+
+    fn echo_declarations(&mut self, src: &str, lenient: bool) -> InterpretRes {
+        for range in declaration_ranges(src) {
+            self.echo_declaration(src, range, lenient)?;
         }
         Ok(())
     }
 
+    fn echo_declaration(&mut self, src: &str, range: Range<usize>, lenient: bool) -> InterpretRes {
+        let depth = self.stack.len();
+        let source = &src[..range.end];
+        let compiled = match self.compile_echo(source, range.start) {
+            Err(errors)
+                if lenient
+                    && matches!(errors.as_slice(), [e] if e.is_missing_semicolon_at_end()) =>
+            {
+                self.compile_echo(&format!("{};", source), range.start)
+                    .map_err(|_| errors)
+            }
+            compiled => compiled,
+        };
+        compiled.map_err(VmErrors::CompileError)?;
+        // runtime errors quote their line from the whole input
+        self.source = Some(src.into());
+        let result = self.run();
+        if result.is_ok() && self.stack.len() > depth {
+            self.reprln(self.peek(0));
+        }
+        self.stack.truncate(depth);
+        result
+    }
+
+    // the declaration starting at `start`, the last one in `source`
+    fn compile_echo(&mut self, source: &str, start: usize) -> Result<(), Vec<Diagnostic>> {
+        let mut cc = Compiler::new(source, self);
+        cc.parser.scanner = Scanner::new(source).skipping_to(start);
+        cc.trailing = Trailing::Echo;
+        cc.compile()
+    }
+
+    // runs `src` and returns the value of its final expression statement,
+    // nil when it does not end in one
+    pub fn eval(&mut self, src: &str) -> Result<LoxValue, LoxError> {
+        let depth = self.stack.len();
+        let mut cc = Compiler::new(src, self);
+        cc.trailing = Trailing::Keep;
+        cc.compile().map_err(LoxError::Compile)?;
+        if let Err(err) = self.run() {
+            self.stack.truncate(depth);
+            return Err(LoxError::from_vm(err, self.error_line));
+        }
+        let value = match self.stack.len() > depth {
+            true => LoxValue::from_value(&self.peek(0), &self.heap),
+            false => LoxValue::Nil,
+        };
+        self.stack.truncate(depth);
+        Ok(value)
+    }
+
+    // calls a global or a value a script handed back, e.g. a callback
+    // registered during an earlier `interpret`
+    pub fn call_function<'a>(
+        &mut self,
+        callee: impl Into<Callee<'a>>,
+        args: &[LoxValue],
+    ) -> Result<LoxValue, LoxError> {
+        let callee = match callee.into() {
+            Callee::Value(value) => value,
+            Callee::Name(name) => {
+                match self.strings.get(name).and_then(|key| self.globals.get(key)) {
+                    Some(value) => *value,
+                    None => {
+                        return Err(LoxError::Runtime {
+                            error: RuntimeErrors::UndefinedVariable(name.into()),
+                            line: None,
+                        })
+                    }
+                }
+            }
+        };
+        let depth = self.stack.len();
+        self.stack.push(callee);
+        for arg in args {
+            let value = arg.clone().into_value(self);
+            self.stack.push(value);
+        }
+        let result = self
+            .call_value(callee, args.len())
+            .map(|_| LoxValue::from_value(&self.peek(0), &self.heap));
+        self.stack.truncate(depth);
+        result.map_err(|err| LoxError::from_vm(not_resumable(err), None))
+    }
+
+    // compiles into `self.chunks` without running it
+    pub fn compile(&mut self, src: &str) -> InterpretRes {
+        let mut cc = Compiler::new(src, self);
+        cc.compile().map_err(VmErrors::CompileError)
+    }
+
     pub fn run(&mut self) -> InterpretRes {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.start();
+        }
+        let chunk = self.chunks.clone();
+        let mut ip = TracingIp::new(&chunk, 0);
+        self.error_line = None;
+        self.error_span = None;
+        let depth = self.stack.len();
+        let started = Clock::now();
+        let running = self.running;
+        // a bug in the vm ends the run with an error rather than the process.
+        // Only running is guarded, a panic while compiling or under `step`
+        // still reaches the host
+        let result = panic_hook::guarded(|| self.execute(&mut ip)).unwrap_or_else(|message| {
+            // the instructions the panic unwound out of never finished
+            self.running = running;
+            let op = ip
+                .chunk
+                .code
+                .get(ip.start)
+                .map(|&byte| OpCode::try_from(byte));
+            let op = match op {
+                Some(Ok(op)) => op.to_string(),
+                _ => "an unknown opcode".into(),
+            };
+            let error = RuntimeErrors::Internal(message, ip.start, op);
+            Err(VmErrors::RuntimeError(error))
+        });
+        let result = result.map_err(not_resumable);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.flush();
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.elapsed += started.elapsed();
+        }
+        if result.is_err() {
+            self.error_line = ip.line;
+            self.error_span = ip.chunk.span_at(ip.start);
+            // whatever the failed statement left half evaluated would sit
+            // under the locals of the next run, globals and strings stay
+            self.stack.truncate(depth);
+        }
+        result
+    }
+
+    // executes one instruction of `self.chunks`, starting a run on the first
+    // call and ending it on Done or Error, so the host decides when the
+    // script makes progress
+    pub fn step(&mut self) -> StepResult {
+        let mut execution = match self.execution.take() {
+            Some(execution) => execution,
+            None => self.begin_execution(),
+        };
+        if let Some(token) = execution.pending {
+            self.execution = Some(execution);
+            return StepResult::Pending(token);
+        }
+        let mut ip = TracingIp::new(&execution.chunk, execution.offset);
+        ip.line = execution.line;
+        if !ip.valid() {
+            return self.end_execution(execution, Ok(()));
+        }
+        if ip.chunk.code[ip.offset] == u8::from(OpCode::Breakpoint)
+            && execution.paused != Some(ip.offset)
+        {
+            execution.paused = Some(ip.offset);
+            self.execution = Some(execution);
+            return StepResult::Paused;
+        }
+        let (heap_baseline, base) = (execution.heap_baseline, execution.base);
+        let mut result = match self.traced() {
+            true => self.instruction::<true>(&mut ip, heap_baseline, base),
+            false => self.instruction::<false>(&mut ip, heap_baseline, base),
+        };
+        if let Err(VmErrors::RuntimeError(error)) = &result {
+            if self.handlers.len() > execution.base && self.catchable(error) {
+                let value = create_string(self, &error.to_string()).into();
+                self.unwind(&mut ip, value);
+                result = Ok(false);
+            }
+        }
+        let (offset, line, start, valid) = (ip.offset, ip.line, ip.start, ip.valid());
+        execution.paused = None;
+        match result {
+            Ok(false) if valid => {
+                execution.offset = offset;
+                execution.line = line;
+                self.execution = Some(execution);
+                StepResult::Continue
+            }
+            Ok(_) => self.end_execution(execution, Ok(())),
+            // the call instruction has run, the callee stays in the slot its
+            // result goes to
+            Err(VmErrors::RuntimeError(RuntimeErrors::Pending(token))) => {
+                execution.offset = offset;
+                execution.line = line;
+                execution.pending = Some(token);
+                self.execution = Some(execution);
+                StepResult::Pending(token)
+            }
+            Err(err) => {
+                self.error_line = line;
+                self.error_span = execution.chunk.span_at(start);
+                self.end_execution(execution, Err(err))
+            }
+        }
+    }
+
+    // steps up to `instructions` times, for timeslicing a script inside a
+    // host loop. Continue means the slice ran out and the run is suspended
+    // in `self.execution` until the next call
+    //
+    //     loop {
+    //         match vm.run_for(10_000) {
+    //             StepResult::Continue => render_frame(),
+    //             StepResult::Done(_) => break,
+    //             ...
+    //         }
+    //     }
+    pub fn run_for(&mut self, instructions: u64) -> StepResult {
+        for _ in 0..instructions {
+            match self.step() {
+                StepResult::Continue => {}
+                other => return other,
+            }
+        }
+        StepResult::Continue
+    }
+
+    // for a native that has to wait on the host (a timer, a request): it
+    // hands the token to whoever will finish the work and returns the error,
+    // the script continues once `VM::resume` gets the token back
+    //
+    //     let (token, pending) = vm.pending();
+    //     timers.push((token, ms));
+    //     Err(pending)
+    pub fn pending(&mut self) -> (u64, VmErrors) {
+        let token = self.next_token;
+        self.next_token += 1;
+        (token, VmErrors::RuntimeError(RuntimeErrors::Pending(token)))
+    }
+
+    // completes the pending native call `token` with `value`, false when the
+    // suspended run isn't waiting on that token
+    pub fn resume(&mut self, token: u64, value: impl Into<LoxValue>) -> bool {
+        if !matches!(&self.execution, Some(execution) if execution.pending == Some(token)) {
+            return false;
+        }
+        let value = value.into().into_value(self);
+        self.stack.pop();
+        self.stack.push(value);
+        if let Some(execution) = &mut self.execution {
+            execution.pending = None;
+        }
+        true
+    }
+
+    // every handle shares one flag, so any clone stops this vm
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    // drops a suspended run, leaving the stack as it was before it started
+    pub fn abort_execution(&mut self) {
+        if let Some(execution) = self.execution.take() {
+            self.stack.truncate(execution.depth);
+            self.handlers.truncate(execution.base);
+        }
+    }
+
+    fn begin_execution(&mut self) -> Execution {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.start();
+        }
+        self.error_line = None;
+        self.error_span = None;
+        Execution {
+            chunk: self.chunks.clone(),
+            offset: 0,
+            line: None,
+            depth: self.stack.len(),
+            base: self.handlers.len(),
+            heap_baseline: self.heap.bytes(),
+            paused: None,
+            pending: None,
+        }
+    }
+
+    fn end_execution(&mut self, execution: Execution, result: InterpretRes) -> StepResult {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.flush();
+        }
+        self.handlers.truncate(execution.base);
+        match result {
+            Ok(()) => match self.stack.len() > execution.depth {
+                true => StepResult::Done(self.peek(0)),
+                false => StepResult::Done(Value::Nil),
+            },
+            Err(err) => {
+                self.stack.truncate(execution.depth);
+                StepResult::Error(err)
+            }
+        }
+    }
+
+    // the position of a stepped run, None when none is in progress
+    pub fn frame(&self) -> Option<Frame> {
+        self.execution.as_ref().map(|execution| Frame {
+            offset: execution.offset,
+            line: execution.chunk.line_at(execution.offset),
+            span: execution.chunk.span_at(execution.offset),
+        })
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    // handlers pushed by this run are the only ones it may unwind to, an
+    // error nobody here catches leaves for the caller
+    fn execute(&mut self, ip: &mut TracingIp) -> InterpretRes {
+        let heap_baseline = self.heap.bytes();
+        let base = self.handlers.len();
+        let result = loop {
+            let dispatched = match self.traced() {
+                true => self.dispatch::<true>(ip, heap_baseline, base),
+                false => self.dispatch::<false>(ip, heap_baseline, base),
+            };
+            match dispatched {
+                Err(VmErrors::RuntimeError(error))
+                    if self.handlers.len() > base && self.catchable(&error) =>
+                {
+                    let value = create_string(self, &error.to_string()).into();
+                    self.unwind(ip, value);
+                }
+                result => break result,
+            }
+        };
+        self.handlers.truncate(base);
+        result
+    }
+
+    // built in errors become strings a `catch` can bind, unless
+    // `config.strict_compat` keeps them fatal as in clox. Limits and
+    // `exit()` are never caught
+    fn catchable(&self, error: &RuntimeErrors) -> bool {
+        !self.config.strict_compat
+            && !matches!(
+                error,
+                RuntimeErrors::Exit(_)
+                    | RuntimeErrors::Pending(_)
+                    | RuntimeErrors::Interrupted
+                    | RuntimeErrors::OutOfFuel
+                    | RuntimeErrors::StackOverflow(_)
+                    | RuntimeErrors::HeapLimitExceeded(_)
+                    | RuntimeErrors::InvalidOpcode
+                    | RuntimeErrors::StackUnderFlow
+            )
+    }
+
+    // drops what the try block left on the stack and resumes at its catch
+    // block with `exception` in the slot the handler was pushed at
+    fn unwind(&mut self, ip: &mut TracingIp, exception: Value) {
+        if let Some(handler) = self.handlers.pop() {
+            self.stack.truncate(handler.stack);
+            self.stack.push(exception);
+            ip.offset = handler.catch;
+        }
+    }
+
+    // whether a tracer, profile or op counts watch each instruction. It is
+    // checked once per dispatch loop, which then runs a copy of the
+    // instruction code compiled with or without them, so an untraced run
+    // pays nothing for them
+    fn traced(&self) -> bool {
+        self.tracer.is_some() || self.profile.is_some() || self.op_counts.is_some()
+    }
+
+    fn dispatch<const TRACE: bool>(
+        &mut self,
+        ip: &mut TracingIp,
+        heap_baseline: usize,
+        base: usize,
+    ) -> InterpretRes {
+        while ip.valid() {
+            if self.instruction::<TRACE>(ip, heap_baseline, base)? {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    // executes the instruction at `ip`, true once the chunk has returned
+    fn instruction<const TRACE: bool>(
+        &mut self,
+        ip: &mut TracingIp,
+        heap_baseline: usize,
+        base: usize,
+    ) -> VMRes<bool> {
+        self.running += 1;
+        let result = self.execute_instruction::<TRACE>(ip, heap_baseline, base);
+        self.running -= 1;
+        result
+    }
+
+    // anything an instruction allocates may collect, so values it still
+    // needs stay on the stack until it is done with them
+    fn execute_instruction<const TRACE: bool>(
+        &mut self,
+        ip: &mut TracingIp,
+        heap_baseline: usize,
+        base: usize,
+    ) -> VMRes<bool> {
         macro_rules! binary_op {
             ($op: tt) => {{
-                let b: f64 = self.pop()?.try_into()?;
-                let a: f64 = self.pop()?.try_into()?;
+                let b = self.pop()?;
+                let b = self.number(&b)?;
+                let a = self.pop()?;
+                let a = self.number(&a)?;
                 self.stack.push((a $op b).into());
             }};
         }
 
+        // concatenation builds in a pooled buffer with room for `$len` bytes
         macro_rules! string {
-            ($a: expr, $b: expr) => {
-                create_string(self, format!("{}{}", $a, $b).as_str())
-            };
+            ($len: expr, $a: expr, $b: expr) => {{
+                let mut str = self.heap.take_buffer($len);
+                let _ = write!(str, "{}{}", $a, $b);
+                intern_string(self, str)
+            }};
         }
 
-        if cfg!(feature = "trace") {
-            cprintln!(Red, "Execution Trace");
+        if TRACE {
+            if let Some(tracer) = &mut self.tracer {
+                let counts = (self.heap.len(), self.strings.len());
+                tracer.instruction(ip.chunk, ip.offset, &self.stack, &self.heap, counts);
+            }
         }
-        let chunk = self.chunks.clone();
-        let mut ip = TracingIp::new(&chunk, 0);
-        while ip.valid() {
-            if cfg!(feature = "trace") {
-                // canot pretty print <Weak<String>>
-                // println!("{:?}\n", self.stack);
-                cprint!(Purple, "[");
-                self.stack
-                    .iter()
-                    .for_each(|e| cprint!(LightPurple, " {} ", e));
-                cprintln!(Purple, "]\n");
-
-                cprintln!(
-                    LightGreen,
-                    "heap: {}, strings: {}, bytes: {}",
-                    self.objs.len(),
-                    self.strings.len(),
-                    get_allocated_bytes()
-                );
-                ip.clone().disassemble_instruction();
-            }
-            let byte = ip.read();
-            match OpCode::try_from(byte) {
-                Ok(op) => match op {
-                    OpCode::Constant => {
-                        let val = ip.chunk.constants[ip.read() as usize].clone();
-                        self.stack.push(val);
-                    }
-                    OpCode::Addition => {
-                        let val2 = self.pop()?;
-                        let val1 = self.pop()?;
-                        match (&val1, &val2) {
-                            (Value::String(v1), Value::String(v2)) => {
-                                let v1 = &v1.upgrade().unwrap().content;
-                                let v2 = &v2.upgrade().unwrap().content;
-                                let str = string!(v1, v2);
-                                self.stack.push(str.into());
-                            }
-                            (Value::String(v1), Value::Number(v2)) => {
-                                let v1 = &v1.upgrade().unwrap().content;
-                                let str = string!(v1, v2);
-                                self.stack.push(str.into());
-                            }
-                            (Value::Number(v1), Value::String(v2)) => {
-                                let v2 = &v2.upgrade().unwrap().content;
-                                let str = string!(v1, v2);
-                                self.stack.push(str.into());
-                            }
-                            (Value::Number(v1), Value::Number(v2)) => {
-                                let concat = v1 + v2;
-                                self.stack.push(concat.into());
-                            }
-                            _ => {
-                                return Err(VmErrors::RuntimeError(RuntimeErrors::InvalidAddition(
-                                    val1.to_string(),
-                                    val2.to_string(),
-                                )))
-                            }
+        ip.start = ip.offset;
+        let mut byte = ip.read();
+        if byte == u8::from(OpCode::Breakpoint) {
+            byte = self.hit_breakpoint(ip);
+        }
+        self.peak_stack = self.peak_stack.max(self.stack.len());
+        self.check_limits(heap_baseline)?;
+        if TRACE {
+            if let Some(profile) = &mut self.profile {
+                profile.record(ip.line, byte);
+            }
+            if let Some(op_counts) = &mut self.op_counts {
+                op_counts.record(byte);
+            }
+        }
+        match OpCode::try_from(byte) {
+            Ok(op) => match op {
+                OpCode::Constant => {
+                    let val = ip.chunk.constants[ip.read() as usize];
+                    self.stack.push(val);
+                }
+                OpCode::Addition => {
+                    let val2 = self.pop()?;
+                    let val1 = self.pop()?;
+                    match (val1, val2) {
+                        (Value::String(v1), Value::String(v2)) => {
+                            let len = self.heap.get(v1).len() + self.heap.get(v2).len();
+                            let str = string!(len, self.heap.get(v1), self.heap.get(v2));
+                            self.stack.push(str.into());
                         }
-                    }
-                    OpCode::Subtract => binary_op!(-),
-                    OpCode::Multiply => binary_op!(*),
-                    OpCode::Divide => binary_op!(/),
-                    OpCode::Not => {
-                        let bool = self.pop()?.is_falsy();
-                        self.stack.push(bool.into())
-                    }
-                    OpCode::Negate => {
-                        let val = self.pop()?;
-                        self.stack.push(val.negate()?)
-                    }
-                    OpCode::Print => println!("{}", self.pop()?),
-                    OpCode::Jump => {
-                        let offset = ip.read_short() as usize;
-                        ip.offset += offset;
-                    }
-                    OpCode::JumpIfFalse => {
-                        let offset = ip.read_short() as usize;
-                        if self.peek(0).is_falsy() {
-                            ip.offset += offset;
+                        (Value::String(v1), Value::Number(v2)) => {
+                            let len = self.heap.get(v1).len() + NUMBER_LEN;
+                            let str = string!(len, self.heap.get(v1), v2);
+                            self.stack.push(str.into());
                         }
-                    }
-                    OpCode::Loop => {
-                        let offset = ip.read_short() as usize;
-                        ip.offset -= offset;
-                    }
-                    OpCode::True => self.stack.push(true.into()),
-                    OpCode::Pop => {
-                        self.pop()?;
-                    }
-                    OpCode::GetLocal => {
-                        let slot = ip.read();
-                        self.stack.push(self.stack[slot as usize].clone())
-                    }
-                    OpCode::SetLocal => {
-                        let slot = ip.read();
-                        self.stack[slot as usize] = self.peek(0);
-                    }
-                    OpCode::GetGlobal => {
-                        let val = ip.read_constant();
-                        let str: InternString = val.clone().try_into()?;
-                        match self.globals.get(&str) {
-                            Some(str) => self.stack.push(str.clone()),
-                            None => {
-                                return Err(VmErrors::RuntimeError(
-                                    RuntimeErrors::UndefinedVariable(val.to_string()),
-                                ))
-                            }
+                        (Value::Number(v1), Value::String(v2)) => {
+                            let len = NUMBER_LEN + self.heap.get(v2).len();
+                            let str = string!(len, v1, self.heap.get(v2));
+                            self.stack.push(str.into());
+                        }
+                        (Value::Number(v1), Value::Number(v2)) => {
+                            let concat = v1 + v2;
+                            self.stack.push(concat.into());
+                        }
+                        _ => {
+                            return Err(VmErrors::RuntimeError(RuntimeErrors::InvalidAddition(
+                                val1.display(&self.heap).to_string(),
+                                val2.display(&self.heap).to_string(),
+                            )))
                         }
                     }
-                    OpCode::DefineGlobal => {
-                        let val = ip.read_constant();
-                        let str: InternString = val.try_into()?;
-                        self.globals.insert(str, self.peek(0));
-                        self.pop()?;
+                }
+                OpCode::Subtract => binary_op!(-),
+                OpCode::Multiply => binary_op!(*),
+                OpCode::Divide => binary_op!(/),
+                OpCode::Not => {
+                    let bool = self.pop()?.is_falsy();
+                    self.stack.push(bool.into())
+                }
+                OpCode::Negate => {
+                    let val = self.pop()?;
+                    let val = self.number(&val)?;
+                    self.stack.push((-val).into())
+                }
+                OpCode::Print => {
+                    let value = self.pop()?;
+                    let _ = writeln!(self.out, "{}", value.display(&self.heap));
+                }
+                OpCode::Jump => {
+                    let offset = ip.read_short() as usize;
+                    ip.offset += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = ip.read_short() as usize;
+                    if self.peek(0).is_falsy() {
+                        ip.offset += offset;
                     }
-                    OpCode::SetGlobal => {
-                        let val = ip.read_constant();
-                        let str: InternString = val.clone().try_into()?;
-                        /*cause double borrow*/
-                        // let peek = self.peek(0);
-                        // if let Entry::Occupied(mut e) = self.globals.entry(str) {
-                        //     e.insert(peek);
-                        #[allow(clippy::map_entry)]
-                        if self.globals.contains_key(&str) {
-                            self.globals.insert(str, self.peek(0));
-                        } else {
+                }
+                OpCode::Loop => {
+                    let offset = ip.read_short() as usize;
+                    ip.offset -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = ip.read() as usize;
+                    self.call_value(self.peek(arg_count), arg_count)?;
+                }
+                OpCode::True => self.stack.push(true.into()),
+                OpCode::Pop => {
+                    self.pop()?;
+                }
+                // loading bytecode can't check slots, they depend on the
+                // stack at run time, so one past it fails like an empty pop
+                OpCode::GetLocal => {
+                    let slot = ip.read() as usize;
+                    let value = *self.stack.get(slot).ok_or_else(underflow)?;
+                    self.stack.push(value)
+                }
+                OpCode::SetLocal => {
+                    let slot = ip.read() as usize;
+                    let value = self.peek(0);
+                    *self.stack.get_mut(slot).ok_or_else(underflow)? = value;
+                }
+                OpCode::GetGlobal => {
+                    let str = self.name(ip.read_constant())?;
+                    match self.globals.get(&str) {
+                        Some(value) => self.stack.push(*value),
+                        None => {
                             return Err(VmErrors::RuntimeError(RuntimeErrors::UndefinedVariable(
-                                val.to_string(),
-                            )));
+                                self.heap.get(str).clone(),
+                            )))
                         }
                     }
-                    OpCode::False => self.stack.push(false.into()),
-                    OpCode::Equal => {
-                        let a = self.pop()?;
-                        let b = self.pop()?;
-                        self.stack.push((a == b).into())
+                }
+                OpCode::DefineGlobal => {
+                    let str = self.name(ip.read_constant())?;
+                    self.globals.insert(str, self.peek(0));
+                    self.pop()?;
+                }
+                OpCode::SetGlobal => {
+                    let str = self.name(ip.read_constant())?;
+                    /*cause double borrow*/
+                    // let peek = self.peek(0);
+                    // if let Entry::Occupied(mut e) = self.globals.entry(str) {
+                    //     e.insert(peek);
+                    #[allow(clippy::map_entry)]
+                    if self.globals.contains_key(&str) {
+                        self.globals.insert(str, self.peek(0));
+                    } else {
+                        return Err(VmErrors::RuntimeError(RuntimeErrors::UndefinedVariable(
+                            self.heap.get(str).clone(),
+                        )));
                     }
-                    OpCode::Greater => binary_op!(>),
-                    OpCode::Less => binary_op!(<),
-                    OpCode::Nil => self.stack.push(Value::Nil),
-                    OpCode::Return => {
-                        return Ok(());
+                }
+                OpCode::False => self.stack.push(false.into()),
+                OpCode::Equal => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    self.stack.push(a.equals(&b, &self.heap).into())
+                }
+                OpCode::Greater => binary_op!(>),
+                OpCode::Less => binary_op!(<),
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::GetProperty => {
+                    let name = self.name(ip.read_constant())?;
+                    let receiver = self.pop()?;
+                    let value = self.get_property(&receiver, &name)?;
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.name(ip.read_constant())?;
+                    let (value, receiver) = (self.peek(0), self.peek(1));
+                    self.set_property(&receiver, &name, value)?;
+                    self.pop()?;
+                    self.pop()?;
+                    self.stack.push(value);
+                }
+                OpCode::Import => {
+                    let path = ip.read_constant().display(&self.heap).to_string();
+                    let module = self.import(&path)?;
+                    self.stack.push(module);
+                }
+                OpCode::Try => {
+                    let offset = ip.read_short() as usize;
+                    self.handlers.push(Handler {
+                        catch: ip.offset + offset,
+                        stack: self.stack.len(),
+                    });
+                }
+                OpCode::EndTry => {
+                    self.handlers.pop();
+                }
+                OpCode::Throw => {
+                    let exception = self.pop()?;
+                    if self.handlers.len() == base {
+                        return Err(VmErrors::RuntimeError(RuntimeErrors::Uncaught(
+                            exception.display(&self.heap).to_string(),
+                        )));
                     }
-                },
-                Err(err) => return Err(VmErrors::RuntimeError(err)),
+                    self.unwind(ip, exception);
+                }
+                // only reached for a breakpoint with no original opcode
+                OpCode::Breakpoint => {}
+                OpCode::Return => {
+                    return Ok(true);
+                }
+            },
+            Err(err) => return Err(VmErrors::RuntimeError(err)),
+        }
+        Ok(false)
+    }
+
+    // enforces the fuel, stack and heap limits of `config` before each
+    // instruction, and stops on a Ctrl-C or an InterruptHandle
+    fn check_limits(&mut self, heap_baseline: usize) -> VMRes<()> {
+        if self.interrupt.take() {
+            return Err(VmErrors::RuntimeError(RuntimeErrors::Interrupted));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if crate::signal::take_interrupt() {
+            return Err(VmErrors::RuntimeError(RuntimeErrors::Interrupted));
+        }
+        if let Some(fuel) = &mut self.config.fuel {
+            if *fuel == 0 {
+                return Err(VmErrors::RuntimeError(RuntimeErrors::OutOfFuel));
+            }
+            *fuel -= 1;
+        }
+        if let Some(max) = self.config.max_stack {
+            if self.stack.len() > max {
+                return Err(VmErrors::RuntimeError(RuntimeErrors::StackOverflow(max)));
+            }
+        }
+        if let Some(max) = self.config.max_heap_bytes {
+            let used = |vm: &Self| vm.heap.bytes().saturating_sub(heap_baseline);
+            if used(self) > max {
+                memory::collect_garbage(self);
+                if used(self) > max {
+                    return Err(VmErrors::RuntimeError(RuntimeErrors::HeapLimitExceeded(
+                        max,
+                    )));
+                }
             }
         }
         Ok(())
-        // match chunk_slice.next() {
-        //     Some(x) => {}
-        //     None => Err(VmErrors::RuntimeError(RuntimeErrors::InvalidOpcode)),
-        // }
+    }
+
+    // runs the debug hook then hands back the opcode the breakpoint replaced
+    fn hit_breakpoint(&mut self, ip: &TracingIp) -> u8 {
+        let offset = ip.offset - 1;
+        if let Some(mut hook) = self.debug_hook.take() {
+            let inspector = VmInspector {
+                chunk: ip.chunk,
+                offset,
+                line: ip.line,
+                span: ip.chunk.span_at(offset),
+                stack: &self.stack,
+                globals: &self.globals,
+                heap: &self.heap,
+            };
+            (hook.0)(&inspector);
+            self.debug_hook = Some(hook);
+        }
+        self.breakpoints
+            .get(&offset)
+            .copied()
+            .unwrap_or(OpCode::Breakpoint.into())
+    }
+
+    // serves `import` from somewhere other than the filesystem
+    pub fn set_module_resolver(&mut self, resolver: impl ModuleResolver + 'static) {
+        self.resolver = Some(Resolver(Box::new(resolver)));
+    }
+
+    // files searched with `config.module_dirs` unless a resolver was set
+    fn with_resolver<R>(&mut self, f: impl FnOnce(&mut dyn ModuleResolver) -> R) -> R {
+        match &mut self.resolver {
+            Some(resolver) => f(resolver.0.as_mut()),
+            None => f(&mut FileResolver::new(self.config.module_dirs.clone())),
+        }
+    }
+
+    // the file being run, so its imports resolve relative to it
+    pub fn set_script_path(&mut self, path: &str) {
+        self.modules.script = fs::canonicalize(path)
+            .ok()
+            .map(|path| path.display().to_string());
+    }
+
+    // source text of a loaded module, or of the script when `key` is None
+    pub fn source_of(&mut self, key: Option<&str>) -> Option<String> {
+        match key {
+            Some(key) => self.with_resolver(|resolver| resolver.load(key)).ok(),
+            None if self.source.is_some() => self.source.clone(),
+            None => {
+                let bytes = fs::read(self.modules.script.as_ref()?).ok()?;
+                match bytecode::is_bytecode(&bytes) {
+                    true => None,
+                    false => Some(String::from_utf8_lossy(&bytes).into_owned()),
+                }
+            }
+        }
+    }
+
+    // compiles the module at `path` unless it already has been, returning
+    // the key its import instruction refers to it by
+    pub fn load_module(&mut self, path: &str) -> Result<String, ModuleErrors> {
+        let importer = self.modules.importer().map(str::to_owned);
+        let key = self
+            .with_resolver(|resolver| resolver.locate(path, importer.as_deref()))
+            .map_err(|reason| ModuleErrors::NotFound(path.into(), reason))?;
+        self.load_key(path, key)
+    }
+
+    fn load_key(&mut self, path: &str, key: String) -> Result<String, ModuleErrors> {
+        if self.modules.loaded.contains_key(&key) {
+            return Ok(key);
+        }
+        if let Some(chain) = self.modules.cycle(&key) {
+            return Err(ModuleErrors::Cycle(chain));
+        }
+        let bytes = self
+            .with_resolver(|resolver| resolver.load_bytes(&key))
+            .map_err(|reason| ModuleErrors::NotFound(path.into(), reason))?;
+        let (source, invalid_utf8) = decode_lossy(&bytes);
+        self.modules.read.push(key.clone());
+        self.modules.loading.push(key.clone());
+        // the chunk being compiled is no root, nothing may collect under it
+        let running = mem::take(&mut self.running);
+        let chunk = Compiler::new(&source, self)
+            .invalid_utf8_at(invalid_utf8)
+            .compile_chunk();
+        self.running = running;
+        self.modules.loading.pop();
+        let chunk = chunk.map_err(|e| ModuleErrors::Compile(path.into(), e))?;
+        let name = Path::new(&key)
+            .file_stem()
+            .map_or(key.clone(), |stem| stem.to_string_lossy().into_owned());
+        let module = LoadedModule {
+            name,
+            chunk,
+            value: None,
+        };
+        self.modules.loaded.insert(key.clone(), module);
+        Ok(key)
+    }
+
+    // runs a module's body the first time it is imported, in a globals table
+    // of its own holding only the natives, which then becomes the module
+    fn import(&mut self, key: &str) -> VMRes<Value> {
+        // chunks loaded from bytecode files import modules they never compiled
+        if !self.modules.loaded.contains_key(key) {
+            self.load_key(key, key.into())
+                .map_err(|e| VmErrors::RuntimeError(RuntimeErrors::ImportError(Box::new(e))))?;
+        }
+        let module = &self.modules.loaded[key];
+        if let Some(value) = module.value {
+            return Ok(value.into());
+        }
+        let (name, chunk) = (module.name.clone(), module.chunk.clone());
+
+        let natives: Table<InternString, Value> = self
+            .globals
+            .iter()
+            .filter(|(_, value)| matches!(value, Value::Native(_)))
+            .map(|(name, value)| (*name, *value))
+            .collect();
+        let importer = mem::replace(&mut self.globals, natives);
+        self.modules.suspended.push(importer);
+        let mut ip = TracingIp::new(&chunk, 0);
+        let result = self.execute(&mut ip);
+        // allocated while its globals are still rooted as the vm's own
+        let value = match result {
+            Ok(()) => Some(self.alloc(Module {
+                name,
+                globals: Table::new(),
+            })),
+            Err(_) => None,
+        };
+        let importer = self.modules.suspended.pop().unwrap_or_default();
+        let globals = mem::replace(&mut self.globals, importer);
+        if let Err(VmErrors::RuntimeError(error)) = result {
+            return Err(VmErrors::RuntimeError(RuntimeErrors::InModule(
+                key.into(),
+                ip.line,
+                Box::new(error),
+            )));
+        }
+        result?;
+
+        let value = value.expect("allocated for a successful run");
+        self.heap.get_mut(value).globals = globals;
+        self.heap.remeasure(value);
+        if let Some(module) = self.modules.loaded.get_mut(key) {
+            module.value = Some(value);
+        }
+        Ok(value.into())
+    }
+
+    fn get_property(&mut self, receiver: &Value, name: &InternString) -> VMRes<Value> {
+        if let Value::Module(module) = *receiver {
+            return match self.heap.get(module).globals.get(name) {
+                Some(value) => Ok(*value),
+                None => Err(self.undefined_property(receiver, name)),
+            };
+        }
+        let (object, method) = self.foreign_member(receiver, name, |class| &class.getters)?;
+        if let Some(getter) = method {
+            return (getter.1.function)(self, &object.data, &[]);
+        }
+        let (object, method) = self.foreign_member(receiver, name, |class| &class.methods)?;
+        match method {
+            // bound to the receiver as a native
+            Some((name, method)) => {
+                let data = object.data.clone();
+                let bound = NativeFn::new(name, Arity::Exact(method.arity), move |vm, args| {
+                    (method.function)(vm, &data, args)
+                });
+                Ok(self.alloc(bound).into())
+            }
+            None => Err(self.undefined_property(receiver, name)),
+        }
+    }
+
+    fn set_property(&mut self, receiver: &Value, name: &InternString, value: Value) -> VMRes<()> {
+        let (object, setter) = self.foreign_member(receiver, name, |class| &class.setters)?;
+        match setter {
+            Some((_, setter)) => (setter.function)(self, &object.data, &[value]).map(|_| ()),
+            None => Err(self.undefined_property(receiver, name)),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn foreign_member(
+        &self,
+        receiver: &Value,
+        name: &InternString,
+        members: impl Fn(&ForeignClass) -> &HashMap<&'static str, ForeignMethod>,
+    ) -> VMRes<(ForeignObj, Option<(&'static str, ForeignMethod)>)> {
+        let object = match receiver {
+            Value::Foreign(object) => self.heap.get(*object).clone(),
+            _ => {
+                return Err(VmErrors::RuntimeError(RuntimeErrors::NoProperties(
+                    receiver.display(&self.heap).to_string(),
+                )))
+            }
+        };
+        let member = self
+            .foreign_classes
+            .get(&object.type_id)
+            .and_then(|class| members(class).get_key_value(self.heap.get(*name).as_str()))
+            .map(|(name, member)| (*name, member.clone()));
+        Ok((object, member))
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> VMRes<()> {
+        match callee {
+            Value::Native(native) => {
+                let native = self.heap.get(native);
+                let (arity, function) = (native.arity, native.function.clone());
+                if !arity.accepts(arg_count) {
+                    return Err(VmErrors::RuntimeError(RuntimeErrors::ArityMismatch(
+                        arity, arg_count,
+                    )));
+                }
+                // the arguments stay on the stack as roots while it runs
+                let start = self.stack.len() - arg_count;
+                let args = self.stack[start..].to_vec();
+                let result = function(self, &args);
+                self.stack.truncate(start);
+                let result = result?;
+                // callee
+                self.pop()?;
+                self.stack.push(result);
+                Ok(())
+            }
+            _ => Err(VmErrors::RuntimeError(RuntimeErrors::NotCallable(
+                callee.display(&self.heap).to_string(),
+            ))),
+        }
+    }
+
+    fn undefined_property(&self, receiver: &Value, name: &InternString) -> VmErrors {
+        VmErrors::RuntimeError(RuntimeErrors::UndefinedProperty(
+            receiver.display(&self.heap).to_string(),
+            self.heap.get(*name).clone(),
+        ))
+    }
+
+    fn number(&self, value: &Value) -> VMRes<f64> {
+        f64::from_lox(value, &self.heap).map_err(VmErrors::RuntimeError)
+    }
+
+    // global and property names are string constants
+    fn name(&self, value: Value) -> VMRes<InternString> {
+        match value {
+            Value::String(name) => Ok(name),
+            _ => Err(VmErrors::RuntimeError(type_error(
+                "string", &value, &self.heap,
+            ))),
+        }
     }
 
     fn pop(&mut self) -> VMRes<Value> {
         match self.stack.pop() {
             Some(x) => Ok(x),
-            None => Err(VmErrors::RuntimeError(RuntimeErrors::StackUnderFlow)),
+            None => Err(underflow()),
         }
     }
 
     fn peek(&self, distance: usize) -> Value {
         // println!("{:#?}", self.stack);
         // println!("{}", self.stack.len());
-        self.stack[self.stack.len() - 1 - distance].clone()
+        self.stack[self.stack.len() - 1 - distance]
     }
 
     // pub fn interpret(&mut self, src: &str) -> InterpretRes {}
 }
+
+// only `step` can suspend a run, a native waiting on the host anywhere else
+// fails rather than hand back a token nothing will resume
+fn not_resumable(err: VmErrors) -> VmErrors {
+    match err {
+        VmErrors::RuntimeError(RuntimeErrors::Pending(token)) => {
+            VmErrors::RuntimeError(RuntimeErrors::NotResumable(token))
+        }
+        err => err,
+    }
+}
+
+fn underflow() -> VmErrors {
+    VmErrors::RuntimeError(RuntimeErrors::StackUnderFlow)
+}
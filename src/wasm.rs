@@ -0,0 +1,43 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    error::VmErrors,
+    highlight,
+    json::Json,
+    output::{CaptureBuffer, Sink},
+    report_compile_errors, report_runtime_error,
+    vm::VM,
+};
+
+// runs `source` on a fresh vm and returns everything it printed, errors
+// included, in the order they were written
+#[wasm_bindgen]
+pub fn interpret(source: &str) -> String {
+    let buffer = CaptureBuffer::default();
+    let mut vm = VM::new();
+    vm.out = Sink::new(buffer.clone());
+    vm.diagnostics = Sink::new(buffer.clone());
+    match vm.interpret(source) {
+        Ok(()) => {}
+        Err(VmErrors::CompileError(errors)) => report_compile_errors(&mut vm, &errors),
+        Err(VmErrors::RuntimeError(e)) => report_runtime_error(&mut vm, &e),
+    }
+    buffer.contents()
+}
+
+// the highlighting classes of `source` as a json array of
+// `{"start", "len", "class"}` objects, offsets in bytes
+#[wasm_bindgen]
+pub fn classify(source: &str) -> String {
+    let spans = highlight::classify(source)
+        .into_iter()
+        .map(|(span, class)| {
+            Json::object([
+                ("start", span.start.into()),
+                ("len", span.len.into()),
+                ("class", class.name().into()),
+            ])
+        })
+        .collect();
+    Json::Array(spans).to_string()
+}
@@ -0,0 +1,62 @@
+use std::{
+    fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::vm::VM;
+
+// how a file looked when last checked, None once it can't be read. The
+// length catches a save within the mtime's resolution
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &PathBuf) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// files polled for changes, for `lox_byte watch`. Polling needs no platform
+// notification api, and a few files every quarter second cost nothing
+#[derive(Debug, Clone)]
+pub struct Watched {
+    files: Vec<(PathBuf, Stamp)>,
+}
+
+impl Watched {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut files: Vec<(PathBuf, Stamp)> = Vec::new();
+        for path in paths {
+            if !files.iter().any(|(seen, _)| *seen == path) {
+                let stamp = stamp(&path);
+                files.push((path, stamp));
+            }
+        }
+        Self { files }
+    }
+
+    // the script at `path` and every module `vm` read while running it
+    pub fn script(path: &str, vm: &VM) -> Self {
+        let imports = vm.modules.read.iter().map(PathBuf::from);
+        Self::new(std::iter::once(PathBuf::from(path)).chain(imports))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn changed(&self) -> bool {
+        self.files.iter().any(|(path, seen)| stamp(path) != *seen)
+    }
+
+    // blocks until one of the files is saved, created or removed
+    pub fn wait(&self) {
+        while !self.changed() {
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
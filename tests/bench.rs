@@ -0,0 +1,27 @@
+use lox_byte::{
+    bench::{self, Sample, Summary},
+    output::Sink,
+    VM,
+};
+
+#[test]
+fn bench_samples_count_instructions_and_summaries_hold_their_statistics() {
+    let mut vm = VM::new();
+    vm.out = Sink::new(std::io::sink());
+    vm.config.fuel = Some(7);
+    // Constant, Print and Return
+    let sample = bench::sample(&mut vm, "print 1;").unwrap();
+    assert_eq!(sample.instructions, 3);
+    assert_eq!(vm.config.fuel, Some(7), "the fuel is put back");
+
+    let sample = |ms: u64, instructions: u64| Sample {
+        elapsed: std::time::Duration::from_millis(ms),
+        instructions,
+    };
+    let summary = Summary::of(&[sample(4, 10), sample(1, 10), sample(2, 13), sample(1, 11)]);
+    assert_eq!(summary.runs, 4);
+    assert_eq!(summary.mean.as_micros(), 2000);
+    assert_eq!(summary.median.as_micros(), 1500);
+    assert_eq!(summary.stddev.as_micros(), 1224);
+    assert_eq!(summary.instructions, 11);
+}
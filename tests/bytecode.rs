@@ -0,0 +1,116 @@
+// moving compiled chunks between vms and loading them from files
+use lox_byte::{
+    bytecode::{self, MAGIC},
+    error::BytecodeErrors,
+    opcode::OpCode,
+    output::Sink,
+    value::create_string,
+    RuntimeErrors, Value, VmErrors, VM,
+};
+
+// a compiled file for `source` and where its code starts in it
+fn compiled(source: &str) -> (Vec<u8>, usize) {
+    let mut vm = VM::new();
+    vm.compile(source).unwrap();
+    let bytes = bytecode::serialize(&vm.chunks, &vm.heap).unwrap();
+    (bytes, MAGIC.len() + 1 + 4)
+}
+
+// offset of the first `op` in the file's code
+fn find(bytes: &[u8], code: usize, op: OpCode) -> usize {
+    let mut offset = code;
+    loop {
+        let found = OpCode::try_from(bytes[offset]).unwrap();
+        if found == op {
+            return offset - code;
+        }
+        offset += 1 + found.operand().size();
+    }
+}
+
+fn load(bytes: &[u8]) -> Result<(), BytecodeErrors> {
+    bytecode::deserialize(&mut VM::new(), bytes).map(|_| ())
+}
+
+#[test]
+fn constant_indices_past_the_table_are_refused() {
+    let (mut bytes, code) = compiled("print 1;");
+    assert!(load(&bytes).is_ok());
+    let at = find(&bytes, code, OpCode::Constant);
+    bytes[code + at + 1] = 1;
+    assert!(matches!(
+        load(&bytes),
+        Err(BytecodeErrors::InvalidConstant(offset, 1)) if offset == at
+    ));
+}
+
+#[test]
+fn jumps_past_the_end_are_refused() {
+    let (mut bytes, code) = compiled("if (true) print 1;");
+    assert!(load(&bytes).is_ok());
+    let at = find(&bytes, code, OpCode::JumpIfFalse);
+    bytes[code + at + 1..code + at + 3].copy_from_slice(&[0x10, 0x00]);
+    assert!(matches!(
+        load(&bytes),
+        Err(BytecodeErrors::InvalidJump(offset, _)) if offset == at
+    ));
+}
+
+#[test]
+fn jumps_into_an_operand_are_refused() {
+    let (mut bytes, code) = compiled("if (true) print 1; else print 2;");
+    let at = find(&bytes, code, OpCode::JumpIfFalse);
+    let jump = u16::from_be_bytes([bytes[code + at + 1], bytes[code + at + 2]]);
+    // one short of the else branch lands in the operand of the jump over it
+    bytes[code + at + 1..code + at + 3].copy_from_slice(&(jump - 1).to_be_bytes());
+    assert!(matches!(
+        load(&bytes),
+        Err(BytecodeErrors::InvalidJump(offset, target))
+            if offset == at && target == (at + 3 + jump as usize - 1) as isize
+    ));
+}
+
+#[test]
+fn loops_before_the_start_are_refused() {
+    let (mut bytes, code) = compiled("while (false) {}");
+    assert!(load(&bytes).is_ok());
+    let at = find(&bytes, code, OpCode::Loop);
+    bytes[code + at + 1..code + at + 3].copy_from_slice(&[0x01, 0x00]);
+    assert!(matches!(
+        load(&bytes),
+        Err(BytecodeErrors::InvalidJump(offset, target)) if offset == at && target < 0
+    ));
+}
+
+#[test]
+fn a_bound_chunk_runs_against_the_globals_of_its_new_vm() {
+    let mut builder = VM::new();
+    builder.compile("print greeting + \"!\";").unwrap();
+    let mut chunk = builder.chunks.clone();
+
+    let mut vm = VM::new();
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    vm.interpret("var greeting = \"hello\";").unwrap();
+    chunk.bind(&builder.heap, &mut vm);
+    // the name is the string the vm already interned, so the lookup finds it
+    let name = create_string(&mut vm, "greeting");
+    assert!(chunk.constants.contains(&Value::String(name)));
+    drop(builder);
+    vm.chunks = chunk;
+    vm.run().unwrap();
+    assert_eq!(output.contents(), "hello!\n");
+}
+
+#[test]
+fn local_slots_past_the_stack_fail_when_run() {
+    let (mut bytes, code) = compiled("{ var a = 1; print a; }");
+    let at = find(&bytes, code, OpCode::GetLocal);
+    bytes[code + at + 1] = 200;
+    let mut vm = VM::new();
+    vm.chunks = bytecode::deserialize(&mut vm, &bytes).unwrap();
+    assert!(matches!(
+        vm.run(),
+        Err(VmErrors::RuntimeError(RuntimeErrors::StackUnderFlow))
+    ));
+}
@@ -0,0 +1,47 @@
+// the C interface, called from rust the way a C host would
+#![cfg(feature = "capi")]
+
+use std::ffi::{CStr, CString};
+
+use lox_byte::capi::{
+    lox_vm_error, lox_vm_free, lox_vm_interpret, lox_vm_new, lox_vm_output, LOX_COMPILE_ERROR,
+    LOX_INVALID_ARGUMENT, LOX_OK, LOX_RUNTIME_ERROR,
+};
+
+fn interpret(vm: *mut lox_byte::capi::LoxVm, source: &str) -> i32 {
+    let source = CString::new(source).unwrap();
+    unsafe { lox_vm_interpret(vm, source.as_ptr()) }
+}
+
+fn read(text: *const std::ffi::c_char) -> String {
+    unsafe { CStr::from_ptr(text) }.to_str().unwrap().into()
+}
+
+#[test]
+fn a_vm_is_created_run_and_freed_through_the_c_interface() {
+    let vm = lox_vm_new();
+    assert_eq!(interpret(vm, "var a = 1; print a;"), LOX_OK);
+    unsafe {
+        assert_eq!(read(lox_vm_output(vm)), "1\n");
+        assert_eq!(read(lox_vm_error(vm)), "");
+    }
+    // globals persist between calls
+    assert_eq!(interpret(vm, "print a + 1; print -nil;"), LOX_RUNTIME_ERROR);
+    unsafe {
+        assert_eq!(read(lox_vm_output(vm)), "2\n");
+        assert!(read(lox_vm_error(vm)).contains("runtime error[E0101]"));
+    }
+    assert_eq!(interpret(vm, "print ;"), LOX_COMPILE_ERROR);
+    unsafe {
+        assert_eq!(read(lox_vm_output(vm)), "");
+        assert!(read(lox_vm_error(vm)).contains("Expected expression."));
+        assert_eq!(lox_vm_interpret(vm, std::ptr::null()), LOX_INVALID_ARGUMENT);
+        assert_eq!(
+            lox_vm_interpret(std::ptr::null_mut(), c"print 1;".as_ptr()),
+            LOX_INVALID_ARGUMENT
+        );
+        assert!(lox_vm_output(std::ptr::null()).is_null());
+        lox_vm_free(vm);
+        lox_vm_free(std::ptr::null_mut());
+    }
+}
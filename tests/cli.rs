@@ -0,0 +1,142 @@
+// the lox_byte binary as a shell or an editor runs it
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use lox_byte::json::Json;
+
+// runs the binary with `args`, `stdin` piped in
+fn lox(args: &[&str], stdin: &str) -> Output {
+    run(
+        Command::new(env!("CARGO_BIN_EXE_lox_byte")).args(args),
+        stdin,
+    )
+}
+
+fn run(command: &mut Command, stdin: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+// a script file of its own for each test, tests run in parallel
+fn script(name: &str, source: &str) -> PathBuf {
+    let name = format!("lox_byte_cli_{}_{}", std::process::id(), name);
+    let path = std::env::temp_dir().join(name);
+    fs::write(&path, source).unwrap();
+    fs::canonicalize(path).unwrap()
+}
+
+// the json diagnostics written to stderr
+fn diagnostics(output: &Output) -> Vec<Json> {
+    String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .map(|line| Json::parse(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn json_errors_carry_every_field() {
+    let path = script("json_compile.lox", "var a = 1;\nprint a +;\n");
+    let output = lox(&["--error-format=json", path.to_str().unwrap()], "");
+    let errors = diagnostics(&output);
+    assert_eq!(errors.len(), 1);
+    let error = &errors[0];
+    assert_eq!(error.get("severity").as_str(), Some("error"));
+    assert_eq!(error.get("code").as_str(), Some("E0005"));
+    assert_eq!(error.get("message").as_str(), Some("Expected expression."));
+    assert_eq!(error.get("file").as_str(), path.to_str());
+    assert_eq!(error.get("line").as_usize(), Some(2));
+    assert_eq!(error.get("column").as_usize(), Some(10));
+    assert_eq!(error.get("length").as_usize(), Some(1));
+    assert_eq!(*error.get("hint"), Json::Null);
+    assert!(error.get("notes").as_array().is_empty());
+    fs::remove_file(path).unwrap();
+
+    let path = script("json_runtime.lox", "print 1;\nprint x;\n");
+    let output = lox(&["--error-format=json", path.to_str().unwrap()], "");
+    let errors = diagnostics(&output);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].get("code").as_str(), Some("E0102"));
+    assert_eq!(errors[0].get("line").as_usize(), Some(2));
+    assert!(errors[0].get("hint").as_str().is_some());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn compile_runtime_and_io_failures_exit_with_distinct_codes() {
+    let stderr = |output: &Output| String::from_utf8_lossy(&output.stderr).into_owned();
+
+    let output = lox(&["-e", "print 1;"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+
+    let output = lox(&["-e", "print ;"], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(
+        stderr(&output).contains("error[E0005]"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("E0005"));
+
+    let output = lox(&["-e", "print 1; print -nil;"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("runtime error[E0101]"));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+
+    let output = lox(&["-e", "exit(3);"], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stderr(&output), "");
+
+    let missing = std::env::temp_dir().join("lox_byte_cli_missing.lox");
+    let output = lox(&[missing.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(66));
+    assert!(stderr(&output).starts_with("IO Error: Cannot open"));
+}
+
+#[test]
+fn a_program_piped_in_runs_and_is_called_stdin_in_diagnostics() {
+    let output = lox(&[], "print \"piped\";\nprint -nil;\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "piped\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(" --> <stdin>:2\n"), "{}", stderr);
+    assert!(stderr.contains("2 | print -nil;"), "{}", stderr);
+
+    let output = lox(&["--error-format=json", "-"], "print 1;\nprint ;\n");
+    assert_eq!(output.status.code(), Some(65));
+    let errors = diagnostics(&output);
+    assert_eq!(errors[0].get("file").as_str(), Some("<stdin>"));
+    assert_eq!(errors[0].get("line").as_usize(), Some(2));
+}
+
+#[test]
+fn a_program_piped_in_imports_from_the_working_directory() {
+    let module = script("piped_module.lox", "var answer = 42;");
+    let name = module.file_name().unwrap().to_str().unwrap();
+    let program = format!("import \"{}\" as m; print m.answer;", name);
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lox_byte"));
+    let output = run(command.current_dir(module.parent().unwrap()), &program);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "42\n",
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::remove_file(module).unwrap();
+}
@@ -0,0 +1,43 @@
+// the color mode is process wide, so its cases share one test
+use lox_byte::{
+    cformat,
+    macros::{colors_for, set_color_mode, ColorMode},
+    output::Sink,
+    report_compile_errors, VmErrors, VM,
+};
+
+fn rendered_error() -> String {
+    let mut vm = VM::new();
+    let (diagnostics, buffer) = Sink::capture();
+    vm.diagnostics = diagnostics;
+    let Err(VmErrors::CompileError(errors)) = vm.compile("print ;") else {
+        panic!("compiled");
+    };
+    report_compile_errors(&mut vm, &errors);
+    buffer.contents()
+}
+
+fn echoed() -> String {
+    let mut vm = VM::new();
+    let (out, buffer) = Sink::capture();
+    vm.out = out;
+    vm.interpret_echo("\"text\"").unwrap();
+    buffer.contents()
+}
+
+#[test]
+fn never_leaves_out_every_color_code() {
+    set_color_mode(ColorMode::Always);
+    assert!(colors_for(false));
+    assert!(rendered_error().contains('\x1b'));
+    assert!(echoed().contains('\x1b'));
+
+    set_color_mode(ColorMode::Never);
+    assert!(!colors_for(true));
+    assert_eq!(cformat!(colors_for(true), Red, "{}", 1), "1");
+    let error = rendered_error();
+    assert!(error.contains("Expected expression."), "{}", error);
+    assert!(!error.contains('\x1b'), "{:?}", error);
+    assert_eq!(echoed(), "\"text\"\n");
+    set_color_mode(ColorMode::Auto);
+}
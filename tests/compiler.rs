@@ -0,0 +1,135 @@
+// compiler options and limits, and the parse log
+use lox_byte::{
+    compiler::Compiler, error::CompileErrors, output::Sink, parse_log::ParseLog, VmConfig,
+    VmErrors, VM,
+};
+
+#[test]
+fn deep_nesting_is_one_compile_error_rather_than_a_stack_overflow() {
+    let depth = 5_000;
+    for source in [
+        format!("print {}1{};", "(".repeat(depth), ")".repeat(depth)),
+        format!("print {}1;", "-".repeat(depth)),
+        format!("{}{}", "{".repeat(depth), "}".repeat(depth)),
+        format!("{}print 1;", "if (true) ".repeat(depth)),
+    ] {
+        let mut vm = VM::new();
+        let Err(VmErrors::CompileError(errors)) = vm.compile(&source) else {
+            panic!("compiled {}...", &source[..20]);
+        };
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].error,
+            CompileErrors::TooDeeplyNested(256)
+        ));
+    }
+    let mut vm = VM::with_config(VmConfig::new().max_nesting(8));
+    assert!(vm.compile("print ((((1))));").is_ok());
+    assert!(vm.compile("print ((((((((((1))))))))));").is_err());
+}
+
+#[test]
+fn modern_print_is_a_native_that_can_be_shadowed() {
+    let mut vm = VM::with_config(VmConfig::new().modern());
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    vm.interpret("print(\"a\", 1, nil); var p = print; { var print = 2; p(print); }")
+        .unwrap();
+    assert_eq!(output.contents(), "a 1 nil\n2\n");
+    assert!(vm.compile("print \"a\";").is_err());
+    assert!(VM::new().compile("print(\"a\", 1);").is_err());
+}
+
+#[test]
+fn chained_comparisons_read_as_pairs_joined_by_and() {
+    let source = "
+        print 1 < do { print \"b\"; 2 } <= 2;
+        print 3 < 1 < do { print \"skipped\"; 2 };
+        { var x = 5; print 0 < x < 10 > 9; print 0 <= x < 5; }";
+    let mut vm = VM::with_config(VmConfig::new().chain_comparisons());
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    vm.interpret(source).unwrap();
+    assert_eq!(output.contents(), "b\ntrue\nfalse\ntrue\nfalse\n");
+
+    let Err(VmErrors::CompileError(errors)) = VM::new().compile("print 1 < 2 < 3;") else {
+        panic!("compiled a chained comparison without the option");
+    };
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0].error, CompileErrors::ChainedComparison));
+}
+
+#[test]
+fn the_parse_log_nests_rules_tokens_and_code() {
+    let mut vm = VM::with_config(VmConfig {
+        dump_failed_chunks: false,
+        ..VmConfig::default()
+    });
+    let mut cc = Compiler::new("print -1 + 2;", &mut vm);
+    cc.parser.log = Some(ParseLog::default());
+    cc.compile_chunk().unwrap();
+    let log = cc.parser.log.take().unwrap();
+    let tree = log.render(&cc.compiling_chunk, &cc.vm.heap);
+    let lines: Vec<_> = tree
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(
+        lines[..16],
+        [
+            "token 'print'",
+            "print_statement",
+            "parse_precedence Assignment",
+            "token '-'",
+            "prefix Minus",
+            "parse_precedence Unary",
+            "token '1'",
+            "prefix Number",
+            "0000 Op_Constant 0 1",
+            "0002 Op_Negate",
+            "token '+'",
+            "infix Plus",
+            "parse_precedence Factor",
+            "token '2'",
+            "prefix Number",
+            "0003 Op_Constant 1 2",
+        ]
+    );
+    // each rule is indented under the one it was parsed in
+    assert!(tree.contains("\n        prefix Number\n          0000 Op_Constant"));
+}
+
+#[test]
+fn fed_pieces_report_errors_where_a_whole_source_does() {
+    let report = |errors: Vec<lox_byte::error::Diagnostic>| {
+        errors
+            .into_iter()
+            .map(|d| (d.error.to_string(), d.span, d.lexeme, d.snippet))
+            .collect::<Vec<_>>()
+    };
+    for source in [
+        "var a = 1; print a +;\n",
+        "print 1_000_; print 1__0; print 0x; print 1e;\n",
+        "var a = 1;\nvar b = 2; print a + b; print b -;\nprint a; // done\nprint ;",
+    ] {
+        let Err(VmErrors::CompileError(whole)) = VM::new().compile(source) else {
+            panic!("compiled {:?}", source);
+        };
+        for size in [1, 3, 7] {
+            let mut vm = VM::new();
+            let mut cc = Compiler::incremental(&mut vm);
+            let chars: Vec<char> = source.chars().collect();
+            for piece in chars.chunks(size) {
+                cc.feed(&piece.iter().collect::<String>());
+            }
+            let fed = cc.finish().unwrap_err();
+            assert_eq!(
+                report(fed),
+                report(whole.clone()),
+                "{:?} by {}",
+                source,
+                size
+            );
+        }
+    }
+}
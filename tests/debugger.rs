@@ -0,0 +1,43 @@
+// breakpoints set by an embedder and what the hook sees when one is hit
+use std::sync::{Arc, Mutex};
+
+use lox_byte::{output::Sink, LoxValue, VM};
+
+#[test]
+fn a_breakpoint_pauses_at_its_line_with_the_state_before_it() {
+    let mut vm = VM::new();
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    vm.compile("var a = 1;\nvar b = a + 1;\nprint a + b;")
+        .unwrap();
+    let offset = vm.set_breakpoint(2).unwrap();
+
+    let hits = Arc::new(Mutex::new(Vec::new()));
+    let seen = hits.clone();
+    vm.on_breakpoint(move |inspector| {
+        let a = inspector
+            .globals
+            .iter()
+            .find(|(name, _)| inspector.heap.get(**name) == "a")
+            .map(|(_, value)| LoxValue::from_value(value, inspector.heap));
+        let b = inspector
+            .globals
+            .iter()
+            .any(|(name, _)| inspector.heap.get(*name) == "b");
+        seen.lock()
+            .unwrap()
+            .push((inspector.offset, inspector.line, a, b));
+    });
+    vm.run().unwrap();
+    assert_eq!(
+        *hits.lock().unwrap(),
+        [(offset, Some(2), Some(LoxValue::Number(1.0)), false)]
+    );
+    // the instruction the breakpoint replaced still ran
+    assert_eq!(output.contents(), "3\n");
+
+    vm.clear_breakpoint(2);
+    vm.run().unwrap();
+    assert_eq!(hits.lock().unwrap().len(), 1);
+    assert_eq!(output.contents(), "3\n3\n");
+}
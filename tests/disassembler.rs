@@ -0,0 +1,44 @@
+// the disassembly tools and tests read instead of the colored listing
+use lox_byte::{disassembler::Disassembler, opcode::OpCode, LoxValue, VM};
+
+#[test]
+fn disassembly_writes_into_any_writer() {
+    let mut vm = VM::new();
+    vm.compile("var a = 1;\nif (a) print \"yes\";").unwrap();
+    let mut out = Vec::new();
+    vm.chunks.disassemble_to(&vm.heap, &mut out).unwrap();
+    let listing = String::from_utf8(out).unwrap();
+    assert_eq!(
+        listing,
+        "\
+0000 0001 Op_Constant 1    1
+0002    | Op_DefineGlobal 0    \"a\"
+0004 0002 Op_GetGlobal 2    \"a\"
+0006    | Op_JumpIfFalse    7 ->   16
+0009    | Op_Pop
+0010    | Op_Constant 3    \"yes\"
+0012    | Op_Print
+0013    | Op_Jump    1 ->   17
+0016    | Op_Pop
+0017    | Op_Return
+"
+    );
+}
+
+#[test]
+fn instructions_decode_operands_constants_and_targets() {
+    let mut vm = VM::new();
+    vm.compile("var a = 1;\nif (a) print \"yes\";").unwrap();
+    let instructions: Vec<_> = vm.chunks.instructions(&vm.heap).collect();
+    assert_eq!(instructions.len(), 10);
+    let define = &instructions[1];
+    assert_eq!(define.opcode, Some(OpCode::DefineGlobal));
+    assert_eq!((define.offset, define.line), (2, Some(1)));
+    assert_eq!(define.operands, [0]);
+    assert_eq!(define.constant, Some(LoxValue::String("a".into())));
+    let jump = &instructions[3];
+    assert_eq!(jump.opcode, Some(OpCode::JumpIfFalse));
+    assert_eq!(jump.operands, [0, 7]);
+    assert_eq!(jump.target, Some(16));
+    assert_eq!(jump.next_offset(), 9);
+}
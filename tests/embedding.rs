@@ -0,0 +1,271 @@
+// the api a host runs scripts through and reads their results with
+use lox_byte::{Capability, CompileErrors, LoxError, LoxValue, RuntimeErrors, Value, VmConfig, VM};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn eval_returns_the_trailing_expression_as_a_typed_value() {
+    let mut vm = VM::new();
+    assert_eq!(vm.eval("1 + 2").unwrap(), LoxValue::Number(3.0));
+    assert_eq!(
+        vm.eval("\"a\" + \"b\"").unwrap(),
+        LoxValue::String("ab".into())
+    );
+    assert_eq!(vm.eval("1 < 2").unwrap(), LoxValue::Bool(true));
+    assert_eq!(vm.eval("nil").unwrap(), LoxValue::Nil);
+    assert_eq!(vm.eval("var a = 1;").unwrap(), LoxValue::Nil);
+    assert_eq!(vm.eval("a + 1").unwrap(), LoxValue::Number(2.0));
+
+    let n: f64 = vm.eval("a * 4").unwrap().try_into().unwrap();
+    assert_eq!(n, 4.0);
+    let s: String = vm.eval("\"x\"").unwrap().try_into().unwrap();
+    assert_eq!(s, "x");
+    let error = f64::try_from(vm.eval("\"x\"").unwrap()).unwrap_err();
+    assert!(matches!(
+        error,
+        LoxError::Conversion {
+            expected: "number",
+            found: "string"
+        }
+    ));
+}
+
+#[test]
+fn eval_reports_compile_and_runtime_errors() {
+    let mut vm = VM::new();
+    let Err(LoxError::Compile(errors)) = vm.eval("1 +") else {
+        panic!("compiled");
+    };
+    assert!(matches!(errors[0].error, CompileErrors::ExpectedExpression));
+    let Err(LoxError::Runtime { error, line }) = vm.eval("\n-\"a\"") else {
+        panic!("ran");
+    };
+    assert!(matches!(error, RuntimeErrors::TypeError(..)));
+    assert_eq!(line, Some(2));
+    // nothing the failed runs left behind shows up in the next
+    assert_eq!(vm.stack.len(), 0);
+    assert_eq!(vm.eval("2").unwrap(), LoxValue::Number(2.0));
+}
+
+#[test]
+fn strings_from_eval_outlive_a_collection() {
+    let mut vm = VM::with_config(VmConfig::new().gc_stress());
+    let value = vm.eval("var s = \"kept\"; s + \"!\"").unwrap();
+    vm.eval("s = nil;").unwrap();
+    vm.collect_garbage();
+    vm.eval("for (var i = 0; i < 10; i = i + 1) \"garbage\" + \"!\";")
+        .unwrap();
+    assert_eq!(value, LoxValue::String("kept!".into()));
+    assert_eq!(value.to_string(), "kept!");
+}
+
+// the runtime error `source` fails with
+fn runtime_error(vm: &mut VM, source: &str) -> RuntimeErrors {
+    match vm.eval(source) {
+        Err(LoxError::Runtime { error, .. }) => error,
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn bound_closures_convert_their_arguments_and_results() {
+    let mut vm = VM::new();
+    vm.bind("add", |a: f64, b: f64| a + b);
+    vm.bind("greet", |name: String, loud: bool| match loud {
+        true => format!("HI {}!", name.to_uppercase()),
+        false => format!("hi {}", name),
+    });
+    vm.bind("or_zero", |n: Option<f64>| n.unwrap_or(0.0));
+    vm.bind("half", |n: f64| match n % 2.0 == 0.0 {
+        true => Ok(n / 2.0),
+        false => Err(format!("{} is odd", n)),
+    });
+    assert_eq!(vm.eval("add(1, 2)").unwrap(), LoxValue::Number(3.0));
+    assert_eq!(
+        vm.eval("greet(\"lox\", true)").unwrap(),
+        LoxValue::String("HI LOX!".into())
+    );
+    assert_eq!(vm.eval("or_zero(nil)").unwrap(), LoxValue::Number(0.0));
+    assert_eq!(vm.eval("or_zero(4)").unwrap(), LoxValue::Number(4.0));
+    assert_eq!(vm.eval("half(4)").unwrap(), LoxValue::Number(2.0));
+
+    assert!(matches!(
+        runtime_error(&mut vm, "add(1);"),
+        RuntimeErrors::ArityMismatch(_, 1)
+    ));
+    assert!(matches!(
+        runtime_error(&mut vm, "add(1, \"2\");"),
+        RuntimeErrors::TypeError("number", _)
+    ));
+    assert!(matches!(
+        runtime_error(&mut vm, "half(3);"),
+        RuntimeErrors::HostError(message) if message == "3 is odd"
+    ));
+    assert!(matches!(
+        runtime_error(&mut vm, "subtract(1, 2);"),
+        RuntimeErrors::UndefinedVariable(name) if name == "subtract"
+    ));
+}
+
+struct Counter {
+    count: f64,
+}
+
+#[test]
+fn foreign_objects_cross_into_lox_and_back() {
+    let mut vm = VM::new();
+    vm.register_foreign::<Counter>("Counter")
+        .method("add", |counter: &mut Counter, n: f64| {
+            counter.count += n;
+            counter.count
+        })
+        .getter("count", |counter: &mut Counter| counter.count)
+        .setter("count", |counter: &mut Counter, n: f64| counter.count = n);
+    let counter = vm.foreign(Counter { count: 1.0 });
+    vm.define_global("counter", counter);
+
+    assert_eq!(vm.eval("counter.add(2)").unwrap(), LoxValue::Number(3.0));
+    vm.eval("counter.count = counter.count * 10;").unwrap();
+    let LoxValue::Foreign(object) = vm.eval("counter").unwrap() else {
+        panic!("not foreign");
+    };
+    assert_eq!(object.to_string(), "<Counter instance>");
+    assert_eq!(
+        object.with(|counter: &mut Counter| counter.count),
+        Some(30.0)
+    );
+    assert_eq!(object.with(|_: &mut String| ()), None);
+
+    // handed back, it is the same rust value rather than a copy
+    let value = LoxValue::Foreign(object.clone()).into_value(&mut vm);
+    vm.define_global("again", value);
+    vm.eval("again.add(1);").unwrap();
+    assert_eq!(
+        object.with(|counter: &mut Counter| counter.count),
+        Some(31.0)
+    );
+    assert_eq!(vm.eval("again == counter").unwrap(), LoxValue::Bool(true));
+
+    assert!(matches!(
+        runtime_error(&mut vm, "counter.add();"),
+        RuntimeErrors::ArityMismatch(_, 0)
+    ));
+    assert!(matches!(
+        runtime_error(&mut vm, "counter.add(\"1\");"),
+        RuntimeErrors::TypeError("number", _)
+    ));
+    assert!(matches!(
+        runtime_error(&mut vm, "counter.missing;"),
+        RuntimeErrors::UndefinedProperty(..)
+    ));
+}
+
+#[test]
+fn call_function_runs_callbacks_a_script_set_up() {
+    let mut vm = VM::new();
+    vm.bind("join", |a: String, b: f64| format!("{}{}", a, b));
+    vm.bind("same", |value: LoxValue| value);
+    let registered = Arc::new(Mutex::new(None));
+    let slot = registered.clone();
+    vm.bind("on_event", move |callback: Value| {
+        *slot.lock().unwrap() = Some(callback);
+    });
+    vm.interpret("var handler = join; on_event(join); var number = 1;")
+        .unwrap();
+
+    let args = [LoxValue::from("n"), LoxValue::from(2)];
+    assert_eq!(
+        vm.call_function("handler", &args).unwrap(),
+        LoxValue::String("n2".into())
+    );
+    let callback = registered.lock().unwrap().unwrap();
+    assert_eq!(
+        vm.call_function(callback, &args).unwrap(),
+        LoxValue::String("n2".into())
+    );
+    let counter = vm.foreign(Counter { count: 5.0 });
+    let counter = LoxValue::from_value(&counter, &vm.heap);
+    assert_eq!(
+        vm.call_function("same", std::slice::from_ref(&counter))
+            .unwrap(),
+        counter
+    );
+
+    let error = |result: Result<LoxValue, LoxError>| match result {
+        Err(LoxError::Runtime { error, .. }) => error,
+        other => panic!("{:?}", other),
+    };
+    assert!(matches!(
+        error(vm.call_function("handler", &args[..1])),
+        RuntimeErrors::ArityMismatch(_, 1)
+    ));
+    assert!(matches!(
+        error(vm.call_function("handler", &[LoxValue::Nil, LoxValue::Nil])),
+        RuntimeErrors::TypeError("string", _)
+    ));
+    assert!(matches!(
+        error(vm.call_function("missing", &[])),
+        RuntimeErrors::UndefinedVariable(name) if name == "missing"
+    ));
+    assert!(matches!(
+        error(vm.call_function("number", &[])),
+        RuntimeErrors::NotCallable(_)
+    ));
+    assert_eq!(vm.stack.len(), 0);
+}
+
+#[test]
+fn running_out_of_fuel_stops_the_script_until_refuelled() {
+    let mut vm = VM::with_config(VmConfig::new().fuel(1_000));
+    assert!(matches!(
+        runtime_error(&mut vm, "while (true) {}"),
+        RuntimeErrors::OutOfFuel
+    ));
+    assert!(matches!(
+        runtime_error(&mut vm, "1 + 1"),
+        RuntimeErrors::OutOfFuel
+    ));
+    vm.config.fuel = Some(1_000);
+    assert_eq!(vm.eval("1 + 1").unwrap(), LoxValue::Number(2.0));
+    assert_eq!(vm.stack.len(), 0);
+}
+
+#[test]
+fn overflowing_the_stack_fails_and_leaves_the_vm_usable() {
+    let mut vm = VM::with_config(VmConfig::new().max_stack(4));
+    let deep = "{ var a = 1; var b = 2; var c = 3; var d = 4; var e = 5; print e; }";
+    assert!(matches!(
+        runtime_error(&mut vm, deep),
+        RuntimeErrors::StackOverflow(4)
+    ));
+    assert_eq!(vm.stack.len(), 0);
+    assert_eq!(
+        vm.eval("var r; { var a = 1; var b = 2; r = a + b; } r")
+            .unwrap(),
+        LoxValue::Number(3.0)
+    );
+}
+
+#[test]
+fn exceeding_the_heap_limit_fails_and_leaves_the_vm_usable() {
+    let mut vm = VM::with_config(VmConfig::new().max_heap_bytes(4_096));
+    let growing = "var s = \"x\"; while (true) s = s + s;";
+    assert!(matches!(
+        runtime_error(&mut vm, growing),
+        RuntimeErrors::HeapLimitExceeded(4_096)
+    ));
+    vm.eval("s = nil;").unwrap();
+    assert_eq!(
+        vm.eval("\"a\" + \"b\"").unwrap(),
+        LoxValue::String("ab".into())
+    );
+}
+
+#[test]
+fn denied_capabilities_leave_their_natives_undefined() {
+    let mut vm = VM::with_config(VmConfig::new().deny(Capability::Time));
+    assert!(matches!(
+        runtime_error(&mut vm, "clock();"),
+        RuntimeErrors::UndefinedVariable(name) if name == "clock"
+    ));
+    assert!(VM::new().eval("clock()").is_ok());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use lox_byte::{formatter::format_source, scanner::Scanner, token::TType};
+use proptest::prelude::*;
+
+fn tokens(source: &str) -> Vec<(TType, Option<&str>)> {
+    Scanner::new(source).map(|t| (t.ttype, t.lexeme)).collect()
+}
+
+#[test]
+fn messy_source_is_laid_out() {
+    let source = "// header
+import \"m.lox\"   as   m ;
+var   a=1+2*-3 ;var b = !true;   // trailing
+{var c=a-1;
+
+
+   // own line
+   print c ; if(c>1){print \"big\";}else if (c < 0) print -c; else {}
+}
+for(var i=0;i<3;i=i+1)print i;
+try{throw \"x\";}catch(e){print e;}
+print f( ) + g(a,b , do {var x = 1; x}) - -1;
+var d = a
+  + // broken
+  b;";
+    let expected = "// header
+import \"m.lox\" as m;
+var a = 1 + 2 * -3;
+var b = !true; // trailing
+{
+  var c = a - 1;
+
+  // own line
+  print c;
+  if (c > 1) {
+    print \"big\";
+  } else if (c < 0) print -c; else {}
+}
+for (var i = 0; i < 3; i = i + 1) print i;
+try {
+  throw \"x\";
+} catch (e) {
+  print e;
+}
+print f() + g(a, b, do {
+  var x = 1;
+  x
+}) - -1;
+var d = a + // broken
+  b;
+";
+    assert_eq!(format_source(source).unwrap(), expected);
+}
+
+#[test]
+fn test_programs_keep_their_tokens_and_comments() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    for path in common::lox_files(&root) {
+        let source = fs::read_to_string(&path).unwrap();
+        let Ok(formatted) = format_source(&source) else {
+            continue;
+        };
+        let name = path.display();
+        assert_eq!(tokens(&formatted), tokens(&source), "{name}");
+        assert_eq!(
+            formatted.matches("// expect").count(),
+            source.matches("// expect").count(),
+            "{name}"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted, "{name}");
+    }
+}
+
+#[test]
+fn sources_that_do_not_scan_are_refused() {
+    let errors = format_source("print \"open;").unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+proptest! {
+    // any scannable text, laid out again, scans to the same tokens, and
+    // laying it out a second time changes nothing
+    #[test]
+    fn formatting_keeps_tokens_and_settles(
+        pieces in prop::collection::vec(
+            (
+                prop::sample::select(vec![
+                    "var", "a", "b", "=", "==", "!", "!=", "<", "-", "+", "*", "/", "1", "2.5", ".",
+                    "\"s\"", "(", ")", "{", "}", ";", ",", "print", "if", "else", "for", "do",
+                    "and", "nil", "// note\n",
+                ]),
+                prop::sample::select(vec!["", " ", "\n", "\n\n"]),
+            ),
+            0..60,
+        ),
+    ) {
+        let source: String = pieces.iter().map(|(token, space)| format!("{token}{space}")).collect();
+        let formatted = format_source(&source);
+        prop_assume!(formatted.is_ok());
+        let formatted = formatted.unwrap();
+        let tokens = |source: &str| -> Vec<(TType, String)> {
+            Scanner::new(source)
+                .map(|t| (t.ttype, t.lexeme.unwrap_or_default().to_owned()))
+                .collect()
+        };
+        prop_assert_eq!(tokens(&formatted), tokens(&source));
+        prop_assert_eq!(formatted.matches("// note").count(), source.matches("// note").count());
+        prop_assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+}
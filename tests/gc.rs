@@ -0,0 +1,77 @@
+// collection, per vm heap accounting and the string buffer pool
+use lox_byte::{output::Sink, value::create_string, VmConfig, VM};
+
+#[test]
+fn a_zero_threshold_collects_while_running_and_on_request() {
+    let mut vm = VM::with_config(VmConfig::new().gc_initial_threshold(0));
+    vm.interpret("var i = 0; while (i < 200) { var s = \"x\" + i; i = i + 1; }")
+        .unwrap();
+    let before = vm.gc_stats().collections;
+    assert!(before > 0);
+    let stats = vm.collect_garbage();
+    assert_eq!(stats.collections, before + 1);
+    assert_eq!(stats, vm.gc_stats());
+    assert!(vm.heap.len() < 200);
+}
+
+#[test]
+fn each_vm_counts_only_its_own_allocations() {
+    let (mut a, mut b) = (VM::new(), VM::new());
+    b.interpret("var kept = \"b\";").unwrap();
+    let held = b.heap.bytes();
+    a.interpret("var s = \"\"; var i = 0; while (i < 100) { s = s + \"x\"; i = i + 1; }")
+        .unwrap();
+    assert_eq!(b.heap.bytes(), held);
+    assert!(a.heap.bytes() > held);
+    a.interpret("s = nil;").unwrap();
+    let before = a.heap.bytes();
+    a.collect_garbage();
+    assert!(a.heap.bytes() < before);
+}
+
+#[test]
+fn concatenation_reuses_the_buffers_of_collected_strings() {
+    let mut vm = VM::with_config(
+        VmConfig::new()
+            .gc_initial_threshold(0)
+            .gc_growth_factor(1.0),
+    );
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    vm.interpret("var i = 0; var s; while (i < 500) { s = \"item \" + i; i = i + 1; } print s;")
+        .unwrap();
+    let stats = vm.heap.pool_stats();
+    assert!(stats.reused > 0);
+    assert!(stats.fresh < 500);
+    assert_eq!(output.contents(), "item 499\n");
+}
+
+#[test]
+fn a_stale_handle_is_refused_once_its_slot_is_reused() {
+    let mut vm = VM::new();
+    let stale = create_string(&mut vm, "collected");
+    // held only by the host, which the collector can't see
+    vm.collect_garbage();
+    assert!(vm.heap.try_get(stale).is_none());
+    let fresh = create_string(&mut vm, "fresh");
+    assert_eq!(fresh.index(), stale.index());
+    assert_ne!(fresh, stale);
+    assert!(vm.heap.try_get(stale).is_none());
+    assert_eq!(vm.heap.get(fresh), "fresh");
+}
+
+// the whole suite runs this way with `--features gc_stress`
+#[cfg(feature = "gc_stress")]
+#[test]
+fn the_gc_stress_feature_collects_before_every_allocation() {
+    assert!(VmConfig::default().gc_stress);
+    let mut vm = VM::new();
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    let before = vm.gc_stats().collections;
+    // every concatenation makes a new string
+    vm.interpret("var s = \"a\"; for (var i = 0; i < 20; i = i + 1) s = s + \"b\"; print s;")
+        .unwrap();
+    assert!(vm.gc_stats().collections - before >= 20);
+    assert_eq!(output.contents(), format!("a{}\n", "b".repeat(20)));
+}
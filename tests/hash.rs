@@ -0,0 +1,15 @@
+use std::hash::BuildHasher;
+
+use lox_byte::hash::FxBuildHasher;
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn fx_hashes_are_the_same_on_every_run(a in ".*", b in ".*") {
+        let hash = |text: &str| FxBuildHasher::default().hash_one(text);
+        prop_assert_eq!(hash(&a), hash(&a));
+        if a != b {
+            prop_assert_ne!(hash(&a), hash(&b));
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use lox_byte::highlight::{self, TokenClass};
+use proptest::prelude::*;
+
+proptest! {
+    // the classified spans run in order without overlapping, and what they
+    // leave out is only whitespace
+    #[test]
+    fn classification_covers_everything_but_whitespace(source in "\\PC{0,80}") {
+        prop_assume!(!source.starts_with("#!"));
+        let mut end = 0;
+        for (span, _) in highlight::classify(&source) {
+            prop_assert!(span.start >= end);
+            prop_assert!(source[end..span.start].trim().is_empty());
+            end = span.start + span.len;
+        }
+        prop_assert!(source[end..].trim().is_empty());
+    }
+}
+
+#[test]
+fn tokens_are_classified_for_highlighting() {
+    let source = "var s = \"a\"; // note\nif (!s) print 0x1f;";
+    let classes: Vec<_> = highlight::classify(source)
+        .into_iter()
+        .map(|(span, class)| (&source[span.range()], class))
+        .collect();
+    use TokenClass::*;
+    assert_eq!(
+        classes,
+        [
+            ("var", Keyword),
+            ("s", Identifier),
+            ("=", Operator),
+            ("\"a\"", String),
+            (";", Operator),
+            ("// note", Comment),
+            ("if", Keyword),
+            ("(", Operator),
+            ("!", Operator),
+            ("s", Identifier),
+            (")", Operator),
+            ("print", Keyword),
+            ("0x1f", Number),
+            (";", Operator),
+        ]
+    );
+}
@@ -0,0 +1,112 @@
+// runs every program under tests/lox and checks it against the expectations
+// written in its comments, in the style of the Crafting Interpreters suite:
+//
+//     print 1 + 2; // expect: 3
+//     print nil + 1; // expect runtime error: Cannot add nil and 1
+//     var = 1; // Error at '=': Expect variable name
+//
+// a compile error may name another line with `// [line N] Error ...`.
+// LOX_TEST=<substring> only runs the files whose path contains it
+mod common;
+
+use std::{env, fs, path::Path};
+
+use lox_byte::{output::Sink, VmErrors, VM};
+
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    output: Vec<String>,
+    // (line, message) pairs
+    compile_errors: Vec<(usize, String)>,
+    runtime_error: Option<(usize, String)>,
+}
+
+fn expectations(source: &str) -> Outcome {
+    let mut expected = Outcome::default();
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let Some((_, comment)) = line.split_once("//") else {
+            continue;
+        };
+        let comment = comment.trim();
+        if let Some(text) = comment.strip_prefix("expect: ") {
+            expected.output.push(text.to_string());
+        } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+            expected.runtime_error = Some((number, message.to_string()));
+        } else if comment.starts_with("Error") {
+            expected.compile_errors.push((number, comment.to_string()));
+        } else if let Some(rest) = comment.strip_prefix("[line ") {
+            if let Some((line, error)) = rest.split_once("] ") {
+                let line = line.parse().expect("bad [line N] expectation");
+                expected.compile_errors.push((line, error.to_string()));
+            }
+        }
+    }
+    expected
+}
+
+fn run(path: &Path, source: &str) -> Outcome {
+    let mut vm = VM::new();
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    vm.diagnostics = Sink::capture().0;
+    vm.set_script_path(&path.display().to_string());
+    let mut outcome = Outcome::default();
+    match vm.interpret(source) {
+        Ok(()) => {}
+        Err(VmErrors::CompileError(errors)) => {
+            outcome.compile_errors = errors
+                .iter()
+                .map(|error| {
+                    // "[line l:c] Error at 'x': msg" without the position
+                    let text = error.to_string();
+                    let message = text.split_once("] ").map_or(text.as_str(), |(_, m)| m);
+                    (error.span.line, message.to_string())
+                })
+                .collect()
+        }
+        Err(VmErrors::RuntimeError(error)) => {
+            outcome.runtime_error = Some((vm.error_line.unwrap_or_default(), error.to_string()))
+        }
+    }
+    outcome.output = output.contents().lines().map(str::to_string).collect();
+    outcome
+}
+
+#[test]
+fn lox_programs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let filter = env::var("LOX_TEST").unwrap_or_default();
+    let files = common::lox_files(&root);
+
+    let mut failures = Vec::new();
+    let mut ran = 0;
+    for path in files {
+        let name = path
+            .strip_prefix(&root)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        if !name.contains(&filter) {
+            continue;
+        }
+        let source = fs::read_to_string(&path).expect("Failed to read test file");
+        let expected = expectations(&source);
+        let actual = run(&path, &source);
+        ran += 1;
+        if actual != expected {
+            failures.push(format!(
+                "{}\n  expected: {:?}\n  actual:   {:?}",
+                name, expected, actual
+            ));
+        }
+    }
+    assert!(ran > 0, "no lox tests matched '{}'", filter);
+    assert!(
+        failures.is_empty(),
+        "{} of {} lox tests failed:\n{}",
+        failures.len(),
+        ran,
+        failures.join("\n")
+    );
+}
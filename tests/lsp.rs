@@ -0,0 +1,138 @@
+use std::io::Cursor;
+
+use lox_byte::{json::Json, lsp};
+
+// frames each message as a client would, returning the exit code and every
+// message the server wrote back
+fn session(messages: &[&str]) -> (i32, Vec<Json>) {
+    let input: String = messages
+        .iter()
+        .map(|body| format!("Content-Length: {}\r\n\r\n{}", body.len(), body))
+        .collect();
+    let mut output = Vec::new();
+    let code = lsp::serve(Cursor::new(input), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let replies = output
+        .split("Content-Length: ")
+        .filter(|part| !part.is_empty())
+        .map(|part| Json::parse(part.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    (code, replies)
+}
+
+fn open(text: &str) -> String {
+    let document = Json::object([("uri", "untitled:a.lox".into()), ("text", text.into())]);
+    let params = Json::object([("textDocument", document)]);
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/didOpen".into()),
+        ("params", params),
+    ])
+    .to_string()
+}
+
+fn at(id: usize, method: &str, line: usize, character: usize) -> String {
+    let params = Json::object([
+        (
+            "textDocument",
+            Json::object([("uri", "untitled:a.lox".into())]),
+        ),
+        (
+            "position",
+            Json::object([("line", line.into()), ("character", character.into())]),
+        ),
+    ]);
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+    .to_string()
+}
+
+const SHUTDOWN: &str = r#"{"jsonrpc":"2.0","id":99,"method":"shutdown"}"#;
+const EXIT: &str = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+
+#[test]
+fn diagnostics_are_published_for_each_version_of_a_document() {
+    let (code, replies) = session(&[
+        &open("var a = ;\nprint 1 < 2 < 3;"),
+        &open("var a = 1;"),
+        EXIT,
+    ]);
+    assert_eq!(code, 1, "exit without shutdown");
+    let diagnostics = |reply: &Json| reply.get("params").get("diagnostics").as_array().to_vec();
+    let first = diagnostics(&replies[0]);
+    assert_eq!(first.len(), 2);
+    assert_eq!(first[0].get("code").as_str(), Some("E0005"));
+    assert_eq!(first[1].get("code").as_str(), Some("E0020"));
+    let start = first[1].get("range").get("start");
+    assert_eq!(
+        (
+            start.get("line").as_usize(),
+            start.get("character").as_usize()
+        ),
+        (Some(1), Some(12))
+    );
+    assert!(diagnostics(&replies[1]).is_empty());
+}
+
+#[test]
+fn definitions_hovers_and_symbols_follow_scopes() {
+    let source = "var n = \"é😀\";\n{ var n = 1; { var m = n; print m + n; } }\nprint n + clock();";
+    let (code, replies) = session(&[
+        &open(source),
+        // the inner `n` resolves to the local, the last one to the global
+        &at(1, "textDocument/definition", 1, 23),
+        &at(2, "textDocument/definition", 2, 6),
+        &at(3, "textDocument/hover", 1, 19),
+        &at(4, "textDocument/hover", 2, 6),
+        &at(5, "textDocument/hover", 2, 12),
+        &at(6, "textDocument/documentSymbol", 0, 0),
+        SHUTDOWN,
+        EXIT,
+    ]);
+    assert_eq!(code, 0);
+    let result = |id: usize| {
+        replies
+            .iter()
+            .find(|r| r.get("id").as_usize() == Some(id))
+            .unwrap()
+            .get("result")
+    };
+    let start = |location: &Json| {
+        let start = location.get("range").get("start");
+        (
+            start.get("line").as_usize().unwrap(),
+            start.get("character").as_usize().unwrap(),
+        )
+    };
+    assert_eq!(start(result(1)), (1, 6));
+    assert_eq!(start(result(2)), (0, 4));
+    let hover = |id: usize| {
+        result(id)
+            .get("contents")
+            .get("value")
+            .as_str()
+            .unwrap()
+            .to_owned()
+    };
+    assert_eq!(hover(3), "local `m` in slot 1");
+    assert_eq!(hover(4), "global `n`");
+    assert_eq!(hover(5), "native fn `clock`, arity 0");
+    let symbols = result(6).as_array();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].get("name").as_str(), Some("n"));
+}
+
+#[test]
+fn json_round_trips_through_its_text() {
+    let text = r#"{"a":[1,-2.5,1e3,true,null],"b":"q\"\\\né😀","c":{}}"#;
+    let value = Json::parse(text).unwrap();
+    assert_eq!(value.get("b").as_str(), Some("q\"\\\né😀"));
+    assert_eq!(value.get("a").as_array()[2], Json::Number(1000.0));
+    assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+    assert!(Json::parse("[1,").is_err());
+    assert!(Json::parse("{} x").is_err());
+}
@@ -0,0 +1,56 @@
+use lox_byte::{error::ManifestErrors, manifest::Manifest, Capability, VmConfig};
+
+#[test]
+fn manifests_configure_a_project_relative_to_their_directory() {
+    let root = std::path::Path::new("/project");
+    let text = r#"
+        # comments may follow anything
+        entry = "src/main.lox" # the script
+        module_dirs = ["lib", "vendor/#x"]
+        natives = ["io", "time"]
+        modern = true
+
+        [limits]
+        max_heap_bytes = 16_000_000
+        fuel = 1000
+    "#;
+    let manifest = Manifest::parse(text, root).unwrap();
+    assert_eq!(manifest.entry, Some(root.join("src/main.lox")));
+    let config = manifest.config;
+    assert_eq!(
+        config.module_dirs,
+        [root.join("lib"), root.join("vendor/#x")]
+    );
+    assert_eq!(config.capabilities, [Capability::Io, Capability::Time]);
+    assert!(config.modern && !config.chain_comparisons);
+    assert_eq!(config.max_heap_bytes, Some(16_000_000));
+    assert_eq!(config.fuel, Some(1000));
+    assert_eq!(config.max_nesting, VmConfig::default().max_nesting);
+
+    let error = |text: &str| Manifest::parse(text, root).unwrap_err();
+    assert!(matches!(
+        error("entry = 1"),
+        ManifestErrors::InvalidValue(1, _, _)
+    ));
+    assert!(matches!(
+        error("natives = [\"net\"]"),
+        ManifestErrors::InvalidValue(1, _, _)
+    ));
+    assert!(matches!(
+        error("\nfuel = 1"),
+        ManifestErrors::UnknownKey(2, _)
+    ));
+    assert!(matches!(
+        error("[limits]\nfuel = 1\nfuel = 2"),
+        ManifestErrors::DuplicateKey(3, _)
+    ));
+    assert!(matches!(error("entry = \"a"), ManifestErrors::Syntax(1, _)));
+    assert!(matches!(
+        error("module_dirs = [\"a\" \"b\"]"),
+        ManifestErrors::Syntax(1, _)
+    ));
+    assert!(matches!(
+        error("[limits]\nfuel = 1_"),
+        ManifestErrors::Syntax(2, _)
+    ));
+}
@@ -0,0 +1,122 @@
+// imports served by a resolver the embedder sets rather than from files
+use std::sync::{Arc, Mutex};
+
+use lox_byte::{
+    module::{MemoryResolver, ModuleResolver},
+    output::{CaptureBuffer, Sink},
+    CompileErrors, ModuleErrors, VmErrors, VM,
+};
+
+fn capturing() -> (VM, CaptureBuffer) {
+    let buffer = CaptureBuffer::default();
+    let mut vm = VM::new();
+    vm.out = Sink::new(buffer.clone());
+    (vm, buffer)
+}
+
+// imports are resolved as the importing script compiles
+fn import_error(vm: &mut VM, source: &str) -> ModuleErrors {
+    match vm.interpret(source) {
+        Err(VmErrors::CompileError(mut errors)) => match errors.remove(0).error {
+            CompileErrors::Import(error) => *error,
+            other => panic!("{:?}", other),
+        },
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn modules_are_imported_from_memory() {
+    let (mut vm, buffer) = capturing();
+    vm.set_module_resolver(
+        MemoryResolver::new()
+            .module(
+                "config",
+                "import \"defaults\"; var name = defaults.name + \"!\";",
+            )
+            .module("defaults", "var name = \"lox\"; print \"defaults ran\";"),
+    );
+    vm.interpret("import \"config\"; import \"defaults\"; print config.name; print defaults.name;")
+        .unwrap();
+    // both imports of defaults share one instance, run once
+    assert_eq!(buffer.contents(), "defaults ran\nlox!\nlox\n");
+}
+
+#[test]
+fn a_module_the_resolver_lacks_is_not_found() {
+    let (mut vm, _) = capturing();
+    vm.set_module_resolver(MemoryResolver::new().module("there", "var a = 1;"));
+    let ModuleErrors::NotFound(path, reason) = import_error(&mut vm, "import \"missing\";") else {
+        panic!("found");
+    };
+    assert_eq!(path, "missing");
+    assert_eq!(reason, "no such module");
+    // the vm carries on, importing what is there
+    vm.interpret("import \"there\"; print there.a;").unwrap();
+}
+
+#[test]
+fn a_module_that_fails_to_compile_names_its_path() {
+    let (mut vm, _) = capturing();
+    vm.set_module_resolver(MemoryResolver::new().module("broken", "var = 1;"));
+    let ModuleErrors::Compile(path, errors) = import_error(&mut vm, "import \"broken\";") else {
+        panic!("compiled");
+    };
+    assert_eq!(path, "broken");
+    assert_eq!(errors.len(), 1);
+}
+
+// resolves every path under a prefix and records what it was asked for
+struct Recording {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl ModuleResolver for Recording {
+    fn locate(&mut self, path: &str, importer: Option<&str>) -> Result<String, String> {
+        let call = format!("locate {} from {}", path, importer.unwrap_or("script"));
+        self.calls.lock().unwrap().push(call);
+        match path {
+            "gone" => Err("deleted".into()),
+            _ => Ok(format!("db/{}", path)),
+        }
+    }
+
+    fn load(&mut self, key: &str) -> Result<String, String> {
+        self.calls.lock().unwrap().push(format!("load {}", key));
+        match key {
+            "db/inner" => Ok("var value = 2;".into()),
+            "db/outer" => Ok("import \"inner\"; var value = inner.value * 3;".into()),
+            _ => Err("unreadable".into()),
+        }
+    }
+}
+
+#[test]
+fn a_custom_resolver_is_asked_for_keys_and_sources() {
+    let (mut vm, buffer) = capturing();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    vm.set_module_resolver(Recording {
+        calls: calls.clone(),
+    });
+    vm.interpret("import \"outer\"; print outer.value;")
+        .unwrap();
+    assert_eq!(buffer.contents(), "6\n");
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "locate outer from script",
+            "load db/outer",
+            "locate inner from db/outer",
+            "load db/inner",
+        ]
+    );
+
+    let ModuleErrors::NotFound(_, reason) = import_error(&mut vm, "import \"gone\";") else {
+        panic!("found");
+    };
+    assert_eq!(reason, "deleted");
+    let ModuleErrors::NotFound(_, reason) = import_error(&mut vm, "import \"other\";") else {
+        panic!("found");
+    };
+    assert_eq!(reason, "unreadable");
+}
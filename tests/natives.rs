@@ -0,0 +1,19 @@
+// natives that read what the host gave the vm, the rest are covered by
+// the programs under tests/lox
+use lox_byte::{
+    output::{CaptureBuffer, Sink},
+    VM,
+};
+
+#[test]
+fn scripts_read_their_arguments_with_args() {
+    let buffer = CaptureBuffer::default();
+    let mut vm = VM::new();
+    vm.out = Sink::new(buffer.clone());
+    vm.script_args = vec!["a".into(), "-x".into()];
+    vm.interpret("for (var i = 0; i <= args(); i = i + 1) print args(i);")
+        .unwrap();
+    assert_eq!(buffer.contents(), "a\n-x\nnil\n");
+    assert!(vm.interpret("args(-1.5);").is_err());
+    assert!(vm.interpret("args(0, 1);").is_err());
+}
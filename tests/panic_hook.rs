@@ -0,0 +1,40 @@
+use lox_byte::{output::Sink, panic_hook, value::create_string, VmConfig, VmErrors, VM};
+
+#[test]
+fn panics_inside_the_vm_become_internal_errors() {
+    panic_hook::install();
+    let mut vm = VM::new();
+    let (out, buffer) = Sink::capture();
+    vm.out = out;
+    vm.define_native("broken", 0, |_, _| panic!("broken native"));
+    let error = vm.interpret("print 1;\nbroken();").unwrap_err();
+    let VmErrors::RuntimeError(error) = error else {
+        panic!("{}", error);
+    };
+    assert_eq!(error.code(), "E0121");
+    let message = error.to_string();
+    assert!(message.contains("in Op_Call at offset"), "{}", message);
+    assert!(
+        message.contains("broken native at tests/panic_hook.rs:"),
+        "{}",
+        message
+    );
+    assert_eq!(vm.error_line, Some(2));
+    // the vm keeps working
+    vm.interpret("print 2;").unwrap();
+    assert_eq!(buffer.contents(), "1\n2\n");
+}
+
+#[test]
+fn a_caught_panic_leaves_host_allocations_uncollected() {
+    panic_hook::install();
+    let mut vm = VM::with_config(VmConfig::new().gc_stress());
+    vm.define_native("broken", 0, |_, _| panic!("broken native"));
+    assert!(vm.interpret("broken();").is_err());
+    assert_eq!(vm.running, 0);
+    let collections = vm.gc_stats.collections;
+    let held = create_string(&mut vm, "held by the host");
+    create_string(&mut vm, "another");
+    assert_eq!(vm.gc_stats.collections, collections);
+    assert_eq!(vm.heap.get(held), "held by the host");
+}
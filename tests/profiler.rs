@@ -0,0 +1,48 @@
+// op counts and the --time report
+use lox_byte::{
+    opcode::OpCode,
+    output::Sink,
+    profiler::{time_report, Profile},
+    VM,
+};
+
+#[test]
+fn op_counts_agree_with_a_profile() {
+    let mut vm = VM::new();
+    vm.out = Sink::new(std::io::sink());
+    vm.op_counts = Some(Box::default());
+    vm.profile = Some(Profile::default());
+    vm.interpret("var a = 0; while (a < 3) a = a + 1; print a;")
+        .unwrap();
+    let op_counts = vm.op_counts.take().unwrap();
+    let profile = vm.profile.take().unwrap();
+    assert_eq!(op_counts.total(), profile.instructions);
+    for (count, byte) in op_counts.sorted() {
+        assert_eq!(profile.opcodes[&byte], count);
+    }
+    assert_eq!(op_counts.sorted().len(), profile.opcodes.len());
+    assert_eq!(op_counts.counts[u8::from(OpCode::Less) as usize], 4);
+}
+
+#[test]
+fn runs_record_their_peak_stack_and_heap() {
+    let mut vm = VM::new();
+    vm.out = Sink::new(std::io::sink());
+    vm.interpret("var a = 1; { var b = 2; print a + (b + (a + b)); }")
+        .unwrap();
+    // b, then four operands
+    assert_eq!(vm.peak_stack, 5);
+    let before = vm.heap.bytes();
+    vm.interpret("var s = \"x\"; s = s + s + s + s; s = nil;")
+        .unwrap();
+    vm.collect_garbage();
+    assert!(vm.heap.peak_bytes() > before);
+    assert!(vm.heap.peak_bytes() >= vm.heap.bytes());
+    let report = time_report(&vm, std::time::Duration::ZERO, std::time::Duration::ZERO);
+    assert!(
+        report.contains("peak stack            5 slots"),
+        "{}",
+        report
+    );
+    assert!(report.contains(&format!("{} bytes", vm.heap.peak_bytes())));
+}
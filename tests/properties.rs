@@ -0,0 +1,312 @@
+// conversions between the vm's types and their encodings must round-trip,
+// and decoding arbitrary input or compiling malformed source must fail
+// cleanly rather than panic
+use lox_byte::{
+    bind::FromLox,
+    bytecode,
+    error::CompileErrors,
+    opcode::OpCode,
+    output::Sink,
+    parser::Precedence,
+    value::{create_string, LoxValue, Value},
+    RuntimeErrors, VmConfig, VmErrors, VM,
+};
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn opcode_bytes_round_trip(byte in any::<u8>()) {
+        match OpCode::try_from(byte) {
+            Ok(op) => prop_assert_eq!(u8::from(op), byte),
+            Err(_) => prop_assert!(byte as usize >= OpCode::ALL.len()),
+        }
+    }
+
+    #[test]
+    fn precedence_round_trips(value in any::<usize>()) {
+        match Precedence::try_from(value) {
+            Ok(precedence) => prop_assert_eq!(usize::from(precedence), value),
+            Err(_) => prop_assert!(value >= Precedence::ALL.len()),
+        }
+    }
+
+    #[test]
+    fn numbers_round_trip(n in any::<f64>()) {
+        let vm = VM::new();
+        let back = f64::from_lox(&Value::from(n), &vm.heap).unwrap();
+        prop_assert!(back == n || (back.is_nan() && n.is_nan()));
+        prop_assert!(bool::from_lox(&Value::from(n), &vm.heap).is_err());
+    }
+
+    #[test]
+    fn bools_round_trip(b in any::<bool>()) {
+        let vm = VM::new();
+        prop_assert_eq!(bool::from_lox(&Value::from(b), &vm.heap).unwrap(), b);
+        prop_assert!(f64::from_lox(&Value::from(b), &vm.heap).is_err());
+        prop_assert!(String::from_lox(&Value::from(b), &vm.heap).is_err());
+    }
+
+    #[test]
+    fn strings_round_trip(text in any::<String>()) {
+        let mut vm = VM::new();
+        let value: Value = create_string(&mut vm, &text).into();
+        prop_assert_eq!(String::from_lox(&value, &vm.heap).unwrap(), text.clone());
+        prop_assert!(f64::from_lox(&value, &vm.heap).is_err());
+    }
+
+    #[test]
+    fn lox_values_round_trip_through_the_vm(value in lox_value()) {
+        let mut vm = VM::new();
+        let back = LoxValue::from_value(&value.clone().into_value(&mut vm), &vm.heap);
+        match (&value, &back) {
+            (LoxValue::Number(a), LoxValue::Number(b)) if a.is_nan() => prop_assert!(b.is_nan()),
+            _ => prop_assert_eq!(back, value),
+        }
+    }
+
+    #[test]
+    fn lox_value_conversions_match_their_type(value in lox_value()) {
+        prop_assert_eq!(f64::try_from(value.clone()).is_ok(), value.type_name() == "number");
+        prop_assert_eq!(bool::try_from(value.clone()).is_ok(), value.type_name() == "bool");
+        prop_assert_eq!(String::try_from(value.clone()).is_ok(), value.type_name() == "string");
+    }
+
+    // a valid file with one byte changed and the tail cut off somewhere
+    #[test]
+    fn deserializing_damaged_bytecode_never_panics(
+        index in any::<prop::sample::Index>(),
+        byte in any::<u8>(),
+        cut in any::<prop::sample::Index>(),
+    ) {
+        let mut vm = VM::new();
+        vm.compile("var a = 1; { var b = \"two\"; print a + b; } while (a < 3) a = a + 1;")
+            .unwrap();
+        let mut bytes = bytecode::serialize(&vm.chunks, &vm.heap).unwrap();
+        let at = index.index(bytes.len());
+        bytes[at] = byte;
+        bytes.truncate(cut.index(bytes.len()) + 1);
+        let _ = bytecode::deserialize(&mut vm, &bytes);
+    }
+}
+
+fn lox_value() -> impl Strategy<Value = LoxValue> {
+    prop_oneof![
+        Just(LoxValue::Nil),
+        any::<bool>().prop_map(LoxValue::from),
+        any::<f64>().prop_map(LoxValue::from),
+        any::<i32>().prop_map(LoxValue::from),
+        any::<String>().prop_map(LoxValue::from),
+    ]
+}
+
+#[test]
+fn every_opcode_is_listed_at_its_discriminant() {
+    for (index, op) in OpCode::ALL.iter().enumerate() {
+        assert_eq!(u8::from(*op) as usize, index, "{}", op);
+    }
+    for (index, precedence) in Precedence::ALL.iter().enumerate() {
+        assert_eq!(usize::from(*precedence), index, "{:?}", precedence);
+    }
+}
+
+// malformed programs built from real tokens must come back as compile
+// errors, never as a panic inside the parser
+const FRAGMENTS: &[&str] = &[
+    "var",
+    "print",
+    "if",
+    "else",
+    "while",
+    "for",
+    "try",
+    "catch",
+    "throw",
+    "import",
+    "and",
+    "or",
+    "class",
+    "fun",
+    "return",
+    "this",
+    "super",
+    "nil",
+    "true",
+    "false",
+    "x",
+    "y",
+    "1",
+    "0x1f",
+    "2.5",
+    "\"s\"",
+    "\"lib.lox\"",
+    "(",
+    ")",
+    "{",
+    "}",
+    ",",
+    ".",
+    "-",
+    "+",
+    ";",
+    "/",
+    "*",
+    "!",
+    "!=",
+    "=",
+    "==",
+    ">",
+    ">=",
+    "<",
+    "<=",
+];
+
+proptest! {
+    #[test]
+    fn compiling_token_soup_never_panics(
+        tokens in prop::collection::vec(prop::sample::select(FRAGMENTS), 0..24)
+    ) {
+        let source = tokens.join(" ");
+        let mut vm = VM::new();
+        let _ = vm.compile(&source);
+    }
+
+    // whatever compiles runs to an end the vm meant, never a panic caught
+    // as an internal error
+    #[test]
+    fn running_token_soup_never_panics(
+        tokens in prop::collection::vec(prop::sample::select(FRAGMENTS), 0..24)
+    ) {
+        let source = tokens.join(" ");
+        let mut vm = VM::with_config(VmConfig::new().fuel(10_000));
+        vm.out = Sink::new(std::io::sink());
+        if vm.compile(&source).is_ok() {
+            let result = vm.run();
+            prop_assert!(
+                !matches!(result, Err(VmErrors::RuntimeError(RuntimeErrors::Internal(..)))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn compiling_arbitrary_text_never_panics(source in any::<String>()) {
+        let mut vm = VM::new();
+        let _ = vm.compile(&source);
+    }
+}
+
+// letters only, digits aren't needed to make a few hundred names
+fn name(mut index: usize) -> String {
+    let mut name = String::from("v");
+    loop {
+        name.push((b'a' + (index % 26) as u8) as char);
+        index /= 26;
+        if index == 0 {
+            return name;
+        }
+    }
+}
+
+#[test]
+fn reading_a_global_past_the_constant_limit_is_a_compile_error() {
+    let source = (0..300)
+        .map(|index| format!("{};", name(index)))
+        .collect::<String>();
+    let mut vm = VM::new();
+    let Err(VmErrors::CompileError(errors)) = vm.compile(&source) else {
+        panic!("expected a compile error");
+    };
+    assert!(errors
+        .iter()
+        .any(|error| matches!(error.error, CompileErrors::TooManyConstants)));
+}
+
+#[test]
+fn malformed_programs_found_by_fuzzing_report_errors() {
+    for source in [
+        "try and ==",
+        "{ try",
+        "try { } catch (",
+        "print -",
+        "import",
+    ] {
+        let mut vm = VM::new();
+        assert!(
+            matches!(vm.compile(source), Err(VmErrors::CompileError(_))),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn reading_a_local_in_its_own_initializer_is_a_compile_error() {
+    for source in ["{ var a = a; }", "{ var a = 1; { var a = a; } }"] {
+        let mut vm = VM::new();
+        let Err(VmErrors::CompileError(errors)) = vm.interpret(source) else {
+            panic!("{}", source);
+        };
+        assert!(matches!(
+            errors.as_slice(),
+            [error] if matches!(error.error, CompileErrors::UninitializedLocal)
+        ));
+    }
+}
+
+#[test]
+fn debug_info_spans_point_at_operators_and_survive_serialization() {
+    let source = "var a = 1;\nprint a + nil;";
+    let mut vm = VM::with_config(VmConfig::new().debug_info());
+    vm.compile(source).unwrap();
+    let bytes = bytecode::serialize(&vm.chunks, &vm.heap).unwrap();
+    let chunk = bytecode::deserialize(&mut vm, &bytes).unwrap();
+    assert_eq!(chunk.spans, vm.chunks.spans);
+    let mut add = 0;
+    while chunk.code[add] != u8::from(OpCode::Addition) {
+        add += 1 + OpCode::try_from(chunk.code[add]).unwrap().operand().size();
+    }
+    assert_eq!(chunk.span_at(add).map(|span| &source[span]), Some("+"));
+
+    let mut plain = VM::new();
+    plain.compile(source).unwrap();
+    assert!(plain.chunks.spans.is_empty());
+}
+
+#[test]
+fn stripped_chunks_run_without_knowing_their_lines() {
+    let mut vm = VM::new();
+    vm.compile("var a = 1;\nprint a + nil;").unwrap();
+    vm.chunks.strip_debug_info();
+    let bytes = bytecode::serialize(&vm.chunks, &vm.heap).unwrap();
+    vm.chunks = bytecode::deserialize(&mut vm, &bytes).unwrap();
+    assert!(vm.chunks.lines.is_empty());
+    assert!(matches!(vm.run(), Err(VmErrors::RuntimeError(_))));
+    assert_eq!(vm.error_line, None);
+}
+
+proptest! {
+    // nesting far past the limit, whatever it is made of, must not crash
+    #[test]
+    fn random_deep_nesting_compiles_or_fails_cleanly(
+        openers in prop::collection::vec(prop::sample::select(vec!["(", "-", "!", "{", "if (1) "]), 300..1200),
+        tail in "[();{}1+]{0,16}",
+    ) {
+        let mut vm = VM::new();
+        let _ = vm.compile(&format!("{}{}", openers.concat(), tail));
+    }
+}
+
+proptest! {
+    // `vm.alloc` skips the intern table, like strings that will arrive
+    // from outside it
+    #[test]
+    fn strings_compare_by_content(a in ".{0,8}", b in ".{0,8}") {
+        let mut vm = VM::new();
+        let interned: Value = create_string(&mut vm, &a).into();
+        let allocated = Value::String(vm.alloc(a.clone()));
+        let other = Value::String(vm.alloc(b.clone()));
+        prop_assert!(interned.equals(&allocated, &vm.heap));
+        prop_assert_eq!(interned.equals(&other, &vm.heap), a == b);
+    }
+}
@@ -0,0 +1,684 @@
+// a deliberately naive tree-walking evaluator for the subset of lox the
+// differential test covers. It shares only the scanner with the crate, every
+// other decision is made again from the source so the compiler and vm can be
+// checked against it
+use std::{cmp::Ordering, collections::HashMap, fmt};
+
+use lox_byte::{
+    scanner::Scanner,
+    token::{TType, Token},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+    Nil,
+    Native(&'static str),
+}
+
+impl Value {
+    fn is_falsy(&self) -> bool {
+        matches!(self, Self::Nil | Self::Bool(false))
+    }
+
+    fn number(&self) -> Result<f64, Unwind> {
+        match self {
+            Self::Number(n) => Ok(*n),
+            other => Err(Unwind::Error(format!(
+                "Expected a number, but found value {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Str(s) => write!(f, "{s}"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Nil => write!(f, "nil"),
+            Self::Native(name) => write!(f, "<native fn {name}>"),
+        }
+    }
+}
+
+// how a program ended
+#[derive(Debug, PartialEq)]
+pub enum Ending {
+    Finished,
+    CompileError,
+    RuntimeError(String),
+    // uses something the evaluator does not model, nothing to compare
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Variable(String),
+    Assign(String, Box<Expr>),
+    Unary(TType, Box<Expr>),
+    Binary(Box<Expr>, TType, Box<Expr>),
+    Logical(Box<Expr>, TType, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    // the block's statements, then the final expression if it has one
+    Do(Vec<Stmt>, Option<Box<Expr>>),
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Print(Expr),
+    Expression(Expr),
+    Var(String, Option<Expr>),
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    Try(Vec<Stmt>, String, Vec<Stmt>),
+    Throw(Expr),
+}
+
+enum Failure {
+    Syntax,
+    Unsupported(String),
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    current: usize,
+    // names declared in each open block, for the duplicate local check, and
+    // whether each has its value yet, a local read in its own initializer
+    // being a compile error
+    scopes: Vec<Vec<(&'a str, bool)>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> TType {
+        self.tokens
+            .get(self.current)
+            .map_or(TType::Eof, |t| t.ttype)
+    }
+
+    fn advance(&mut self) -> &Token<'a> {
+        if self.current < self.tokens.len() - 1 {
+            self.current += 1;
+        }
+        &self.tokens[self.current - 1]
+    }
+
+    fn check(&mut self, ttype: TType) -> bool {
+        if self.peek() == ttype {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, ttype: TType) -> Result<&'a str, Failure> {
+        if self.peek() != ttype {
+            return Err(Failure::Syntax);
+        }
+        Ok(self.advance().lexeme.unwrap_or_default())
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, Failure> {
+        match self.peek() {
+            TType::Var => {
+                self.advance();
+                let name = self.expect(TType::Identifer)?;
+                if let Some(scope) = self.scopes.last_mut() {
+                    if scope.iter().any(|&(declared, _)| declared == name) {
+                        return Err(Failure::Syntax);
+                    }
+                    scope.push((name, false));
+                }
+                let init = match self.check(TType::Equal) {
+                    true => Some(self.expression()?),
+                    false => None,
+                };
+                if let Some(local) = self.scopes.last_mut().and_then(|scope| scope.last_mut()) {
+                    local.1 = true;
+                }
+                self.expect(TType::SemiColon)?;
+                Ok(Stmt::Var(name.into(), init))
+            }
+            TType::Import => Err(Failure::Unsupported("import".into())),
+            _ => self.statement(),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, Failure> {
+        let ttype = self.peek();
+        match ttype {
+            TType::Print | TType::Throw => {
+                self.advance();
+                let value = self.expression()?;
+                self.expect(TType::SemiColon)?;
+                Ok(match ttype {
+                    TType::Print => Stmt::Print(value),
+                    _ => Stmt::Throw(value),
+                })
+            }
+            TType::LeftBrace => {
+                self.advance();
+                Ok(Stmt::Block(self.block()?))
+            }
+            TType::If => {
+                self.advance();
+                self.expect(TType::LeftParen)?;
+                let condition = self.expression()?;
+                self.expect(TType::RightParen)?;
+                let then = Box::new(self.statement()?);
+                let otherwise = match self.check(TType::Else) {
+                    true => Some(Box::new(self.statement()?)),
+                    false => None,
+                };
+                Ok(Stmt::If(condition, then, otherwise))
+            }
+            TType::While => {
+                self.advance();
+                self.expect(TType::LeftParen)?;
+                let condition = self.expression()?;
+                self.expect(TType::RightParen)?;
+                Ok(Stmt::While(condition, Box::new(self.statement()?)))
+            }
+            TType::For => {
+                self.advance();
+                self.for_statement()
+            }
+            TType::Try => {
+                self.advance();
+                self.expect(TType::LeftBrace)?;
+                let body = self.block()?;
+                self.expect(TType::Catch)?;
+                self.expect(TType::LeftParen)?;
+                let name = self.expect(TType::Identifer)?;
+                self.expect(TType::RightParen)?;
+                self.expect(TType::LeftBrace)?;
+                self.scopes.push(vec![(name, true)]);
+                let handler = self.block_body()?;
+                self.scopes.pop();
+                Ok(Stmt::Try(body, name.into(), handler))
+            }
+            _ => {
+                let expr = self.expression()?;
+                self.expect(TType::SemiColon)?;
+                Ok(Stmt::Expression(expr))
+            }
+        }
+    }
+
+    // `for` is sugar for a block around a while loop
+    fn for_statement(&mut self) -> Result<Stmt, Failure> {
+        self.expect(TType::LeftParen)?;
+        self.scopes.push(Vec::new());
+        let init = match self.peek() {
+            TType::SemiColon => {
+                self.advance();
+                None
+            }
+            TType::Var => Some(self.declaration()?),
+            _ => Some(self.statement()?),
+        };
+        let condition = match self.peek() {
+            TType::SemiColon => Expr::Literal(Value::Bool(true)),
+            _ => self.expression()?,
+        };
+        self.expect(TType::SemiColon)?;
+        let increment = match self.peek() {
+            TType::RightParen => None,
+            _ => Some(self.expression()?),
+        };
+        self.expect(TType::RightParen)?;
+        let mut body = vec![self.statement()?];
+        self.scopes.pop();
+        if let Some(increment) = increment {
+            body.push(Stmt::Expression(increment));
+        }
+        let mut outer: Vec<Stmt> = init.into_iter().collect();
+        outer.push(Stmt::While(condition, Box::new(Stmt::Block(body))));
+        Ok(Stmt::Block(outer))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, Failure> {
+        self.scopes.push(Vec::new());
+        let body = self.block_body();
+        self.scopes.pop();
+        body
+    }
+
+    fn block_body(&mut self) -> Result<Vec<Stmt>, Failure> {
+        let mut body = Vec::new();
+        while !matches!(self.peek(), TType::RightBrace | TType::Eof) {
+            body.push(self.declaration()?);
+        }
+        self.expect(TType::RightBrace)?;
+        Ok(body)
+    }
+
+    // like block_body, except an expression statement right before the '}'
+    // may leave out its ';' and becomes the value
+    fn do_body(&mut self) -> Result<Expr, Failure> {
+        let mut body = Vec::new();
+        loop {
+            match self.peek() {
+                TType::RightBrace | TType::Eof => break,
+                TType::Var
+                | TType::Import
+                | TType::Print
+                | TType::Throw
+                | TType::LeftBrace
+                | TType::If
+                | TType::While
+                | TType::For
+                | TType::Try => body.push(self.declaration()?),
+                _ => {
+                    let expr = self.expression()?;
+                    if self.check(TType::RightBrace) {
+                        return Ok(Expr::Do(body, Some(Box::new(expr))));
+                    }
+                    self.expect(TType::SemiColon)?;
+                    body.push(Stmt::Expression(expr));
+                }
+            }
+        }
+        self.expect(TType::RightBrace)?;
+        Ok(Expr::Do(body, None))
+    }
+
+    fn expression(&mut self) -> Result<Expr, Failure> {
+        let target = self.or()?;
+        if self.check(TType::Equal) {
+            let value = self.expression()?;
+            return match target {
+                Expr::Variable(name) => Ok(Expr::Assign(name, Box::new(value))),
+                _ => Err(Failure::Syntax),
+            };
+        }
+        Ok(target)
+    }
+
+    fn or(&mut self) -> Result<Expr, Failure> {
+        let mut left = self.and()?;
+        while self.check(TType::Or) {
+            left = Expr::Logical(Box::new(left), TType::Or, Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, Failure> {
+        let mut left = self.binary(0)?;
+        while self.check(TType::And) {
+            left = Expr::Logical(Box::new(left), TType::And, Box::new(self.binary(0)?));
+        }
+        Ok(left)
+    }
+
+    // equality, comparison, term and factor, loosest first
+    fn binary(&mut self, level: usize) -> Result<Expr, Failure> {
+        const LEVELS: [&[TType]; 4] = [
+            &[TType::EqualEqual, TType::BangEqual],
+            &[
+                TType::Greater,
+                TType::GreaterEqual,
+                TType::Less,
+                TType::LessEqual,
+            ],
+            &[TType::Plus, TType::Minus],
+            &[TType::Star, TType::Slash],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while LEVELS[level].contains(&self.peek()) {
+            let op = self.advance().ttype;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(Box::new(left), op, Box::new(right));
+            // `a < b < c` is refused rather than comparing a bool with c
+            if level == 1 && LEVELS[level].contains(&self.peek()) {
+                return Err(Failure::Syntax);
+            }
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Failure> {
+        if matches!(self.peek(), TType::Bang | TType::Minus) {
+            let op = self.advance().ttype;
+            return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+        }
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, Failure> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.check(TType::LeftParen) {
+                let mut args = Vec::new();
+                if self.peek() != TType::RightParen {
+                    loop {
+                        args.push(self.expression()?);
+                        if !self.check(TType::Comma) {
+                            break;
+                        }
+                    }
+                }
+                self.expect(TType::RightParen)?;
+                expr = Expr::Call(Box::new(expr), args);
+            } else if self.peek() == TType::Dot {
+                return Err(Failure::Unsupported("properties".into()));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, Failure> {
+        let token = self.advance().clone();
+        let lexeme = token.lexeme.unwrap_or_default();
+        Ok(Expr::Literal(match token.ttype {
+            TType::Number => Value::Number(number(lexeme)),
+            TType::String => Value::Str(lexeme[1..lexeme.len() - 1].into()),
+            TType::True => Value::Bool(true),
+            TType::False => Value::Bool(false),
+            TType::Nil => Value::Nil,
+            TType::Identifer => {
+                let local = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.iter().find(|&&(name, _)| name == lexeme));
+                if let Some((_, false)) = local {
+                    return Err(Failure::Syntax);
+                }
+                return Ok(Expr::Variable(lexeme.into()));
+            }
+            TType::LeftParen => {
+                let inner = self.expression()?;
+                self.expect(TType::RightParen)?;
+                return Ok(inner);
+            }
+            TType::Do => {
+                self.expect(TType::LeftBrace)?;
+                self.scopes.push(Vec::new());
+                let body = self.do_body();
+                self.scopes.pop();
+                return body;
+            }
+            _ => return Err(Failure::Syntax),
+        }))
+    }
+}
+
+fn number(lexeme: &str) -> f64 {
+    let digits = lexeme.replace('_', "");
+    let radix = |digits: &str, radix| i64::from_str_radix(digits, radix).unwrap_or_default() as f64;
+    match digits.get(..2) {
+        Some("0x" | "0X") => radix(&digits[2..], 16),
+        Some("0b" | "0B") => radix(&digits[2..], 2),
+        _ => digits.parse().unwrap_or_default(),
+    }
+}
+
+enum Unwind {
+    Error(String),
+    Thrown(Value),
+    Unsupported(String),
+}
+
+struct Interpreter {
+    globals: HashMap<String, Value>,
+    scopes: Vec<HashMap<String, Value>>,
+    output: Vec<String>,
+}
+
+impl Interpreter {
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        match stmt {
+            Stmt::Print(expr) => {
+                let value = self.evaluate(expr)?;
+                self.output.push(value.to_string());
+            }
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Var(name, init) => {
+                let value = match init {
+                    Some(expr) => self.evaluate(expr)?,
+                    None => Value::Nil,
+                };
+                match self.scopes.last_mut() {
+                    Some(scope) => scope.insert(name.clone(), value),
+                    None => self.globals.insert(name.clone(), value),
+                };
+            }
+            Stmt::Block(body) => self.block(body, HashMap::new())?,
+            Stmt::If(condition, then, otherwise) => {
+                if !self.evaluate(condition)?.is_falsy() {
+                    self.execute(then)?;
+                } else if let Some(otherwise) = otherwise {
+                    self.execute(otherwise)?;
+                }
+            }
+            Stmt::While(condition, body) => {
+                while !self.evaluate(condition)?.is_falsy() {
+                    self.execute(body)?;
+                }
+            }
+            Stmt::Try(body, name, handler) => {
+                let depth = self.scopes.len();
+                let exception = match self.block(body, HashMap::new()) {
+                    Ok(()) => return Ok(()),
+                    Err(Unwind::Error(message)) => Value::Str(message),
+                    Err(Unwind::Thrown(value)) => value,
+                    Err(unsupported) => return Err(unsupported),
+                };
+                self.scopes.truncate(depth);
+                self.block(handler, HashMap::from([(name.clone(), exception)]))?;
+            }
+            Stmt::Throw(expr) => return Err(Unwind::Thrown(self.evaluate(expr)?)),
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, body: &[Stmt], scope: HashMap<String, Value>) -> Result<(), Unwind> {
+        self.scopes.push(scope);
+        let result = body.iter().try_for_each(|stmt| self.execute(stmt));
+        self.scopes.pop();
+        result
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<&mut Value, Unwind> {
+        let undefined = || Unwind::Error(format!("Value {}, is not defined.", name));
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(value) = scope.get_mut(name) {
+                return Ok(value);
+            }
+        }
+        self.globals.get_mut(name).ok_or_else(undefined)
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, Unwind> {
+        Ok(match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::Variable(name) => match self.lookup(name) {
+                Ok(value) => value.clone(),
+                Err(_) if NATIVES.contains(&name.as_str()) => Value::Native(native(name)),
+                Err(_) if UNSUPPORTED.contains(&name.as_str()) => {
+                    return Err(Unwind::Unsupported(name.clone()))
+                }
+                Err(e) => return Err(e),
+            },
+            Expr::Assign(name, value) => {
+                let value = self.evaluate(value)?;
+                *self.lookup(name)? = value.clone();
+                value
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.evaluate(operand)?;
+                match op {
+                    TType::Bang => Value::Bool(value.is_falsy()),
+                    _ => Value::Number(-value.number()?),
+                }
+            }
+            Expr::Logical(left, op, right) => {
+                let left = self.evaluate(left)?;
+                match (op, left.is_falsy()) {
+                    (TType::Or, false) | (TType::And, true) => left,
+                    _ => self.evaluate(right)?,
+                }
+            }
+            Expr::Binary(left, op, right) => {
+                let a = self.evaluate(left)?;
+                let b = self.evaluate(right)?;
+                binary(a, *op, b)?
+            }
+            Expr::Call(callee, args) => {
+                let callee = self.evaluate(callee)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                call(callee, &args)?
+            }
+            Expr::Do(body, value) => {
+                self.scopes.push(HashMap::new());
+                let result = body
+                    .iter()
+                    .try_for_each(|stmt| self.execute(stmt))
+                    .and_then(|()| match value {
+                        Some(value) => self.evaluate(value),
+                        None => Ok(Value::Nil),
+                    });
+                self.scopes.pop();
+                result?
+            }
+        })
+    }
+}
+
+const NATIVES: &[&str] = &["type", "assert", "panic"];
+// natives whose behaviour the evaluator does not reproduce
+const UNSUPPORTED: &[&str] = &[
+    "format",
+    "printf",
+    "exit",
+    "clock",
+    "now_ms",
+    "readLine",
+    "memoryUsed",
+    "gcCollect",
+    "objectCount",
+];
+
+fn native(name: &str) -> &'static str {
+    NATIVES.iter().find(|n| **n == name).copied().unwrap_or("?")
+}
+
+fn binary(a: Value, op: TType, b: Value) -> Result<Value, Unwind> {
+    Ok(match op {
+        TType::Plus => match (&a, &b) {
+            (Value::Number(x), Value::Number(y)) => Value::Number(x + y),
+            (Value::Str(_) | Value::Number(_), Value::Str(_))
+            | (Value::Str(_), Value::Number(_)) => Value::Str(format!("{}{}", a, b)),
+            _ => return Err(Unwind::Error(format!("Cannot add {} and {}", a, b))),
+        },
+        TType::EqualEqual => Value::Bool(a == b),
+        TType::BangEqual => Value::Bool(a != b),
+        _ => {
+            // the vm pops the right operand first, so it is checked first
+            let y = b.number()?;
+            let x = a.number()?;
+            match op {
+                TType::Minus => Value::Number(x - y),
+                TType::Star => Value::Number(x * y),
+                TType::Slash => Value::Number(x / y),
+                TType::Greater => Value::Bool(x > y),
+                TType::Less => Value::Bool(x < y),
+                // compiled as the negated opposite, so NaN compares true
+                TType::GreaterEqual => Value::Bool(x.partial_cmp(&y) != Some(Ordering::Less)),
+                _ => Value::Bool(x.partial_cmp(&y) != Some(Ordering::Greater)),
+            }
+        }
+    })
+}
+
+fn call(callee: Value, args: &[Value]) -> Result<Value, Unwind> {
+    let Value::Native(name) = callee else {
+        return Err(Unwind::Error(format!(
+            "Can only call functions, found {}.",
+            callee
+        )));
+    };
+    let arity = if name == "assert" { 2 } else { 1 };
+    if args.len() != arity {
+        return Err(Unwind::Error(format!(
+            "Expected {} arguments but got {}.",
+            arity,
+            args.len()
+        )));
+    }
+    match name {
+        "type" => Ok(Value::Str(
+            match &args[0] {
+                Value::Number(_) => "number",
+                Value::Str(_) => "string",
+                Value::Bool(_) => "bool",
+                Value::Nil => "nil",
+                Value::Native(_) => "function",
+            }
+            .into(),
+        )),
+        "assert" if args[0].is_falsy() => {
+            Err(Unwind::Error(format!("Assertion failed: {}", args[1])))
+        }
+        "assert" => Ok(Value::Nil),
+        _ => Err(Unwind::Error(format!("Panic: {}", args[0]))),
+    }
+}
+
+// runs `source`, returning what it printed and how it stopped
+pub fn run(source: &str) -> (Vec<String>, Ending) {
+    let tokens: Vec<Token> = Scanner::new(source).collect();
+    if tokens.iter().any(|t| t.ttype.error_message().is_some()) {
+        return (Vec::new(), Ending::CompileError);
+    }
+    let mut parser = Parser {
+        tokens,
+        current: 0,
+        scopes: Vec::new(),
+    };
+    parser
+        .tokens
+        .push(Token::new(TType::Eof, None, 0, 0, source.len(), 0));
+    let mut program = Vec::new();
+    while parser.peek() != TType::Eof {
+        match parser.declaration() {
+            Ok(stmt) => program.push(stmt),
+            Err(Failure::Syntax) => return (Vec::new(), Ending::CompileError),
+            Err(Failure::Unsupported(what)) => return (Vec::new(), Ending::Unsupported(what)),
+        }
+    }
+
+    let mut interpreter = Interpreter {
+        globals: HashMap::new(),
+        scopes: Vec::new(),
+        output: Vec::new(),
+    };
+    let ending = match program
+        .iter()
+        .try_for_each(|stmt| interpreter.execute(stmt))
+    {
+        Ok(()) => Ending::Finished,
+        Err(Unwind::Error(message)) => Ending::RuntimeError(message),
+        Err(Unwind::Thrown(value)) => {
+            Ending::RuntimeError(format!("Uncaught exception: {}", value))
+        }
+        Err(Unwind::Unsupported(what)) => Ending::Unsupported(what),
+    };
+    (interpreter.output, ending)
+}
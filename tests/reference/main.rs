@@ -0,0 +1,68 @@
+// differential test: every program under tests/lox also runs through the
+// tree-walking evaluator in eval.rs, and the vm must print the same lines
+// and stop the same way. Programs using what the evaluator does not model
+// (imports, properties, most natives) are skipped.
+//
+//     cargo test --test reference
+#[path = "../common/mod.rs"]
+mod common;
+mod eval;
+
+use std::{env, fs, path::Path};
+
+use eval::Ending;
+use lox_byte::{output::Sink, VmErrors, VM};
+
+fn run_vm(path: &Path, source: &str) -> (Vec<String>, Ending) {
+    let mut vm = VM::new();
+    let (out, output) = Sink::capture();
+    vm.out = out;
+    vm.diagnostics = Sink::capture().0;
+    vm.set_script_path(&path.display().to_string());
+    let ending = match vm.interpret(source) {
+        Ok(()) => Ending::Finished,
+        Err(VmErrors::CompileError(_)) => Ending::CompileError,
+        Err(VmErrors::RuntimeError(error)) => Ending::RuntimeError(error.to_string()),
+    };
+    let lines = output.contents().lines().map(str::to_string).collect();
+    (lines, ending)
+}
+
+#[test]
+fn vm_matches_reference() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let filter = env::var("LOX_TEST").unwrap_or_default();
+    let mut divergences = Vec::new();
+    let mut compared = 0;
+    for path in common::lox_files(&root) {
+        let name = path
+            .strip_prefix(&root)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        if !name.contains(&filter) {
+            continue;
+        }
+        let source = fs::read_to_string(&path).expect("Failed to read test file");
+        let reference = eval::run(&source);
+        if let Ending::Unsupported(_) = reference.1 {
+            continue;
+        }
+        compared += 1;
+        let vm = run_vm(&path, &source);
+        if vm != reference {
+            divergences.push(format!(
+                "{}\n  vm:        {:?}\n  reference: {:?}",
+                name, vm, reference
+            ));
+        }
+    }
+    assert!(compared > 0, "no programs the reference evaluator supports");
+    assert!(
+        divergences.is_empty(),
+        "{} of {} programs diverge:\n{}",
+        divergences.len(),
+        compared,
+        divergences.join("\n")
+    );
+}
@@ -0,0 +1,144 @@
+// running a script an instruction or a slice at a time, and natives that
+// wait on the host
+use lox_byte::{
+    output::{CaptureBuffer, Sink},
+    RuntimeErrors, StepResult, Value, VmErrors, VM,
+};
+
+fn capturing() -> (VM, CaptureBuffer) {
+    let buffer = CaptureBuffer::default();
+    let mut vm = VM::new();
+    vm.out = Sink::new(buffer.clone());
+    (vm, buffer)
+}
+
+#[test]
+fn stepping_runs_one_instruction_at_a_time_until_done() {
+    let (mut vm, buffer) = capturing();
+    vm.compile("var a = 1;\nprint a + 1;").unwrap();
+    let mut steps = 0;
+    let value = loop {
+        match vm.step() {
+            StepResult::Continue => steps += 1,
+            StepResult::Done(value) => break value,
+            other => panic!("{:?}", other),
+        }
+        if steps == 2 {
+            // the global is defined but nothing printed yet
+            assert_eq!(buffer.contents(), "");
+            assert_eq!(vm.frame().unwrap().line, Some(2));
+        }
+    };
+    assert!(matches!(value, Value::Nil));
+    assert_eq!(buffer.contents(), "2\n");
+    assert!(steps > 2);
+    assert!(vm.frame().is_none());
+}
+
+#[test]
+fn stepping_pauses_before_a_breakpoint_and_then_runs_it() {
+    let (mut vm, buffer) = capturing();
+    vm.compile("print 1;\nprint 2;").unwrap();
+    vm.set_breakpoint(2).unwrap();
+    let mut paused = 0;
+    loop {
+        match vm.step() {
+            StepResult::Continue => {}
+            StepResult::Paused => {
+                paused += 1;
+                assert_eq!(buffer.contents(), "1\n");
+            }
+            StepResult::Done(_) => break,
+            other => panic!("{:?}", other),
+        }
+    }
+    assert_eq!(paused, 1);
+    assert_eq!(buffer.contents(), "1\n2\n");
+}
+
+#[test]
+fn stepping_into_an_error_ends_the_run() {
+    let (mut vm, _) = capturing();
+    vm.compile("print 1;\nprint -nil;").unwrap();
+    let error = loop {
+        match vm.step() {
+            StepResult::Continue => {}
+            StepResult::Error(error) => break error,
+            other => panic!("{:?}", other),
+        }
+    };
+    assert!(matches!(
+        error,
+        VmErrors::RuntimeError(RuntimeErrors::TypeError(..))
+    ));
+    assert_eq!(vm.error_line, Some(2));
+    assert!(vm.frame().is_none());
+    assert_eq!(vm.stack().len(), 0);
+}
+
+#[test]
+fn run_for_stops_at_its_budget_and_carries_on_from_there() {
+    let (mut vm, buffer) = capturing();
+    vm.compile("var i = 0;\nwhile (i < 100) i = i + 1;\nprint i;")
+        .unwrap();
+    let mut slices = 0;
+    loop {
+        match vm.run_for(50) {
+            StepResult::Continue => {
+                slices += 1;
+                assert_eq!(buffer.contents(), "");
+                assert!(vm.frame().is_some());
+            }
+            StepResult::Done(_) => break,
+            other => panic!("{:?}", other),
+        }
+    }
+    assert!(slices > 1);
+    assert_eq!(buffer.contents(), "100\n");
+
+    // a slice that finishes early returns what ended it
+    vm.compile("print 1;").unwrap();
+    assert!(matches!(vm.run_for(1_000), StepResult::Done(_)));
+    assert_eq!(buffer.contents(), "100\n1\n");
+}
+
+#[test]
+fn a_pending_native_holds_the_run_until_resumed() {
+    let (mut vm, buffer) = capturing();
+    vm.define_native("wait", 0, |vm, _| Err(vm.pending().1));
+    vm.compile("print wait();").unwrap();
+    let token = loop {
+        match vm.step() {
+            StepResult::Continue => {}
+            StepResult::Pending(token) => break token,
+            other => panic!("{:?}", other),
+        }
+    };
+    assert!(matches!(vm.step(), StepResult::Pending(t) if t == token));
+    assert!(!vm.resume(token + 1, 1.0));
+    assert!(vm.resume(token, "done"));
+    assert!(!vm.resume(token, "again"));
+    while let StepResult::Continue = vm.step() {}
+    assert_eq!(buffer.contents(), "done\n");
+}
+
+#[test]
+fn a_native_waiting_outside_a_stepped_run_is_an_error() {
+    let mut vm = VM::new();
+    vm.define_native("wait", 0, |vm, _| Err(vm.pending().1));
+    let Err(VmErrors::RuntimeError(error)) = vm.interpret("wait();") else {
+        panic!("ran");
+    };
+    assert!(matches!(error, RuntimeErrors::NotResumable(_)));
+    assert_eq!(error.code(), "E0122");
+    assert!(error.hint().is_some());
+    assert!(vm.execution.is_none());
+    let error = vm.call_function("wait", &[]).unwrap_err();
+    assert!(matches!(
+        error,
+        lox_byte::LoxError::Runtime {
+            error: RuntimeErrors::NotResumable(_),
+            ..
+        }
+    ));
+}
@@ -0,0 +1,56 @@
+// the execution trace tools read
+use lox_byte::{
+    json::Json,
+    output::Sink,
+    trace::{TraceFormat, Tracer},
+    VM,
+};
+
+#[test]
+fn json_traces_are_one_object_per_instruction() {
+    let mut vm = VM::new();
+    let (out, _) = Sink::capture();
+    vm.out = out;
+    let (trace, lines) = Sink::capture();
+    vm.tracer = Some(Tracer::new(Box::new(trace), TraceFormat::Json));
+    vm.interpret("var a = \"x\";\nprint a;").unwrap();
+
+    let lines: Vec<Json> = lines
+        .contents()
+        .lines()
+        .map(|line| Json::parse(line).unwrap())
+        .collect();
+    let opcodes: Vec<&str> = lines
+        .iter()
+        .map(|line| line.get("opcode").as_str().unwrap())
+        .collect();
+    assert_eq!(
+        opcodes,
+        [
+            "Op_Constant",
+            "Op_DefineGlobal",
+            "Op_GetGlobal",
+            "Op_Print",
+            "Op_Return"
+        ]
+    );
+    let first = &lines[0];
+    let keys: Vec<&str> = match first {
+        Json::Object(fields) => fields.iter().map(|(key, _)| key.as_str()).collect(),
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(
+        keys,
+        ["offset", "line", "opcode", "operands", "constant", "stack"]
+    );
+    assert_eq!(first.get("offset").as_usize(), Some(0));
+    assert_eq!(first.get("line").as_usize(), Some(1));
+    assert_eq!(first.get("operands").as_array(), [Json::Number(1.0)]);
+    assert_eq!(first.get("constant").as_str(), Some("x"));
+    assert!(first.get("stack").as_array().is_empty());
+    // the stack as it is before each instruction runs
+    let print = &lines[3];
+    assert_eq!(print.get("line").as_usize(), Some(2));
+    assert_eq!(print.get("stack").as_array(), [Json::from("x")]);
+    assert_eq!(lines[1].get("constant").as_str(), Some("a"));
+}
@@ -0,0 +1,27 @@
+use lox_byte::{watch::Watched, VmConfig, VM};
+
+#[test]
+fn watching_sees_a_script_and_its_imports_change() {
+    let dir = std::env::temp_dir().join(format!("lox_byte_watch_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("main.lox");
+    let module = dir.join("lib.lox");
+    std::fs::write(&script, "import \"lib.lox\"; print lib.x;").unwrap();
+    std::fs::write(&module, "var x = ;").unwrap();
+    let script = script.display().to_string();
+
+    let mut vm = VM::with_config(VmConfig {
+        dump_failed_chunks: false,
+        ..VmConfig::default()
+    });
+    vm.set_script_path(&script);
+    let source = std::fs::read_to_string(&script).unwrap();
+    // an import that failed to compile is watched too, to see it fixed
+    assert!(vm.interpret(&source).is_err());
+    let watched = Watched::script(&script, &vm);
+    assert_eq!(watched.len(), 2);
+    assert!(!watched.changed());
+    std::fs::write(&module, "var x = 1;").unwrap();
+    assert!(watched.changed());
+    std::fs::remove_dir_all(&dir).unwrap();
+}